@@ -1,11 +1,17 @@
 //! Example minimal cluster contract.
 //!
 //! This contract is nothing more than a comms layer for nodes and a store for the shared public key. More enshrined
-//! implementations may want to add additional parameters to the store as well as contact logic to verify signatures, etc.
+//! implementations may want to add additional parameters to the store as well as contact logic to verify quotes, etc.
+//!
+//! Onboard messages are signed with an ed25519 key derived from the shared secret, whose pubkey is set at bootstrap.
+//! Only nodes that got the secret from the cluster can onboard others, so newcomers don't have to go through (and
+//! can't be stalled by) garbage messages posted by anyone.
 //!
 
 #![no_std]
-use soroban_sdk::{contract, contractimpl, contracttype, symbol_short, Env, String};
+use soroban_sdk::{
+    contract, contractimpl, contracttype, symbol_short, xdr::ToXdr, Address, BytesN, Env, String,
+};
 
 #[contract]
 pub struct ClusterContract;
@@ -13,38 +19,70 @@ pub struct ClusterContract;
 #[contracttype]
 pub enum DataKey {
     SharedPub,
+    SigningPub,
+    BootQuote,
 }
 
 #[contractimpl]
 impl ClusterContract {
-    pub fn bootstrap(env: Env, shared_public: String, quote: String) {
+    /// Note: [`quote`] binds [`signing_public`] along with [`shared_public`]. The contract doesn't check it, joining
+    /// nodes verify it against the stored pubkeys before registering, which is why it's kept in storage rather than
+    /// only published (RPC nodes don't retain events for long).
+    pub fn bootstrap(env: Env, shared_public: String, quote: String, signing_public: BytesN<32>) {
         if env.storage().instance().has(&DataKey::SharedPub) {
             panic!() // already bootstrapped
         }
         env.storage()
             .instance()
             .set(&DataKey::SharedPub, &shared_public);
-        env.events()
-            .publish((symbol_short!("boot"), shared_public), quote);
+        env.storage()
+            .instance()
+            .set(&DataKey::SigningPub, &signing_public);
+        env.storage().instance().set(&DataKey::BootQuote, &quote);
+        env.events().publish(
+            (symbol_short!("boot"), shared_public),
+            (quote, signing_public),
+        );
     }
 
-    pub fn register(env: Env, node_pubkey: String, quote: String) {
+    /// Note: event logs are too big for events and transactions, only their sha384 goes on-chain. The log itself
+    /// is served at [`event_log_endpoint`] (e.g the registering host), onboarding nodes check it against
+    /// [`event_log_hash`] before replaying it against the quote.
+    ///
+    /// [`host`] is the account of the host registering its node and must authorize the call, so that members can
+    /// tell which registrations come from the same host without trusting anything self-reported.
+    pub fn register(
+        env: Env,
+        host: Address,
+        node_pubkey: String,
+        quote: String,
+        event_log_hash: BytesN<48>,
+        event_log_endpoint: String,
+    ) {
         if !env.storage().instance().has(&DataKey::SharedPub) {
             panic!() // not bootstrapped
         }
+        host.require_auth();
 
-        env.events()
-            .publish((symbol_short!("register"), node_pubkey), quote);
+        env.events().publish(
+            (symbol_short!("register"), node_pubkey),
+            (quote, event_log_hash, event_log_endpoint, host),
+        );
     }
 
-    // Note: anyone can call this!
-    pub fn onboard(env: Env, node_pubkey: String, encrypted: String) {
-        if !env.storage().instance().has(&DataKey::SharedPub) {
-            panic!() // not bootstrapped
-        }
+    /// [`signature`] is over the XDR of the `(node_pubkey, encrypted)` tuple, anything else panics.
+    pub fn onboard(env: Env, node_pubkey: String, encrypted: String, signature: BytesN<64>) {
+        let signing_public: BytesN<32> =
+            env.storage().instance().get(&DataKey::SigningPub).unwrap(); // not bootstrapped
+
+        let message = (node_pubkey.clone(), encrypted.clone()).to_xdr(&env);
+        env.crypto()
+            .ed25519_verify(&signing_public, &message, &signature);
 
-        env.events()
-            .publish((symbol_short!("onboard"), node_pubkey), encrypted);
+        env.events().publish(
+            (symbol_short!("onboard"), node_pubkey),
+            (encrypted, signature),
+        );
     }
 }
 
@@ -1,24 +1,94 @@
 #![cfg(test)]
 
 use super::*;
-use soroban_sdk::{Env, String};
+use ed25519_dalek::{Signer, SigningKey};
+use soroban_sdk::{testutils::Address as _, Address, Bytes, Env, String};
 
-#[test]
-fn test() {
-    let env = Env::default();
+fn sign(env: &Env, key: &SigningKey, node_pubkey: &String, encrypted: &String) -> BytesN<64> {
+    let message: Bytes = (node_pubkey.clone(), encrypted.clone()).to_xdr(env);
+    let mut buf = [0; 256];
+    let buf = &mut buf[..message.len() as usize];
+    message.copy_into_slice(buf);
+    BytesN::from_array(env, &key.sign(buf).to_bytes())
+}
+
+fn setup(env: &Env, key: &SigningKey) -> ClusterContractClient<'static> {
     let contract_id = env.register_contract(None, ClusterContract);
-    let client = ClusterContractClient::new(&env, &contract_id);
+    let client = ClusterContractClient::new(env, &contract_id);
 
     client.bootstrap(
-        &String::from_str(&env, "bootstrap"),
-        &String::from_str(&env, "quote"),
+        &String::from_str(env, "bootstrap"),
+        &String::from_str(env, "quote"),
+        &BytesN::from_array(env, key.verifying_key().as_bytes()),
     );
+    client
+}
+
+#[test]
+fn test() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let client = setup(&env, &key);
+
+    let host = Address::generate(&env);
     client.register(
+        &host,
         &String::from_str(&env, "register"),
         &String::from_str(&env, "quote"),
+        &BytesN::from_array(&env, &[1; 48]),
+        &String::from_str(&env, "localhost:8000"),
     );
+    // Registrations are attributed to the host that authorized them.
+    assert_eq!(env.auths()[0].0, host);
+
+    let node_pubkey = String::from_str(&env, "onboard");
+    let encrypted = String::from_str(&env, "encrypted_shared_secret");
     client.onboard(
-        &String::from_str(&env, "onboard"),
-        &String::from_str(&env, "encrypted_shared_secret"),
+        &node_pubkey,
+        &encrypted,
+        &sign(&env, &key, &node_pubkey, &encrypted),
     );
 }
+
+#[test]
+fn rejects_unsigned_onboards() {
+    let env = Env::default();
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let client = setup(&env, &key);
+
+    let node_pubkey = String::from_str(&env, "onboard");
+    let encrypted = String::from_str(&env, "encrypted_shared_secret");
+    let spam = String::from_str(&env, "garbage");
+
+    // Signed by someone else.
+    let other = SigningKey::from_bytes(&[8; 32]);
+    assert!(client
+        .try_onboard(
+            &node_pubkey,
+            &encrypted,
+            &sign(&env, &other, &node_pubkey, &encrypted)
+        )
+        .is_err());
+    // A member's signature doesn't carry over to another message.
+    assert!(client
+        .try_onboard(
+            &node_pubkey,
+            &spam,
+            &sign(&env, &key, &node_pubkey, &encrypted)
+        )
+        .is_err());
+}
+
+#[test]
+fn keeps_bootstrap_quote() {
+    let env = Env::default();
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let client = setup(&env, &key);
+
+    // Joining nodes read it from storage to check what it binds.
+    let quote: String = env.as_contract(&client.address, || {
+        env.storage().instance().get(&DataKey::BootQuote).unwrap()
+    });
+    assert_eq!(quote, String::from_str(&env, "quote"));
+}
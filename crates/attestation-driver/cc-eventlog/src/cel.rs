@@ -0,0 +1,438 @@
+//! TCG Canonical Event Log (CEL) encodings.
+//!
+//! Allows exporting event logs in the CEL-JSON and CEL-CBOR formats consumed by standard remote-attestation
+//! tooling, and parsing them back. Boot time events use the `pcclient_std` content type while runtime events
+//! (the ones with a name, see [`TdxEventLog`]) use a `dstack_runtime` content type carrying the event name and
+//! payload, since their digest can't be recomputed from the payload alone.
+//!
+//! Note: the `pcr` field holds the TCG MR index like the CCEL does, i.e MRTD is 0 and RTMR n is n + 1 (see
+//! [`TdxEventLog::imr`] which is the zero-based RTMR index).
+//!
+//! NB: `dstack_runtime` isn't a TCG registered content type. None of the registered ones (`pcclient_std`, `ima_*`,
+//! `cel`) can carry an event name that's hashed along with the payload, so runtime events use the private type
+//! 0x80, above the registered ones. Standard tooling can still replay them from their digests but doesn't know how
+//! to check their payload, use [`TdxEventLog::validate`] after [`from_cel`] for that.
+//!
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use ciborium::Value;
+use serde::{Deserialize, Serialize};
+
+use crate::{tcg, EventDigest, EventLogs, TdxEventLog};
+
+// CEL-TLV types, also used as CEL-CBOR map keys.
+const CEL_RECNUM: u8 = 0;
+const CEL_PCR: u8 = 1;
+const CEL_DIGESTS: u8 = 3;
+const CEL_PCCLIENT_STD: u8 = 5;
+// Private type for runtime events, not registered with the TCG (see the module docs).
+const CEL_DSTACK_RUNTIME: u8 = 0x80;
+
+// Content fields.
+const CEL_EVENT_TYPE: u8 = 0;
+const CEL_EVENT_DATA: u8 = 1;
+const CEL_EVENT_NAME: u8 = 2;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CelRecord {
+    pub recnum: u64,
+    pub pcr: u32,
+    pub digests: Vec<CelDigest>,
+    #[serde(flatten)]
+    pub content: CelContent,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CelDigest {
+    #[serde(rename = "hashAlg", with = "hash_alg")]
+    pub hash_alg: u16,
+    #[serde(with = "hex_bytes")]
+    pub digest: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "content_type", content = "content", rename_all = "snake_case")]
+pub enum CelContent {
+    PcclientStd {
+        event_type: u32,
+        #[serde(with = "base64_bytes")]
+        event_data: Vec<u8>,
+    },
+    DstackRuntime {
+        event_type: u32,
+        event: String,
+        #[serde(with = "base64_bytes")]
+        event_data: Vec<u8>,
+    },
+}
+
+impl CelRecord {
+    fn to_cbor_value(&self) -> Value {
+        let digests = self
+            .digests
+            .iter()
+            .map(|d| (Value::from(d.hash_alg), Value::Bytes(d.digest.clone())))
+            .collect();
+        let (content_type, content) = match &self.content {
+            CelContent::PcclientStd {
+                event_type,
+                event_data,
+            } => (
+                CEL_PCCLIENT_STD,
+                vec![
+                    (Value::from(CEL_EVENT_TYPE), Value::from(*event_type)),
+                    (
+                        Value::from(CEL_EVENT_DATA),
+                        Value::Bytes(event_data.clone()),
+                    ),
+                ],
+            ),
+            CelContent::DstackRuntime {
+                event_type,
+                event,
+                event_data,
+            } => (
+                CEL_DSTACK_RUNTIME,
+                vec![
+                    (Value::from(CEL_EVENT_TYPE), Value::from(*event_type)),
+                    (
+                        Value::from(CEL_EVENT_DATA),
+                        Value::Bytes(event_data.clone()),
+                    ),
+                    (Value::from(CEL_EVENT_NAME), Value::Text(event.clone())),
+                ],
+            ),
+        };
+
+        Value::Map(vec![
+            (Value::from(CEL_RECNUM), Value::from(self.recnum)),
+            (Value::from(CEL_PCR), Value::from(self.pcr)),
+            (Value::from(CEL_DIGESTS), Value::Map(digests)),
+            (Value::from(content_type), Value::Map(content)),
+        ])
+    }
+
+    fn from_cbor_value(value: Value) -> Result<Self> {
+        let mut recnum = None;
+        let mut pcr = None;
+        let mut digests = None;
+        let mut content = None;
+
+        for (key, value) in into_map(value)? {
+            match cbor_key(&key)? {
+                CEL_RECNUM => recnum = Some(cbor_int(&value)?),
+                CEL_PCR => pcr = Some(cbor_int(&value)?),
+                CEL_DIGESTS => {
+                    digests = Some(
+                        into_map(value)?
+                            .into_iter()
+                            .map(|(alg, digest)| {
+                                Ok(CelDigest {
+                                    hash_alg: cbor_int(&alg)?,
+                                    digest: into_bytes(digest)?,
+                                })
+                            })
+                            .collect::<Result<Vec<_>>>()?,
+                    )
+                }
+                content_type @ (CEL_PCCLIENT_STD | CEL_DSTACK_RUNTIME) => {
+                    let mut event_type = None;
+                    let mut event_data = None;
+                    let mut event = None;
+                    for (key, value) in into_map(value)? {
+                        match cbor_key(&key)? {
+                            CEL_EVENT_TYPE => event_type = Some(cbor_int(&value)?),
+                            CEL_EVENT_DATA => event_data = Some(into_bytes(value)?),
+                            CEL_EVENT_NAME => {
+                                event = Some(
+                                    value
+                                        .into_text()
+                                        .map_err(|_| anyhow!("event name is not text"))?,
+                                )
+                            }
+                            other => bail!("unknown content field {}", other),
+                        }
+                    }
+                    let event_type = event_type.context("missing event type")?;
+                    let event_data = event_data.context("missing event data")?;
+                    content = Some(if content_type == CEL_PCCLIENT_STD {
+                        CelContent::PcclientStd {
+                            event_type,
+                            event_data,
+                        }
+                    } else {
+                        CelContent::DstackRuntime {
+                            event_type,
+                            event: event.context("missing event name")?,
+                            event_data,
+                        }
+                    });
+                }
+                other => bail!("unsupported cel record field {}", other),
+            }
+        }
+
+        Ok(Self {
+            recnum: recnum.context("missing recnum")?,
+            pcr: pcr.context("missing pcr")?,
+            digests: digests.context("missing digests")?,
+            content: content.context("missing content")?,
+        })
+    }
+}
+
+fn into_map(value: Value) -> Result<Vec<(Value, Value)>> {
+    value.into_map().map_err(|_| anyhow!("expected a cbor map"))
+}
+
+fn into_bytes(value: Value) -> Result<Vec<u8>> {
+    value
+        .into_bytes()
+        .map_err(|_| anyhow!("expected cbor bytes"))
+}
+
+fn cbor_int<T: TryFrom<ciborium::value::Integer>>(value: &Value) -> Result<T> {
+    value
+        .as_integer()
+        .and_then(|int| T::try_from(int).ok())
+        .ok_or(anyhow!("expected a cbor integer in range"))
+}
+
+fn cbor_key(value: &Value) -> Result<u8> {
+    cbor_int(value)
+}
+
+impl TdxEventLog {
+    pub fn to_cel(&self, recnum: u64) -> CelRecord {
+        let content = if self.event.is_empty() {
+            CelContent::PcclientStd {
+                event_type: self.event_type,
+                event_data: self.event_payload.clone(),
+            }
+        } else {
+            CelContent::DstackRuntime {
+                event_type: self.event_type,
+                event: self.event.clone(),
+                event_data: self.event_payload.clone(),
+            }
+        };
+
+        CelRecord {
+            recnum,
+            pcr: self.imr + 1,
+            digests: std::iter::once(CelDigest {
+                hash_alg: tcg::TPM_ALG_SHA384,
+                digest: self.digest.to_vec(),
+            })
+            .chain(self.extra_digests.iter().map(|d| CelDigest {
+                hash_alg: d.algo_id,
+                digest: d.digest.clone(),
+            }))
+            .collect(),
+            content,
+        }
+    }
+
+    pub fn from_cel(record: &CelRecord) -> Result<Self> {
+        let digest = record
+            .digests
+            .iter()
+            .find(|d| d.hash_alg == tcg::TPM_ALG_SHA384)
+            .context("cel record has no sha384 digest")?
+            .digest
+            .as_slice()
+            .try_into()
+            .context("invalid sha384 digest size")?;
+        let (event_type, event, event_payload) = match &record.content {
+            CelContent::PcclientStd {
+                event_type,
+                event_data,
+            } => (*event_type, String::new(), event_data.clone()),
+            CelContent::DstackRuntime {
+                event_type,
+                event,
+                event_data,
+            } => (*event_type, event.clone(), event_data.clone()),
+        };
+
+        let extra_digests = record
+            .digests
+            .iter()
+            .filter(|d| d.hash_alg != tcg::TPM_ALG_SHA384)
+            .map(|d| EventDigest {
+                algo_id: d.hash_alg,
+                digest: d.digest.clone(),
+            })
+            .collect();
+
+        Ok(Self {
+            imr: record
+                .pcr
+                .checked_sub(1)
+                .context("cel record is for mrtd, which has no event log")?,
+            event_type,
+            digest,
+            event,
+            event_payload,
+            extra_digests,
+        })
+    }
+}
+
+impl EventLogs {
+    /// Boot time events as CEL records. The spec id header isn't measured and is not part of the export.
+    pub fn to_cel(&self) -> Result<Vec<CelRecord>> {
+        Ok(to_cel(&self.to_tdx_event_logs()?))
+    }
+}
+
+/// Numbers [`event_logs`] in order.
+pub fn to_cel(event_logs: &[TdxEventLog]) -> Vec<CelRecord> {
+    event_logs
+        .iter()
+        .enumerate()
+        .map(|(recnum, event_log)| event_log.to_cel(recnum as u64))
+        .collect()
+}
+
+pub fn from_cel(records: &[CelRecord]) -> Result<Vec<TdxEventLog>> {
+    records.iter().map(TdxEventLog::from_cel).collect()
+}
+
+pub fn to_json(records: &[CelRecord]) -> Result<String> {
+    Ok(serde_json::to_string(records)?)
+}
+
+pub fn from_json(json: &str) -> Result<Vec<CelRecord>> {
+    Ok(serde_json::from_str(json)?)
+}
+
+/// CEL-CBOR is a sequence of records, we encode it as a cbor array.
+pub fn to_cbor(records: &[CelRecord]) -> Result<Vec<u8>> {
+    let value = Value::Array(records.iter().map(CelRecord::to_cbor_value).collect());
+    let mut encoded = vec![];
+    ciborium::into_writer(&value, &mut encoded)?;
+    Ok(encoded)
+}
+
+pub fn from_cbor(cbor: &[u8]) -> Result<Vec<CelRecord>> {
+    let value: Value = ciborium::from_reader(cbor)?;
+    value
+        .into_array()
+        .map_err(|_| anyhow!("expected a cbor array of records"))?
+        .into_iter()
+        .map(CelRecord::from_cbor_value)
+        .collect()
+}
+
+mod hash_alg {
+    use crate::tcg::*;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    const NAMES: [(u16, &str); 4] = [
+        (TPM_ALG_SHA1, "sha1"),
+        (TPM_ALG_SHA256, "sha256"),
+        (TPM_ALG_SHA384, "sha384"),
+        (TPM_ALG_SHA512, "sha512"),
+    ];
+
+    pub fn serialize<S: Serializer>(alg: &u16, serializer: S) -> Result<S::Ok, S::Error> {
+        match NAMES.iter().find(|(id, _)| id == alg) {
+            Some((_, name)) => serializer.serialize_str(name),
+            None => Err(serde::ser::Error::custom(format!(
+                "unknown hash alg {}",
+                alg
+            ))),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u16, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        NAMES
+            .iter()
+            .find(|(_, n)| *n == name)
+            .map(|(id, _)| *id)
+            .ok_or(D::Error::custom(format!("unknown hash alg {}", name)))
+    }
+}
+
+mod hex_bytes {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        hex::decode(String::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
+
+mod base64_bytes {
+    use super::*;
+    use serde::{de::Error, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&BASE64_STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        BASE64_STANDARD
+            .decode(String::deserialize(deserializer)?)
+            .map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_records() -> Vec<CelRecord> {
+        let boot_time_data = include_bytes!("../samples/ccel.bin");
+        let mut event_logs = EventLogs::decode(&mut boot_time_data.as_slice())
+            .unwrap()
+            .into_tdx_event_logs()
+            .unwrap();
+        event_logs.push(TdxEventLog::new_str(3, 0x08000001, "app-id", "new-york"));
+        to_cel(&event_logs)
+    }
+
+    #[test]
+    fn json_roundtrip() {
+        let records = sample_records();
+        let json = to_json(&records).unwrap();
+        assert_eq!(from_json(&json).unwrap(), records);
+
+        let runtime = serde_json::to_string_pretty(records.last().unwrap()).unwrap();
+        insta::assert_snapshot!(runtime);
+    }
+
+    #[test]
+    fn cbor_roundtrip() {
+        let records = sample_records();
+        let cbor = to_cbor(&records).unwrap();
+        let decoded = from_cbor(&cbor).unwrap();
+        assert_eq!(decoded, records);
+
+        // Back to event logs that still replay the same way.
+        let event_logs = from_cel(&decoded).unwrap();
+        assert_eq!(
+            crate::replay_rtmrs(&event_logs).unwrap(),
+            crate::replay_rtmrs(&from_cel(&records).unwrap()).unwrap()
+        );
+        event_logs.last().unwrap().validate().unwrap();
+    }
+
+    #[test]
+    fn uses_mr_indexes() {
+        let records = sample_records();
+        // RTMR3 is MR 4.
+        assert_eq!(records.last().unwrap().pcr, 4);
+        assert_eq!(from_cel(&records).unwrap().last().unwrap().imr, 3);
+
+        let mut mrtd = records.last().unwrap().clone();
+        mrtd.pcr = 0;
+        assert!(TdxEventLog::from_cel(&mrtd).is_err());
+    }
+}
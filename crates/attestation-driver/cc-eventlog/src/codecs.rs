@@ -1,6 +1,6 @@
 use std::ops::Deref;
 
-use scale::{Decode, Input};
+use scale::{Decode, Encode, Input, Output};
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct VecOf<I, T> {
@@ -32,6 +32,15 @@ impl<I: Decode + Into<u32> + Copy, T: Decode> Decode for VecOf<I, T> {
     }
 }
 
+impl<I: Encode, T: Encode> Encode for VecOf<I, T> {
+    fn encode_to<O: Output + ?Sized>(&self, dest: &mut O) {
+        self.len.encode_to(dest);
+        for item in &self.inner {
+            item.encode_to(dest);
+        }
+    }
+}
+
 impl<I, T> VecOf<I, T> {
     pub fn into_inner(self) -> Vec<T> {
         self.inner
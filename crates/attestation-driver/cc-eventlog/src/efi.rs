@@ -0,0 +1,410 @@
+//! Typed decoding of EFI event payloads.
+//!
+//! Boot time events only carry raw bytes, which is fine for replaying but not for writing policies over what was
+//! actually booted. This decodes the payloads of the common event types as defined by the TCG PC Client Platform
+//! Firmware Profile so that verifiers can e.g check the kernel command line (see [`kernel_cmdline`]).
+//!
+//! Note: payloads of event types we don't know about are returned as [`EfiEvent::Raw`], while malformed payloads
+//! of known types are an error.
+//!
+
+use anyhow::{bail, Context, Result};
+use scale::{Decode, Input};
+use serde::Serialize;
+
+use crate::{tcg::*, TdxEventLog};
+
+/// Prefix grub uses when measuring the kernel command line as `EV_IPL`.
+const GRUB_KERNEL_CMDLINE_PREFIX: &str = "kernel_cmdline: ";
+/// Prefixes of grub's `EV_IPL` events, grub only measures the string after them.
+const GRUB_PREFIXES: [&str; 3] = [GRUB_KERNEL_CMDLINE_PREFIX, "grub_cmd: ", "module_cmdline: "];
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EfiEvent {
+    /// `EV_EFI_VARIABLE_DRIVER_CONFIG`, `EV_EFI_VARIABLE_BOOT(2)` and `EV_EFI_VARIABLE_AUTHORITY`.
+    Variable(UefiVariable),
+    /// `EV_EFI_BOOT_SERVICES_APPLICATION`, `EV_EFI_BOOT_SERVICES_DRIVER` and `EV_EFI_RUNTIME_SERVICES_DRIVER`.
+    ImageLoad(UefiImageLoad),
+    /// `EV_IPL`, e.g kernel command line and bootloader commands.
+    Ipl { text: String },
+    /// `EV_EFI_PLATFORM_FIRMWARE_BLOB`.
+    FirmwareBlob { base: u64, length: u64 },
+    /// `EV_EFI_PLATFORM_FIRMWARE_BLOB2`.
+    FirmwareBlob2 {
+        description: String,
+        base: u64,
+        length: u64,
+    },
+    /// `EV_SEPARATOR`, the value is 0 unless the firmware hit an error.
+    Separator { value: u32 },
+    /// `EV_ACTION` and `EV_EFI_ACTION`.
+    Action { text: String },
+    Raw {
+        #[serde(with = "serde_human_bytes")]
+        data: Vec<u8>,
+    },
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct UefiVariable {
+    pub guid: String,
+    pub name: String,
+    #[serde(with = "serde_human_bytes")]
+    pub data: Vec<u8>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct UefiImageLoad {
+    pub location: u64,
+    pub length: u64,
+    pub link_time_address: u64,
+    pub device_path: Vec<DevicePathNode>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DevicePathNode {
+    Vendor {
+        guid: String,
+    },
+    FirmwareFile {
+        guid: String,
+    },
+    FirmwareVolume {
+        guid: String,
+    },
+    FilePath {
+        path: String,
+    },
+    Other {
+        node_type: u8,
+        sub_type: u8,
+        #[serde(with = "serde_human_bytes")]
+        data: Vec<u8>,
+    },
+}
+
+/// Decodes the payload of an event of type [`event_type`].
+pub fn decode_event(event_type: u32, data: &[u8]) -> Result<EfiEvent> {
+    let input = &mut &data[..];
+    let event = match event_type {
+        EV_EFI_VARIABLE_DRIVER_CONFIG
+        | EV_EFI_VARIABLE_BOOT
+        | EV_EFI_VARIABLE_BOOT2
+        | EV_EFI_VARIABLE_AUTHORITY => EfiEvent::Variable(decode_variable(input)?),
+        EV_EFI_BOOT_SERVICES_APPLICATION
+        | EV_EFI_BOOT_SERVICES_DRIVER
+        | EV_EFI_RUNTIME_SERVICES_DRIVER => EfiEvent::ImageLoad(decode_image_load(input)?),
+        EV_IPL => EfiEvent::Ipl {
+            text: decode_text(data),
+        },
+        EV_EFI_PLATFORM_FIRMWARE_BLOB => EfiEvent::FirmwareBlob {
+            base: u64::decode(input)?,
+            length: u64::decode(input)?,
+        },
+        EV_EFI_PLATFORM_FIRMWARE_BLOB2 => {
+            let description_size = u8::decode(input)?;
+            let description = read_bytes(input, description_size as usize)?;
+            EfiEvent::FirmwareBlob2 {
+                description: decode_text(&description),
+                base: u64::decode(input)?,
+                length: u64::decode(input)?,
+            }
+        }
+        EV_SEPARATOR => EfiEvent::Separator {
+            value: u32::decode(input)?,
+        },
+        EV_ACTION | EV_EFI_ACTION => EfiEvent::Action {
+            text: decode_text(data),
+        },
+        _ => EfiEvent::Raw {
+            data: data.to_vec(),
+        },
+    };
+
+    Ok(event)
+}
+
+impl TdxEventLog {
+    pub fn decode_efi(&self) -> Result<EfiEvent> {
+        decode_event(self.event_type, &self.event_payload)
+    }
+}
+
+/// Returns what the firmware may have hashed into the digest of a boot time event of type [`event_type`], derived
+/// from its [`payload`]. `None` for event types whose digest covers data outside of the log, e.g loaded images,
+/// firmware volumes or ACPI tables, their payload only describes what was measured.
+///
+/// Note: `EV_EFI_VARIABLE_BOOT` only hashes the variable's data and grub doesn't hash its `EV_IPL` prefixes nor the
+/// trailing NUL, so there may be more than one candidate.
+pub fn measured_data(event_type: u32, payload: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let mut candidates = vec![payload.to_vec()];
+    match event_type {
+        EV_SEPARATOR
+        | EV_ACTION
+        | EV_EFI_ACTION
+        | EV_EFI_VARIABLE_DRIVER_CONFIG
+        | EV_EFI_VARIABLE_BOOT2
+        | EV_EFI_VARIABLE_AUTHORITY
+        | EV_EFI_GPT_EVENT => {}
+        EV_EFI_VARIABLE_BOOT => {
+            if let Ok(variable) = decode_variable(&mut &payload[..]) {
+                candidates.push(variable.data);
+            }
+        }
+        EV_IPL => {
+            let trimmed = payload.strip_suffix(&[0]).unwrap_or(payload);
+            candidates.push(trimmed.to_vec());
+            if let Some(stripped) = GRUB_PREFIXES
+                .iter()
+                .find_map(|prefix| trimmed.strip_prefix(prefix.as_bytes()))
+            {
+                candidates.push(stripped.to_vec());
+            }
+        }
+        _ => return None,
+    }
+
+    Some(candidates)
+}
+
+/// Returns the kernel command line measured by the bootloader as `EV_IPL`, if any. Grub's `kernel_cmdline: `
+/// prefix is stripped.
+///
+/// Replaying the log doesn't cover payloads, so every command line must match its digest (grub only measures
+/// what follows the prefix) and grub must have measured the same one every time, anything else is an error.
+pub fn kernel_cmdline(event_logs: &[TdxEventLog]) -> Result<Option<String>> {
+    use sha2::Digest;
+
+    let mut cmdline: Option<String> = None;
+    for event_log in event_logs.iter().filter(|e| e.event_type == EV_IPL) {
+        let EfiEvent::Ipl { text } = event_log.decode_efi()? else {
+            continue;
+        };
+        let Some(stripped) = text.strip_prefix(GRUB_KERNEL_CMDLINE_PREFIX) else {
+            continue;
+        };
+        if sha2::Sha384::digest(stripped.as_bytes())[..] != event_log.digest[..] {
+            bail!("kernel cmdline {} doesn't match its digest", stripped);
+        }
+        match &cmdline {
+            Some(previous) if previous != stripped => {
+                bail!(
+                    "kernel cmdline {} was measured after {}",
+                    stripped,
+                    previous
+                )
+            }
+            _ => cmdline = Some(stripped.to_string()),
+        }
+    }
+
+    Ok(cmdline)
+}
+
+fn read_bytes(input: &mut &[u8], len: usize) -> Result<Vec<u8>> {
+    if input.len() < len {
+        bail!("event payload is truncated");
+    }
+    let mut bytes = vec![0; len];
+    input.read(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn decode_variable(input: &mut &[u8]) -> Result<UefiVariable> {
+    let guid = read_bytes(input, 16)?;
+    let name_length = u64::decode(input)? as usize;
+    let data_length = u64::decode(input)? as usize;
+    let name = read_bytes(
+        input,
+        name_length.checked_mul(2).context("invalid name length")?,
+    )?;
+    let data = read_bytes(input, data_length)?;
+
+    Ok(UefiVariable {
+        guid: format_guid(&guid),
+        name: decode_utf16(&name),
+        data,
+    })
+}
+
+fn decode_image_load(input: &mut &[u8]) -> Result<UefiImageLoad> {
+    let location = u64::decode(input)?;
+    let length = u64::decode(input)?;
+    let link_time_address = u64::decode(input)?;
+    let device_path_length = u64::decode(input)? as usize;
+    let device_path = read_bytes(input, device_path_length)?;
+
+    Ok(UefiImageLoad {
+        location,
+        length,
+        link_time_address,
+        device_path: decode_device_path(&device_path)?,
+    })
+}
+
+fn decode_device_path(mut data: &[u8]) -> Result<Vec<DevicePathNode>> {
+    let input = &mut data;
+    let mut nodes = vec![];
+    while !input.is_empty() {
+        let node_type = u8::decode(input)?;
+        let sub_type = u8::decode(input)?;
+        let length = u16::decode(input)? as usize;
+        let data = read_bytes(
+            input,
+            length.checked_sub(4).context("invalid device path node")?,
+        )?;
+
+        let node = match (node_type, sub_type) {
+            // End of device path.
+            (0x7f, 0xff) => break,
+            (0x7f, _) => continue,
+            (0x01 | 0x04, 0x03) if data.len() >= 16 => DevicePathNode::Vendor {
+                guid: format_guid(&data[..16]),
+            },
+            (0x04, 0x04) => DevicePathNode::FilePath {
+                path: decode_utf16(&data),
+            },
+            (0x04, 0x06) if data.len() == 16 => DevicePathNode::FirmwareFile {
+                guid: format_guid(&data),
+            },
+            (0x04, 0x07) if data.len() == 16 => DevicePathNode::FirmwareVolume {
+                guid: format_guid(&data),
+            },
+            _ => DevicePathNode::Other {
+                node_type,
+                sub_type,
+                data,
+            },
+        };
+        nodes.push(node);
+    }
+
+    Ok(nodes)
+}
+
+/// EFI GUIDs are mixed-endian: the first three fields are little endian.
+fn format_guid(guid: &[u8]) -> String {
+    format!(
+        "{:08x}-{:04x}-{:04x}-{}-{}",
+        u32::from_le_bytes(guid[0..4].try_into().unwrap()),
+        u16::from_le_bytes(guid[4..6].try_into().unwrap()),
+        u16::from_le_bytes(guid[6..8].try_into().unwrap()),
+        hex::encode(&guid[8..10]),
+        hex::encode(&guid[10..16])
+    )
+}
+
+fn decode_utf16(data: &[u8]) -> String {
+    let units: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+        .trim_end_matches('\0')
+        .to_string()
+}
+
+/// Text payloads are usually ascii but some firmware measures UTF-16 strings.
+fn decode_text(data: &[u8]) -> String {
+    let looks_utf16 = data.len() >= 2 && data.len().is_multiple_of(2) && data[1] == 0;
+    if looks_utf16 {
+        decode_utf16(data)
+    } else {
+        String::from_utf8_lossy(data)
+            .trim_end_matches('\0')
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EventLogs;
+
+    #[test]
+    fn decode_ccel() {
+        let boot_time_data = include_bytes!("../samples/ccel.bin");
+        let event_logs = EventLogs::decode(&mut boot_time_data.as_slice())
+            .unwrap()
+            .into_tdx_event_logs()
+            .unwrap();
+        let decoded = event_logs
+            .iter()
+            .map(|e| e.decode_efi().unwrap())
+            .collect::<Vec<_>>();
+        insta::assert_snapshot!(serde_json::to_string_pretty(&decoded).unwrap());
+    }
+
+    /// What grub logs when measuring [`measured`] after [`prefix`].
+    fn grub_ipl(prefix: &str, measured: &str) -> TdxEventLog {
+        use sha2::Digest;
+        TdxEventLog {
+            imr: 1,
+            event_type: EV_IPL,
+            digest: sha2::Sha384::digest(measured.as_bytes()).into(),
+            event: String::new(),
+            event_payload: format!("{}{}\0", prefix, measured).into_bytes(),
+            extra_digests: vec![],
+        }
+    }
+
+    #[test]
+    fn grub_kernel_cmdline() {
+        let event_logs = vec![
+            grub_ipl("grub_cmd: ", "linux /vmlinuz console=ttyS0"),
+            grub_ipl("kernel_cmdline: ", "/vmlinuz console=ttyS0"),
+        ];
+
+        assert_eq!(
+            kernel_cmdline(&event_logs).unwrap().as_deref(),
+            Some("/vmlinuz console=ttyS0")
+        );
+        assert_eq!(kernel_cmdline(&event_logs[..1]).unwrap(), None);
+
+        // Measured again, e.g after a reload.
+        let mut twice = event_logs.clone();
+        twice.push(grub_ipl("kernel_cmdline: ", "/vmlinuz console=ttyS0"));
+        assert!(kernel_cmdline(&twice).unwrap().is_some());
+        twice.push(grub_ipl("kernel_cmdline: ", "/vmlinuz init=/bin/sh"));
+        assert!(kernel_cmdline(&twice).is_err());
+
+        // The payload must be what was measured.
+        let mut spoofed = event_logs.clone();
+        spoofed[1].event_payload = b"kernel_cmdline: /vmlinuz init=/bin/sh\0".to_vec();
+        assert!(kernel_cmdline(&spoofed).is_err());
+    }
+
+    #[test]
+    fn validates_ccel_payloads() {
+        let boot_time_data = include_bytes!("../samples/ccel.bin");
+        let event_logs = EventLogs::decode(&mut boot_time_data.as_slice())
+            .unwrap()
+            .into_tdx_event_logs()
+            .unwrap();
+
+        let mut checked = 0;
+        for event_log in &event_logs {
+            if event_log.validate_payload().unwrap() {
+                checked += 1;
+            }
+        }
+        // Variables, separators and actions, not the images, firmware volumes and ACPI tables.
+        assert_eq!(checked, 12);
+
+        let mut tampered = event_logs[2].clone();
+        tampered.event_payload[30] ^= 1;
+        assert!(tampered.validate_payload().is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_payloads() {
+        assert!(decode_event(EV_EFI_VARIABLE_BOOT, &[0; 20]).is_err());
+        assert!(decode_event(EV_SEPARATOR, &[0; 2]).is_err());
+        assert_eq!(
+            decode_event(EV_POST_CODE2, &[1, 2]).unwrap(),
+            EfiEvent::Raw { data: vec![1, 2] }
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//! Encoding event logs back to the binary CCEL layout.
+//!
+//! This is the inverse of [`EventLogs::decode`]: a `TCG_PCClientPCREvent` header carrying the spec id event,
+//! followed by `TCG_PCR_EVENT2` entries and a terminator. Together with [`CcelBuilder`] it allows producing
+//! synthetic CCEL blobs, e.g to test measurement policies without booting a TD.
+//!
+//! Note: the ACPI table the firmware exposes is padded with `0xff` after the terminator, encoding only yields
+//! the used part of it.
+//!
+
+use anyhow::{bail, Result};
+use scale::Encode;
+
+use crate::{
+    replay::digest_with,
+    tcg::{self, TcgDigest, TcgEfiSpecIdEvent, TcgEfiSpecIdEventAlgorithmSize},
+    EventLogs, TcgEventLog,
+};
+
+/// TDVF logs the spec id header with MR index 1, i.e RTMR0.
+const SPEC_ID_HEADER_IMR_INDEX: u32 = 1;
+/// Signature of the crypto agile log format.
+const SPEC_ID_SIGNATURE: &[u8; 16] = b"Spec ID Event03\0";
+/// The decoder stops at the first entry with this IMR index, it's also what the unused part of the table is
+/// filled with.
+const TERMINATOR: [u8; 4] = [0xff; 4];
+
+impl EventLogs {
+    /// Encodes the header, events and terminator, see the module docs.
+    pub fn encode(&self) -> Vec<u8> {
+        let spec_id_event = self.spec_id_header_event.encode();
+
+        let mut ccel = vec![];
+        SPEC_ID_HEADER_IMR_INDEX.encode_to(&mut ccel);
+        tcg::EV_NO_ACTION.encode_to(&mut ccel);
+        [0_u8; 20].encode_to(&mut ccel);
+        (spec_id_event.len() as u32).encode_to(&mut ccel);
+        ccel.extend(spec_id_event);
+        for event_log in &self.event_logs {
+            event_log.encode_to(&mut ccel);
+        }
+        ccel.extend(TERMINATOR);
+        ccel
+    }
+}
+
+/// Builds synthetic boot time event logs, with one digest per declared bank for every event.
+#[derive(Clone, Debug)]
+pub struct CcelBuilder {
+    spec_id_header_event: TcgEfiSpecIdEvent,
+    event_logs: Vec<TcgEventLog>,
+}
+
+impl Default for CcelBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CcelBuilder {
+    /// Only the SHA-384 bank, which is what TDVF logs.
+    pub fn new() -> Self {
+        Self::with_banks(&[tcg::TPM_ALG_SHA384]).expect("sha384 is supported")
+    }
+
+    /// Declares [`banks`] in the spec id header, in that order. SHA-384 must be one of them and only the SHA-2
+    /// algorithms are supported.
+    pub fn with_banks(banks: &[u16]) -> Result<Self> {
+        let mut digest_sizes = vec![];
+        for &algo_id in banks {
+            let Some(digest_size) = crate::alg_id_to_digest_size(algo_id) else {
+                bail!("unsupported digest algorithm {:#x}", algo_id);
+            };
+            // Makes sure we can actually compute the digests of this bank later on.
+            digest_with(algo_id, &[])?;
+            digest_sizes.push(TcgEfiSpecIdEventAlgorithmSize {
+                algo_id,
+                digest_size: digest_size as u16,
+            });
+        }
+
+        let spec_id_header_event = TcgEfiSpecIdEvent {
+            signature: *SPEC_ID_SIGNATURE,
+            spec_version_major: 2,
+            uintn_ize: 2,
+            digest_sizes: (digest_sizes.len() as u32, digest_sizes).into(),
+            ..TcgEfiSpecIdEvent::new()
+        };
+        spec_id_header_event.validate()?;
+
+        Ok(Self {
+            spec_id_header_event,
+            event_logs: vec![],
+        })
+    }
+
+    /// Adds an event whose digests are the hashes of [`event_data`] itself, as for e.g `EV_SEPARATOR` or
+    /// `EV_IPL`. [`imr`] is zero-based like [`crate::TdxEventLog::imr`].
+    pub fn event(self, imr: u32, event_type: u32, event_data: impl Into<Vec<u8>>) -> Self {
+        let event_data = event_data.into();
+        let measured = event_data.clone();
+        self.event_measuring(imr, event_type, event_data, &measured)
+    }
+
+    /// Adds an event whose digests are the hashes of [`measured`] rather than of the logged data, as for e.g
+    /// image loads where the payload only describes the image.
+    pub fn event_measuring(
+        mut self,
+        imr: u32,
+        event_type: u32,
+        event_data: impl Into<Vec<u8>>,
+        measured: &[u8],
+    ) -> Self {
+        let digests: Vec<_> = self
+            .spec_id_header_event
+            .digest_sizes
+            .iter()
+            .map(|size| TcgDigest {
+                algo_id: size.algo_id,
+                hash: digest_with(size.algo_id, &[measured])
+                    .expect("banks are checked on creation"),
+            })
+            .collect();
+        let event_data = event_data.into();
+
+        self.event_logs.push(TcgEventLog {
+            imr_index: imr + 1,
+            event_type,
+            digests: (digests.len() as u32, digests).into(),
+            event: (event_data.len() as u32, event_data).into(),
+        });
+        self
+    }
+
+    pub fn build_event_logs(self) -> EventLogs {
+        EventLogs {
+            spec_id_header_event: self.spec_id_header_event,
+            event_logs: self.event_logs,
+        }
+    }
+
+    /// Encoded CCEL, see [`EventLogs::encode`].
+    pub fn build(self) -> Vec<u8> {
+        self.build_event_logs().encode()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{replay_bank, replay_rtmrs, tcg::*, TdxEventLog};
+    use sha2::Digest;
+
+    fn decode(ccel: &[u8]) -> Vec<TdxEventLog> {
+        EventLogs::decode(&mut &ccel[..])
+            .unwrap()
+            .into_tdx_event_logs()
+            .unwrap()
+    }
+
+    #[test]
+    fn roundtrip_ccel() {
+        let boot_time_data = include_bytes!("../samples/ccel.bin");
+        let encoded = EventLogs::decode(&mut boot_time_data.as_slice())
+            .unwrap()
+            .encode();
+
+        // Byte for byte, up to the padding.
+        assert_eq!(encoded[..], boot_time_data[..encoded.len()]);
+        assert!(boot_time_data[encoded.len()..].iter().all(|b| *b == 0xff));
+        assert_eq!(decode(&encoded), decode(boot_time_data));
+    }
+
+    #[test]
+    fn builds_synthetic_ccel() {
+        let kernel = b"not really a kernel";
+        let ccel = CcelBuilder::with_banks(&[TPM_ALG_SHA256, TPM_ALG_SHA384])
+            .unwrap()
+            .event(0, EV_SEPARATOR, [0; 4])
+            .event_measuring(1, EV_EFI_BOOT_SERVICES_APPLICATION, vec![1; 32], kernel)
+            .event_measuring(2, EV_IPL, "kernel_cmdline: console=ttyS0", b"console=ttyS0")
+            .build();
+
+        let event_logs = decode(&ccel);
+        assert_eq!(event_logs.len(), 3);
+        assert_eq!(event_logs[1].imr, 1);
+        assert_eq!(event_logs[1].event_payload, vec![1; 32]);
+        assert_eq!(event_logs[1].digest[..], sha2::Sha384::digest(kernel)[..]);
+        assert_eq!(
+            event_logs[1].digest_for(TPM_ALG_SHA256),
+            Some(&sha2::Sha256::digest(kernel)[..])
+        );
+        assert_eq!(
+            crate::efi::kernel_cmdline(&event_logs).unwrap().as_deref(),
+            Some("console=ttyS0")
+        );
+
+        let rtmrs = replay_rtmrs(&event_logs).unwrap();
+        assert_eq!(
+            rtmrs[1],
+            crate::extend_rtmr(&[0; 48], &event_logs[1].digest)
+        );
+        assert_eq!(rtmrs[3], [0; 48]);
+        replay_bank(&event_logs, TPM_ALG_SHA256).unwrap();
+
+        // Decoding and encoding again is lossless.
+        assert_eq!(
+            EventLogs::decode(&mut ccel.as_slice()).unwrap().encode(),
+            ccel
+        );
+    }
+
+    #[test]
+    fn rejects_unsupported_banks() {
+        assert!(CcelBuilder::with_banks(&[TPM_ALG_SHA256]).is_err());
+        assert!(CcelBuilder::with_banks(&[TPM_ALG_SHA1, TPM_ALG_SHA384]).is_err());
+        assert!(CcelBuilder::with_banks(&[TPM_ALG_RSA, TPM_ALG_SHA384]).is_err());
+    }
+}
@@ -1,23 +1,59 @@
 use crate::codecs::VecOf;
-use anyhow::{Context, Result};
-use scale::Decode;
+use anyhow::{bail, Context, Result};
+use scale::{Decode, Input};
 use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 use tcg::{TcgDigest, TcgEfiSpecIdEvent};
 
+pub mod cel;
 mod codecs;
+pub mod efi;
+mod encode;
+mod policy;
+mod replay;
 mod tcg;
 
+pub use encode::CcelBuilder;
+pub use policy::{EventLogPolicy, EventRule};
+pub use replay::{extend_rtmr, replay_bank, replay_rtmrs, verify_rtmrs, Rtmr, RTMR_COUNT};
+pub use tcg::{
+    EV_EFI_ACTION, EV_EFI_BOOT_SERVICES_APPLICATION, EV_EVENT_TAG, EV_IPL, EV_NO_ACTION,
+    EV_SEPARATOR, TPM_ALG_SHA1, TPM_ALG_SHA256, TPM_ALG_SHA384, TPM_ALG_SHA512,
+};
+
+/// RTMR runtime events are measured into, RTMR0-2 are used by firmware and the boot chain.
+pub const RUNTIME_RTMR: u32 = 3;
+/// Event type of runtime events, see [`TdxEventLog::new`].
+pub const RUNTIME_EVENT_TYPE: u32 = 0x08000001;
+
 /// The path to the userspace TDX event log file.
 pub const RUNTIME_EVENT_LOG_FILE: &str = "/run/log/tdx_mr3/tdx_events.log";
 /// The path to boottime ccel file.
-const CCEL_FILE: &str = "/sys/firmware/acpi/tables/data/CCEL";
+pub const CCEL_FILE: &str = "/sys/firmware/acpi/tables/data/CCEL";
+
+/// Where event logs are read from. Defaults to [`CCEL_FILE`] and [`RUNTIME_EVENT_LOG_FILE`], other paths are
+/// mostly useful for tests and images that mount them elsewhere.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EventLogPaths {
+    pub ccel: PathBuf,
+    pub runtime: PathBuf,
+}
+
+impl Default for EventLogPaths {
+    fn default() -> Self {
+        Self {
+            ccel: CCEL_FILE.into(),
+            runtime: RUNTIME_EVENT_LOG_FILE.into(),
+        }
+    }
+}
 
 /// This is the common struct for tcg event logs to be delivered in different formats.
 /// Currently TCG supports several event log formats defined in TCG_PCClient Spec,
 /// Canonical Eventlog Spec, etc.
 /// This struct provides the functionality to convey event logs in different format
 /// according to request.
-#[derive(Clone, scale::Decode)]
+#[derive(Clone, scale::Decode, scale::Encode)]
 pub struct TcgEventLog {
     /// IMR index, starts from 1
     pub imr_index: u32,
@@ -30,12 +66,12 @@ pub struct TcgEventLog {
 }
 
 /// This is the TDX event log format that is used to store the event log in the TDX guest.
-/// It is a simplified version of the TCG event log format, containing only a single digest
-/// and the raw event data. The IMR index is zero-based, unlike the TCG event log format
-/// which is one-based.
+/// It is a simplified version of the TCG event log format, containing the SHA-384 digest RTMRs
+/// are extended with (other banks are kept in `extra_digests`) and the raw event data. The IMR
+/// index is zero-based, unlike the TCG event log format which is one-based.
 ///
 /// As for RTMR3, the digest extended is calculated as `sha384(event_type.to_ne_bytes() || b":" || event || b":" || event_payload)`.
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TdxEventLog {
     /// IMR index, starts from 0
     pub imr: u32,
@@ -49,6 +85,18 @@ pub struct TdxEventLog {
     /// Event payload
     #[serde(with = "serde_human_bytes")]
     pub event_payload: Vec<u8>,
+    /// Digests from banks other than SHA-384 (the one RTMRs are extended with), e.g SHA-256 for tooling that
+    /// expects TPM-like PCR banks.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_digests: Vec<EventDigest>,
+}
+
+/// Digest of an event in one of the algorithm banks.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventDigest {
+    pub algo_id: u16,
+    #[serde(with = "serde_human_bytes")]
+    pub digest: Vec<u8>,
 }
 
 fn event_digest(ty: u32, event: &str, payload: &[u8]) -> [u8; 48] {
@@ -71,7 +119,19 @@ impl TdxEventLog {
             digest,
             event,
             event_payload,
+            extra_digests: vec![],
+        }
+    }
+
+    /// Returns the digest of the [`algo_id`] bank, if the event has one.
+    pub fn digest_for(&self, algo_id: u16) -> Option<&[u8]> {
+        if algo_id == tcg::TPM_ALG_SHA384 {
+            return Some(&self.digest);
         }
+        self.extra_digests
+            .iter()
+            .find(|d| d.algo_id == algo_id)
+            .map(|d| d.digest.as_slice())
     }
 
     pub fn new_str(imr: u32, event_type: u32, event: &str, event_payload: &str) -> Self {
@@ -94,37 +154,105 @@ impl TdxEventLog {
         }
         Ok(())
     }
+
+    /// Checks that the payload is what the digest covers, replaying a log only vouches for the digests. Returns
+    /// false for boot time events whose digest covers data outside of the log (see [`efi::measured_data`]), their
+    /// payload can't be trusted either way.
+    pub fn validate_payload(&self) -> Result<bool> {
+        if self.imr == RUNTIME_RTMR {
+            self.validate()?;
+            return Ok(true);
+        }
+        let Some(candidates) = efi::measured_data(self.event_type, &self.event_payload) else {
+            return Ok(false);
+        };
+
+        use sha2::Digest;
+        if !candidates
+            .iter()
+            .any(|data| sha2::Sha384::digest(data)[..] == self.digest[..])
+        {
+            bail!(
+                "payload of event type {:#x} on imr {} doesn't match its digest",
+                self.event_type,
+                self.imr
+            );
+        }
+        Ok(true)
+    }
 }
 
 impl TryFrom<TcgEventLog> for TdxEventLog {
     type Error = anyhow::Error;
 
     fn try_from(value: TcgEventLog) -> Result<Self> {
-        if value.digests.len() != 1 {
-            return Err(anyhow::anyhow!(
-                "expected 1 digest, got {}",
-                value.digests.len()
-            ));
+        let mut digest = None;
+        let mut extra_digests = vec![];
+        for tcg_digest in value.digests.into_inner() {
+            if tcg_digest.algo_id == tcg::TPM_ALG_SHA384 {
+                digest = Some(
+                    tcg_digest
+                        .hash
+                        .try_into()
+                        .ok()
+                        .context("invalid digest size")?,
+                );
+            } else {
+                extra_digests.push(EventDigest {
+                    algo_id: tcg_digest.algo_id,
+                    digest: tcg_digest.hash,
+                });
+            }
         }
-        let digest = value
-            .digests
-            .into_inner()
-            .into_iter()
-            .next()
-            .context("digest not found")?
-            .hash
-            .try_into()
-            .ok()
-            .context("invalid digest size")?;
+
         Ok(TdxEventLog {
             imr: value
                 .imr_index
                 .checked_sub(1)
                 .context("invalid imr index")?,
             event_type: value.event_type,
-            digest,
+            digest: digest.context("sha384 digest not found")?,
             event: Default::default(),
             event_payload: value.event.into(),
+            extra_digests,
+        })
+    }
+}
+
+impl TcgEventLog {
+    /// Decodes an event using the digest sizes declared in the spec id header rather than the ones we know
+    /// about. Every event must carry exactly one digest per declared algorithm.
+    fn decode_with_spec(input: &mut &[u8], spec: &TcgEfiSpecIdEvent) -> Result<Self> {
+        let imr_index = u32::decode(input).context("failed to decode imr")?;
+        let event_type = u32::decode(input).context("failed to decode event type")?;
+        let count = u32::decode(input).context("failed to decode digest count")?;
+        if count as usize != spec.digest_sizes.len() {
+            bail!(
+                "event has {} digests but the spec id header declares {} algorithms",
+                count,
+                spec.digest_sizes.len()
+            );
+        }
+
+        let mut digests = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let algo_id = u16::decode(input).context("failed to decode digest algorithm")?;
+            let size = spec.digest_size(algo_id).with_context(|| {
+                format!(
+                    "digest algorithm {:#x} is not declared in the spec id header",
+                    algo_id
+                )
+            })?;
+            let mut hash = vec![0; size as usize];
+            input.read(&mut hash).context("failed to read digest")?;
+            digests.push(TcgDigest { algo_id, hash });
+        }
+
+        Ok(Self {
+            imr_index,
+            event_type,
+            digests: (count, digests).into(),
+            event: VecOf::decode(input).context("failed to decode event data")?,
         })
     }
 }
@@ -164,6 +292,13 @@ pub struct EventLogs {
     pub event_logs: Vec<TcgEventLog>,
 }
 
+impl scale::Encode for TcgDigest {
+    fn encode_to<O: scale::Output + ?Sized>(&self, dest: &mut O) {
+        self.algo_id.encode_to(dest);
+        dest.write(&self.hash);
+    }
+}
+
 impl scale::Decode for TcgDigest {
     fn decode<I: scale::Input>(input: &mut I) -> Result<Self, scale::Error> {
         let algo_id = u16::decode(input)?;
@@ -184,6 +319,7 @@ impl EventLogs {
     pub fn decode(input: &mut &[u8]) -> Result<Self> {
         let (_spec_id_header, spec_id_header_event) =
             parse_spec_id_event_log(input).context("Failed to parse spec id event")?;
+        spec_id_header_event.validate()?;
         let mut event_logs = vec![];
         loop {
             // A tmp head_buffer is used to peek the imr and event type
@@ -192,7 +328,8 @@ impl EventLogs {
             if imr == 0xFFFFFFFF {
                 break;
             }
-            let event_log = TcgEventLog::decode(input).context("Failed to parse event log")?;
+            let event_log = TcgEventLog::decode_with_spec(input, &spec_id_header_event)
+                .context("Failed to parse event log")?;
             event_logs.push(event_log);
         }
         Ok(EventLogs {
@@ -202,7 +339,11 @@ impl EventLogs {
     }
 
     pub fn decode_from_ccel_file() -> Result<Self> {
-        let data = fs_err::read(CCEL_FILE).context("Failed to read CCEL")?;
+        Self::decode_from_file(CCEL_FILE)
+    }
+
+    pub fn decode_from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let data = fs_err::read(path.as_ref()).context("Failed to read CCEL")?;
         Self::decode(&mut data.as_slice())
     }
 
@@ -252,8 +393,8 @@ fn parse_spec_id_event_log<I: scale::Input>(
     Ok((spec_id_header, spec_id_event))
 }
 
-fn read_runtime_event_logs() -> Result<Vec<TdxEventLog>> {
-    let data = match fs_err::read_to_string(RUNTIME_EVENT_LOG_FILE) {
+fn read_runtime_event_logs(path: &Path) -> Result<Vec<TdxEventLog>> {
+    let data = match fs_err::read_to_string(path) {
         Ok(data) => data,
         Err(e) => {
             if e.kind() == std::io::ErrorKind::NotFound {
@@ -276,8 +417,13 @@ fn read_runtime_event_logs() -> Result<Vec<TdxEventLog>> {
 
 /// Read both boottime and runtime event logs.
 pub fn read_event_logs() -> Result<Vec<TdxEventLog>> {
-    let mut event_logs = EventLogs::decode_from_ccel_file()?.to_tdx_event_logs()?;
-    event_logs.extend(read_runtime_event_logs()?);
+    read_event_logs_from(&EventLogPaths::default())
+}
+
+/// Same as [`read_event_logs`] but from [`paths`].
+pub fn read_event_logs_from(paths: &EventLogPaths) -> Result<Vec<TdxEventLog>> {
+    let mut event_logs = EventLogs::decode_from_file(&paths.ccel)?.into_tdx_event_logs()?;
+    event_logs.extend(read_runtime_event_logs(&paths.runtime)?);
     Ok(event_logs)
 }
 
@@ -294,4 +440,91 @@ mod tests {
         let json = serde_json::to_string_pretty(&tdx_event_logs).unwrap();
         insta::assert_snapshot!(json);
     }
+
+    /// Minimal CCEL with a separator event in RTMR1, [`banks`] are the algorithms declared in the header and
+    /// [`event_banks`] the ones the event carries.
+    fn two_bank_ccel(banks: &[(u16, u16)], event_banks: &[u16]) -> Vec<u8> {
+        use sha2::Digest;
+
+        let mut spec_id_event = b"Spec ID Event03\0".to_vec();
+        spec_id_event.extend(0_u32.to_le_bytes());
+        spec_id_event.extend([0, 2, 0, 2]);
+        spec_id_event.extend((banks.len() as u32).to_le_bytes());
+        for (algo_id, size) in banks {
+            spec_id_event.extend(algo_id.to_le_bytes());
+            spec_id_event.extend(size.to_le_bytes());
+        }
+        spec_id_event.push(0);
+
+        let mut ccel = vec![];
+        ccel.extend(1_u32.to_le_bytes());
+        ccel.extend(tcg::EV_NO_ACTION.to_le_bytes());
+        ccel.extend([0; 20]);
+        ccel.extend((spec_id_event.len() as u32).to_le_bytes());
+        ccel.extend(spec_id_event);
+
+        let payload = [0_u8; 4];
+        ccel.extend(2_u32.to_le_bytes());
+        ccel.extend(tcg::EV_SEPARATOR.to_le_bytes());
+        ccel.extend((event_banks.len() as u32).to_le_bytes());
+        for algo_id in event_banks {
+            ccel.extend(algo_id.to_le_bytes());
+            match *algo_id {
+                TPM_ALG_SHA256 => ccel.extend(sha2::Sha256::digest(payload)),
+                _ => ccel.extend(sha2::Sha384::digest(payload)),
+            }
+        }
+        ccel.extend((payload.len() as u32).to_le_bytes());
+        ccel.extend(payload);
+        ccel.extend([0xff; 4]);
+        ccel
+    }
+
+    #[test]
+    fn multiple_digest_banks() {
+        use sha2::Digest;
+
+        let banks = [(TPM_ALG_SHA256, 32), (TPM_ALG_SHA384, 48)];
+        let ccel = two_bank_ccel(&banks, &[TPM_ALG_SHA256, TPM_ALG_SHA384]);
+        let event_logs = EventLogs::decode(&mut ccel.as_slice())
+            .unwrap()
+            .into_tdx_event_logs()
+            .unwrap();
+
+        let sha256 = sha2::Sha256::digest([0; 4]).to_vec();
+        assert_eq!(event_logs[0].digest_for(TPM_ALG_SHA256), Some(&sha256[..]));
+
+        let sha256_bank = replay_bank(&event_logs, TPM_ALG_SHA256).unwrap();
+        let expected: Vec<u8> = sha2::Sha256::new()
+            .chain_update([0; 32])
+            .chain_update(&sha256)
+            .finalize()
+            .to_vec();
+        assert_eq!(sha256_bank[1], expected);
+        assert_eq!(
+            replay_bank(&event_logs, TPM_ALG_SHA384).unwrap()[1],
+            replay_rtmrs(&event_logs).unwrap()[1]
+        );
+
+        // Survives the CEL roundtrip.
+        let records = cel::from_cbor(&cel::to_cbor(&cel::to_cel(&event_logs)).unwrap()).unwrap();
+        assert_eq!(cel::from_cel(&records).unwrap()[0], event_logs[0]);
+    }
+
+    #[test]
+    fn digests_must_match_spec_id_header() {
+        let banks = [(TPM_ALG_SHA256, 32), (TPM_ALG_SHA384, 48)];
+        // Missing bank.
+        let ccel = two_bank_ccel(&banks, &[TPM_ALG_SHA384]);
+        assert!(EventLogs::decode(&mut ccel.as_slice()).is_err());
+        // Undeclared bank.
+        let ccel = two_bank_ccel(&banks[1..], &[TPM_ALG_SHA256]);
+        assert!(EventLogs::decode(&mut ccel.as_slice()).is_err());
+        // Wrong declared size.
+        let ccel = two_bank_ccel(&[(TPM_ALG_SHA384, 32)], &[TPM_ALG_SHA384]);
+        assert!(EventLogs::decode(&mut ccel.as_slice()).is_err());
+        // No sha384 bank.
+        let ccel = two_bank_ccel(&banks[..1], &[TPM_ALG_SHA256]);
+        assert!(EventLogs::decode(&mut ccel.as_slice()).is_err());
+    }
 }
@@ -0,0 +1,269 @@
+//! Policies over individual event log entries.
+//!
+//! Once an event log has been replayed against the RTMRs of a verified quote (see [`crate::verify_rtmrs`]) its
+//! digests can be trusted, and a verifier can decide based on what was actually loaded, e.g "the `app-id` event
+//! on RTMR3 must be `new-york`".
+//!
+//! NB: replaying doesn't say anything about the payloads next to the digests, anyone can rewrite them. Rules only
+//! look at events whose payload was checked against its digest (see [`TdxEventLog::validate_payload`]).
+//!
+//! Policies can also pin whole registers, e.g with the values computed ahead of deployment by `tdx-measure`.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{tcg::EV_NO_ACTION, TdxEventLog};
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct EventLogPolicy {
+    #[serde(default)]
+    pub rules: Vec<EventRule>,
+    /// Kernel command line the bootloader must have measured, see [`crate::efi::kernel_cmdline`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kernel_cmdline: Option<String>,
+    /// Hex-encoded MRTD, see [`EventLogPolicy::check_mrtd`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mrtd: Option<String>,
+    /// Hex-encoded RTMRs by index, compared with the replayed event log.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub rtmrs: BTreeMap<u32, String>,
+}
+
+/// Matches events by IMR and name.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct EventRule {
+    /// IMR index, starts from 0.
+    pub imr: u32,
+    /// Event name.
+    pub event: String,
+    /// Hex-encoded payloads the matching events may carry. Any payload is accepted if empty.
+    #[serde(default)]
+    pub allowed_payloads: Vec<String>,
+    /// Whether at least one matching event must be present.
+    #[serde(default = "default_required")]
+    pub required: bool,
+}
+
+fn default_required() -> bool {
+    true
+}
+
+impl EventRule {
+    fn matches(&self, event_log: &TdxEventLog) -> bool {
+        event_log.imr == self.imr
+            && event_log.event == self.event
+            && event_log.event_type != EV_NO_ACTION
+    }
+
+    fn check(&self, event_logs: &[&TdxEventLog]) -> Result<()> {
+        let mut found = false;
+        for event_log in event_logs.iter().filter(|e| self.matches(e)) {
+            found = true;
+            let payload = hex::encode(&event_log.event_payload);
+            if !self.allowed_payloads.is_empty()
+                && !self
+                    .allowed_payloads
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(&payload))
+            {
+                bail!(
+                    "event {} on imr {} has disallowed payload {}",
+                    self.event,
+                    self.imr,
+                    payload
+                );
+            }
+        }
+
+        if self.required && !found {
+            bail!(
+                "required event {} on imr {} not found",
+                self.event,
+                self.imr
+            );
+        }
+        Ok(())
+    }
+}
+
+impl EventLogPolicy {
+    /// Checks [`event_logs`] against every rule. The payloads of the events on the IMRs rules cover are checked
+    /// against their digests first, events whose digest doesn't cover their payload (e.g loaded images) never
+    /// match a rule.
+    ///
+    /// Note: this doesn't replay the log, callers must have verified its digests against the quote first.
+    pub fn check(&self, event_logs: &[TdxEventLog]) -> Result<()> {
+        let imrs: BTreeSet<u32> = self.rules.iter().map(|rule| rule.imr).collect();
+        let mut measured = vec![];
+        for event_log in event_logs
+            .iter()
+            .filter(|e| imrs.contains(&e.imr) && e.event_type != EV_NO_ACTION)
+        {
+            if event_log.validate_payload()? {
+                measured.push(event_log);
+            }
+        }
+
+        for rule in &self.rules {
+            rule.check(&measured)?;
+        }
+
+        if let Some(expected) = &self.kernel_cmdline {
+            match crate::efi::kernel_cmdline(event_logs)? {
+                Some(cmdline) if &cmdline == expected => {}
+                Some(cmdline) => bail!("unexpected kernel cmdline {}", cmdline),
+                None => bail!("kernel cmdline not found in event log"),
+            }
+        }
+
+        if !self.rtmrs.is_empty() {
+            let replayed = crate::replay_rtmrs(event_logs)?;
+            for (index, expected) in &self.rtmrs {
+                let replayed = replayed
+                    .get(*index as usize)
+                    .with_context(|| format!("policy pins unknown rtmr {}", index))?;
+                if !expected.eq_ignore_ascii_case(&hex::encode(replayed)) {
+                    bail!(
+                        "rtmr{} is {}, expected {}",
+                        index,
+                        hex::encode(replayed),
+                        expected
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the MRTD of a verified quote, which isn't covered by the event log.
+    pub fn check_mrtd(&self, mrtd: &[u8]) -> Result<()> {
+        match &self.mrtd {
+            Some(expected) if !expected.eq_ignore_ascii_case(&hex::encode(mrtd)) => {
+                bail!("mrtd is {}, expected {}", hex::encode(mrtd), expected)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(allowed: &[&str], required: bool) -> EventRule {
+        EventRule {
+            imr: 3,
+            event: "app-id".into(),
+            allowed_payloads: allowed.iter().map(hex::encode).collect(),
+            required,
+        }
+    }
+
+    #[test]
+    fn checks_rules() {
+        let event_logs = vec![
+            TdxEventLog::new_str(3, 0x08000001, "app-id", "new-york"),
+            TdxEventLog::new_str(3, 0x08000001, "config", "{}"),
+        ];
+
+        let policy = EventLogPolicy {
+            rules: vec![rule(&["new-york"], true)],
+            ..Default::default()
+        };
+        policy.check(&event_logs).unwrap();
+
+        let policy = EventLogPolicy {
+            rules: vec![rule(&["other"], true)],
+            ..Default::default()
+        };
+        assert!(policy.check(&event_logs).is_err());
+
+        // Missing required event.
+        let policy = EventLogPolicy {
+            rules: vec![rule(&[], true)],
+            ..Default::default()
+        };
+        assert!(policy.check(&event_logs[1..]).is_err());
+
+        // Optional events are only checked when present.
+        let policy = EventLogPolicy {
+            rules: vec![rule(&["other"], false)],
+            ..Default::default()
+        };
+        policy.check(&event_logs[1..]).unwrap();
+
+        // Payloads must match their digest.
+        let mut tampered = event_logs.clone();
+        tampered[0].event_payload = b"other".to_vec();
+        let policy = EventLogPolicy {
+            rules: vec![rule(&["other"], true)],
+            ..Default::default()
+        };
+        assert!(policy.check(&tampered).is_err());
+
+        // Events that weren't measured don't count.
+        let mut not_measured = event_logs.clone();
+        not_measured[0].event_type = EV_NO_ACTION;
+        let policy = EventLogPolicy {
+            rules: vec![rule(&[], true)],
+            ..Default::default()
+        };
+        assert!(policy.check(&not_measured).is_err());
+    }
+
+    #[test]
+    fn checks_kernel_cmdline() {
+        use sha2::Digest;
+        // Grub measures the command line without its prefix.
+        let ipl = TdxEventLog {
+            digest: sha2::Sha384::digest(b"/vmlinuz console=ttyS0").into(),
+            ..TdxEventLog::new_str(
+                1,
+                crate::tcg::EV_IPL,
+                "",
+                "kernel_cmdline: /vmlinuz console=ttyS0",
+            )
+        };
+        let policy = EventLogPolicy {
+            kernel_cmdline: Some("/vmlinuz console=ttyS0".into()),
+            ..Default::default()
+        };
+
+        policy.check(&[ipl]).unwrap();
+        assert!(policy.check(&[]).is_err());
+
+        // Spoofed payloads don't pass for the measured command line.
+        let spoofed = TdxEventLog {
+            event_payload: b"kernel_cmdline: /vmlinuz console=ttyS0".to_vec(),
+            ..TdxEventLog::new_str(1, crate::tcg::EV_IPL, "", "")
+        };
+        assert!(policy.check(&[spoofed]).is_err());
+    }
+
+    #[test]
+    fn pins_registers() {
+        let event_logs = vec![TdxEventLog::new_str(3, 0x08000001, "app-id", "new-york")];
+        let rtmr3 = crate::replay_rtmrs(&event_logs).unwrap()[3];
+        let mut policy = EventLogPolicy {
+            mrtd: Some(hex::encode([1; 48])),
+            rtmrs: [(0, hex::encode([0; 48])), (3, hex::encode(rtmr3))].into(),
+            ..Default::default()
+        };
+
+        policy.check(&event_logs).unwrap();
+        policy.check_mrtd(&[1; 48]).unwrap();
+        assert!(policy.check_mrtd(&[2; 48]).is_err());
+        assert!(policy.check(&[]).is_err());
+
+        policy.rtmrs.insert(4, hex::encode([0; 48]));
+        assert!(policy.check(&event_logs).is_err());
+    }
+
+    #[test]
+    fn deserializes_with_defaults() {
+        let policy: EventLogPolicy =
+            serde_json::from_str(r#"{"rules":[{"imr":3,"event":"app-id"}]}"#).unwrap();
+        assert_eq!(policy.rules[0], rule(&[], true));
+    }
+}
@@ -0,0 +1,185 @@
+//! RTMR replay.
+//!
+//! An RTMR is only a running hash: `rtmr = sha384(rtmr || digest)` starting from 48 zero bytes. Replaying the
+//! event log and comparing the result with the RTMRs of a verified quote proves that the log is the one that was
+//! measured, after which individual entries (e.g the app compose hash) can be trusted rather than the opaque
+//! RTMR values.
+
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+use crate::{tcg, TdxEventLog};
+
+/// Number of runtime measurement registers.
+pub const RTMR_COUNT: usize = 4;
+
+pub type Rtmr = [u8; 48];
+
+/// Extends [`rtmr`] with [`digest`] as the TDX module does.
+pub fn extend_rtmr(rtmr: &Rtmr, digest: &[u8; 48]) -> Rtmr {
+    let mut hasher = Sha384::new();
+    hasher.update(rtmr);
+    hasher.update(digest);
+    hasher.finalize().into()
+}
+
+/// Recomputes RTMR0-3 from [`event_logs`].
+///
+/// `EV_NO_ACTION` events are informational and never extended, so they are skipped.
+pub fn replay_rtmrs(event_logs: &[TdxEventLog]) -> Result<[Rtmr; RTMR_COUNT]> {
+    let mut rtmrs = [[0; 48]; RTMR_COUNT];
+    for event_log in event_logs {
+        if event_log.event_type == tcg::EV_NO_ACTION {
+            continue;
+        }
+        let Some(rtmr) = rtmrs.get_mut(event_log.imr as usize) else {
+            bail!("event log targets unknown rtmr {}", event_log.imr);
+        };
+        *rtmr = extend_rtmr(rtmr, &event_log.digest);
+    }
+    Ok(rtmrs)
+}
+
+/// Replays the [`algo_id`] bank (e.g [`crate::TPM_ALG_SHA256`]) the same way RTMRs are replayed, starting from
+/// zeroed registers of that algorithm's size.
+///
+/// Note: only the SHA-384 bank can be compared with a quote, the others are for tooling expecting other banks.
+pub fn replay_bank(event_logs: &[TdxEventLog], algo_id: u16) -> Result<[Vec<u8>; RTMR_COUNT]> {
+    let size = crate::alg_id_to_digest_size(algo_id)
+        .with_context(|| format!("unsupported digest algorithm {:#x}", algo_id))?;
+    let mut registers: [Vec<u8>; RTMR_COUNT] = Default::default();
+    for register in registers.iter_mut() {
+        *register = vec![0; size as usize];
+    }
+
+    for event_log in event_logs {
+        if event_log.event_type == tcg::EV_NO_ACTION {
+            continue;
+        }
+        let Some(register) = registers.get_mut(event_log.imr as usize) else {
+            bail!("event log targets unknown rtmr {}", event_log.imr);
+        };
+        let digest = event_log.digest_for(algo_id).with_context(|| {
+            format!(
+                "event {:?} has no digest for algorithm {:#x}",
+                event_log.event, algo_id
+            )
+        })?;
+        *register = digest_with(algo_id, &[register, digest])?;
+    }
+    Ok(registers)
+}
+
+/// Hashes the concatenation of [`parts`] with the [`algo_id`] algorithm.
+pub(crate) fn digest_with(algo_id: u16, parts: &[&[u8]]) -> Result<Vec<u8>> {
+    fn digest<D: Digest>(parts: &[&[u8]]) -> Vec<u8> {
+        let mut hasher = D::new();
+        for part in parts {
+            hasher.update(part);
+        }
+        hasher.finalize().to_vec()
+    }
+
+    Ok(match algo_id {
+        tcg::TPM_ALG_SHA256 => digest::<Sha256>(parts),
+        tcg::TPM_ALG_SHA384 => digest::<Sha384>(parts),
+        tcg::TPM_ALG_SHA512 => digest::<Sha512>(parts),
+        other => bail!("unsupported digest algorithm {:#x}", other),
+    })
+}
+
+/// Checks every event's digest and that replaying [`event_logs`] yields [`expected`] (the RTMRs of a verified
+/// quote).
+pub fn verify_rtmrs(event_logs: &[TdxEventLog], expected: &[Rtmr; RTMR_COUNT]) -> Result<()> {
+    for event_log in event_logs {
+        event_log.validate()?;
+    }
+
+    let replayed = replay_rtmrs(event_logs)?;
+    for (index, (replayed, expected)) in replayed.iter().zip(expected).enumerate() {
+        if replayed != expected {
+            bail!(
+                "rtmr{} mismatch: replayed {}, expected {}",
+                index,
+                hex::encode(replayed),
+                hex::encode(expected)
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EventLogs;
+
+    fn sample_event_logs() -> Vec<TdxEventLog> {
+        let boot_time_data = include_bytes!("../samples/ccel.bin");
+        let mut event_logs = EventLogs::decode(&mut boot_time_data.as_slice())
+            .unwrap()
+            .into_tdx_event_logs()
+            .unwrap();
+        event_logs.push(TdxEventLog::new_str(3, 0x08000001, "app-id", "new-york"));
+        event_logs
+    }
+
+    #[test]
+    fn replay_ccel() {
+        let rtmrs = replay_rtmrs(&sample_event_logs()).unwrap();
+        insta::assert_debug_snapshot!(rtmrs.iter().map(hex::encode).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn replay_runtime_events() {
+        let first = TdxEventLog::new_str(3, 0x08000001, "a", "1");
+        let second = TdxEventLog::new_str(3, 0x08000001, "b", "2");
+        let expected = extend_rtmr(&extend_rtmr(&[0; 48], &first.digest), &second.digest);
+
+        let rtmrs = replay_rtmrs(&[first, second]).unwrap();
+        assert_eq!(rtmrs[3], expected);
+        assert_eq!(rtmrs[0], [0; 48]);
+    }
+
+    #[test]
+    fn verify_detects_tampering() {
+        let event_logs = sample_event_logs();
+        let rtmrs = replay_rtmrs(&event_logs).unwrap();
+        verify_rtmrs(&event_logs, &rtmrs).unwrap();
+
+        // Payload doesn't match the digest anymore.
+        let mut tampered = event_logs.clone();
+        tampered.last_mut().unwrap().event_payload = b"other".to_vec();
+        assert!(verify_rtmrs(&tampered, &rtmrs).is_err());
+
+        // Event consistent with its digest, but not the one that was measured.
+        let mut replaced = event_logs.clone();
+        *replaced.last_mut().unwrap() = TdxEventLog::new_str(3, 0x08000001, "app-id", "other");
+        assert!(verify_rtmrs(&replaced, &rtmrs).is_err());
+
+        // Dropped event.
+        let mut dropped = event_logs;
+        dropped.remove(1);
+        assert!(verify_rtmrs(&dropped, &rtmrs).is_err());
+    }
+
+    #[test]
+    fn sha384_bank_matches_rtmrs() {
+        let event_logs = sample_event_logs();
+        let rtmrs = replay_rtmrs(&event_logs).unwrap();
+        let bank = replay_bank(&event_logs, tcg::TPM_ALG_SHA384).unwrap();
+        assert!(rtmrs
+            .iter()
+            .zip(&bank)
+            .all(|(rtmr, reg)| rtmr == reg.as_slice()));
+
+        // The sample only has the sha384 bank.
+        assert!(replay_bank(&event_logs, tcg::TPM_ALG_SHA256).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_rtmr() {
+        let event_log = TdxEventLog::new_str(4, 0x08000001, "a", "1");
+        assert!(replay_rtmrs(&[event_log]).is_err());
+    }
+}
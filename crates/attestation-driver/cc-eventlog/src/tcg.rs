@@ -154,7 +154,7 @@ pub struct TcgPcClientImrEvent {
         BYTE[VendorInfoSize] vendorInfo;
     } TCG_EfiSpecIDEventStruct;
 */
-#[derive(Clone, scale::Decode, Debug)]
+#[derive(Clone, scale::Decode, scale::Encode, Debug)]
 pub struct TcgEfiSpecIdEvent {
     pub signature: [u8; 16],
     pub platform_class: u32,
@@ -185,6 +185,34 @@ impl TcgEfiSpecIdEvent {
             vendor_info: Default::default(),
         }
     }
+
+    pub fn digest_size(&self, algo_id: u16) -> Option<u16> {
+        self.digest_sizes
+            .iter()
+            .find(|s| s.algo_id == algo_id)
+            .map(|s| s.digest_size)
+    }
+
+    /// Checks that the declared sizes of known algorithms are correct and that the SHA-384 bank RTMRs are
+    /// extended with is present.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for size in self.digest_sizes.iter() {
+            if let Some(expected) = crate::alg_id_to_digest_size(size.algo_id) {
+                if size.digest_size != expected as u16 {
+                    anyhow::bail!(
+                        "spec id header declares size {} for algorithm {:#x}, expected {}",
+                        size.digest_size,
+                        size.algo_id,
+                        expected
+                    );
+                }
+            }
+        }
+        if self.digest_size(TPM_ALG_SHA384).is_none() {
+            anyhow::bail!("spec id header doesn't declare sha384");
+        }
+        Ok(())
+    }
 }
 
 /***
@@ -196,7 +224,7 @@ impl TcgEfiSpecIdEvent {
         UINT16 digestSize;
     } TCG_EfiSpecIdEventAlgorithmSize;
 */
-#[derive(Clone, scale::Decode, Debug)]
+#[derive(Clone, scale::Decode, scale::Encode, Debug)]
 pub struct TcgEfiSpecIdEventAlgorithmSize {
     pub algo_id: u16,
     pub digest_size: u16,
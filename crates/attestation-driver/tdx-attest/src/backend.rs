@@ -0,0 +1,260 @@
+//! Backends abstracting access to the TDX module and the event logs.
+//!
+//! [`HardwareBackend`] goes through the `tdx_attest` library and the event log files of the guest, while
+//! [`SoftwareBackend`] keeps the RTMRs and the event log in memory. The latter is what allows measurement and
+//! replay logic to be tested on plain Linux, its reports and quotes only carry the report data and RTMRs at the
+//! right offsets and will never pass verification.
+//!
+
+use anyhow::{bail, Context};
+use cc_eventlog::{EventLogPaths, Rtmr, TdxEventLog, RTMR_COUNT};
+use std::sync::Mutex;
+
+use crate::{sys::TDX_REPORT_SIZE, TdxReport, TdxReportData, RUNTIME_EVENT_TYPE, RUNTIME_RTMR};
+
+// TDREPORT offsets: REPORTDATA within REPORTMACSTRUCT and RTMR0 within TDINFO.
+const REPORT_REPORT_DATA_OFFSET: usize = 128;
+const REPORT_RTMRS_OFFSET: usize = 720;
+
+// v4 quote offsets: 48 bytes header followed by the 584 bytes TD quote body.
+const QUOTE_HEADER_SIZE: usize = 48;
+const QUOTE_BODY_SIZE: usize = 584;
+const QUOTE_RTMRS_OFFSET: usize = QUOTE_HEADER_SIZE + 328;
+const QUOTE_REPORT_DATA_OFFSET: usize = QUOTE_HEADER_SIZE + 520;
+
+pub trait TdxBackend: Send + Sync {
+    fn get_report(&self, report_data: &TdxReportData) -> anyhow::Result<TdxReport>;
+
+    fn get_quote(&self, report_data: &TdxReportData) -> anyhow::Result<Vec<u8>>;
+
+    /// Extends RTMR [`index`] without logging anything, prefer [`TdxBackend::measure`].
+    fn extend_rtmr(&self, index: u32, event_type: u32, digest: [u8; 48]) -> anyhow::Result<()>;
+
+    /// Extends the RTMR of [`event_log`] and appends it to the event log. Implementations must make sure that
+    /// concurrent measurements are logged in the same order they are extended.
+    fn measure(&self, event_log: &TdxEventLog) -> anyhow::Result<()>;
+
+    /// Boot time followed by runtime event logs.
+    fn read_event_logs(&self) -> anyhow::Result<Vec<TdxEventLog>>;
+
+    /// Measures a named runtime event into RTMR3, see [`crate::measure_event`].
+    fn measure_event(&self, event_name: &str, payload: &[u8]) -> anyhow::Result<TdxEventLog> {
+        let event_log = TdxEventLog::new(
+            RUNTIME_RTMR,
+            RUNTIME_EVENT_TYPE,
+            event_name.to_string(),
+            payload.to_vec(),
+        );
+        self.measure(&event_log)?;
+
+        Ok(event_log)
+    }
+}
+
+/// Backend for actual TDX guests.
+#[derive(Clone, Debug, Default)]
+pub struct HardwareBackend {
+    pub paths: EventLogPaths,
+}
+
+impl HardwareBackend {
+    pub fn new(paths: EventLogPaths) -> Self {
+        Self { paths }
+    }
+}
+
+impl TdxBackend for HardwareBackend {
+    fn get_report(&self, report_data: &TdxReportData) -> anyhow::Result<TdxReport> {
+        Ok(crate::get_report(report_data)?)
+    }
+
+    fn get_quote(&self, report_data: &TdxReportData) -> anyhow::Result<Vec<u8>> {
+        let (_, quote) = crate::get_quote(report_data, None)?;
+        Ok(quote)
+    }
+
+    fn extend_rtmr(&self, index: u32, event_type: u32, digest: [u8; 48]) -> anyhow::Result<()> {
+        Ok(crate::extend_rtmr(index, event_type, digest)?)
+    }
+
+    // NB: the lock is on the log file itself so that it's also honored by other processes measuring events.
+    fn measure(&self, event_log: &TdxEventLog) -> anyhow::Result<()> {
+        let mut logfile = crate::open_event_log(&self.paths.runtime)?;
+        logfile
+            .file()
+            .lock()
+            .context("Failed to lock event log file")?;
+
+        crate::extend_rtmr(event_log.imr, event_log.event_type, event_log.digest)
+            .context("Failed to extend RTMR")?;
+        crate::write_event_log(&mut logfile, event_log)
+            .context("RTMR extended but the event log couldn't be updated, it won't replay")
+    }
+
+    fn read_event_logs(&self) -> anyhow::Result<Vec<TdxEventLog>> {
+        cc_eventlog::read_event_logs_from(&self.paths)
+    }
+}
+
+struct SoftwareState {
+    rtmrs: [Rtmr; RTMR_COUNT],
+    event_logs: Vec<TdxEventLog>,
+}
+
+/// In-memory backend with software RTMRs.
+pub struct SoftwareBackend {
+    state: Mutex<SoftwareState>,
+}
+
+impl Default for SoftwareBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SoftwareBackend {
+    /// Starts with zeroed RTMRs and an empty event log.
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(SoftwareState {
+                rtmrs: [[0; 48]; RTMR_COUNT],
+                event_logs: vec![],
+            }),
+        }
+    }
+
+    /// Starts as if [`boot_event_logs`] (e.g decoded from a CCEL sample) had been measured by the firmware.
+    pub fn with_boot_event_logs(boot_event_logs: Vec<TdxEventLog>) -> anyhow::Result<Self> {
+        let rtmrs = cc_eventlog::replay_rtmrs(&boot_event_logs)?;
+        Ok(Self {
+            state: Mutex::new(SoftwareState {
+                rtmrs,
+                event_logs: boot_event_logs,
+            }),
+        })
+    }
+
+    pub fn rtmrs(&self) -> [Rtmr; RTMR_COUNT] {
+        self.state.lock().unwrap().rtmrs
+    }
+}
+
+impl SoftwareState {
+    fn extend(&mut self, index: u32, digest: &[u8; 48]) -> anyhow::Result<()> {
+        let Some(rtmr) = self.rtmrs.get_mut(index as usize) else {
+            bail!("invalid rtmr index {}", index);
+        };
+        *rtmr = cc_eventlog::extend_rtmr(rtmr, digest);
+        Ok(())
+    }
+}
+
+impl TdxBackend for SoftwareBackend {
+    fn get_report(&self, report_data: &TdxReportData) -> anyhow::Result<TdxReport> {
+        let rtmrs = self.rtmrs();
+        let mut report = TdxReport([0; TDX_REPORT_SIZE as usize]);
+        report.0[REPORT_REPORT_DATA_OFFSET..REPORT_REPORT_DATA_OFFSET + 64]
+            .copy_from_slice(report_data);
+        report.0[REPORT_RTMRS_OFFSET..REPORT_RTMRS_OFFSET + 48 * RTMR_COUNT]
+            .copy_from_slice(rtmrs.as_flattened());
+
+        Ok(report)
+    }
+
+    fn get_quote(&self, report_data: &TdxReportData) -> anyhow::Result<Vec<u8>> {
+        let rtmrs = self.rtmrs();
+        let mut quote = vec![0; QUOTE_HEADER_SIZE + QUOTE_BODY_SIZE];
+        // version 4, ECDSA-256 attestation key, TDX tee type.
+        quote[0..2].copy_from_slice(&4_u16.to_le_bytes());
+        quote[2..4].copy_from_slice(&2_u16.to_le_bytes());
+        quote[4..8].copy_from_slice(&0x81_u32.to_le_bytes());
+        quote[QUOTE_RTMRS_OFFSET..QUOTE_RTMRS_OFFSET + 48 * RTMR_COUNT]
+            .copy_from_slice(rtmrs.as_flattened());
+        quote[QUOTE_REPORT_DATA_OFFSET..QUOTE_REPORT_DATA_OFFSET + 64].copy_from_slice(report_data);
+
+        Ok(quote)
+    }
+
+    fn extend_rtmr(&self, index: u32, _event_type: u32, digest: [u8; 48]) -> anyhow::Result<()> {
+        self.state.lock().unwrap().extend(index, &digest)
+    }
+
+    fn measure(&self, event_log: &TdxEventLog) -> anyhow::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.extend(event_log.imr, &event_log.digest)?;
+        state.event_logs.push(event_log.clone());
+
+        Ok(())
+    }
+
+    fn read_event_logs(&self) -> anyhow::Result<Vec<TdxEventLog>> {
+        Ok(self.state.lock().unwrap().event_logs.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cc_eventlog::EventLogs;
+
+    fn boot_event_logs() -> Vec<TdxEventLog> {
+        let boot_time_data = include_bytes!("../../cc-eventlog/samples/ccel.bin");
+        EventLogs::decode(&mut boot_time_data.as_slice())
+            .unwrap()
+            .into_tdx_event_logs()
+            .unwrap()
+    }
+
+    #[test]
+    fn software_measurements_replay() {
+        let backend = SoftwareBackend::with_boot_event_logs(boot_event_logs()).unwrap();
+        backend.measure_event("app-id", b"new-york").unwrap();
+        backend.measure_event("config", b"{}").unwrap();
+
+        let event_logs = backend.read_event_logs().unwrap();
+        cc_eventlog::verify_rtmrs(&event_logs, &backend.rtmrs()).unwrap();
+
+        // Unlogged extensions break the replay.
+        backend.extend_rtmr(3, RUNTIME_EVENT_TYPE, [1; 48]).unwrap();
+        assert!(cc_eventlog::verify_rtmrs(&event_logs, &backend.rtmrs()).is_err());
+        assert!(backend.extend_rtmr(4, RUNTIME_EVENT_TYPE, [1; 48]).is_err());
+    }
+
+    #[test]
+    fn software_report_and_quote_layout() {
+        let backend = SoftwareBackend::new();
+        backend.measure_event("app-id", b"new-york").unwrap();
+        let rtmr3 = backend.rtmrs()[3];
+        let report_data = [7; 64];
+
+        let report = backend.get_report(&report_data).unwrap();
+        assert_eq!(report.0[128..192], report_data);
+        assert_eq!(report.0[720 + 3 * 48..720 + 4 * 48], rtmr3);
+
+        let quote = backend.get_quote(&report_data).unwrap();
+        assert_eq!(quote.len(), 632);
+        assert_eq!(quote[568..632], report_data);
+        assert_eq!(quote[376 + 3 * 48..376 + 4 * 48], rtmr3);
+    }
+
+    #[test]
+    fn hardware_reads_configured_paths() {
+        let dir = std::env::temp_dir().join(format!("tdx-attest-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let paths = EventLogPaths {
+            ccel: dir.join("CCEL"),
+            runtime: dir.join("tdx_events.log"),
+        };
+        std::fs::write(&paths.ccel, include_bytes!("../../cc-eventlog/samples/ccel.bin")).unwrap();
+
+        let event_log = TdxEventLog::new_str(3, RUNTIME_EVENT_TYPE, "app-id", "new-york");
+        let mut logfile = crate::open_event_log(&paths.runtime).unwrap();
+        crate::write_event_log(&mut logfile, &event_log).unwrap();
+
+        let event_logs = HardwareBackend::new(paths).read_event_logs().unwrap();
+        assert_eq!(event_logs.len(), boot_event_logs().len() + 1);
+        assert_eq!(event_logs.last().unwrap().event, "app-id");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -3,6 +3,7 @@ use eventlog::TdxEventLog;
 pub use tdx_attest_sys as sys;
 
 use std::io::Write;
+use std::path::Path;
 use std::ptr;
 use std::slice;
 
@@ -12,7 +13,13 @@ use fs_err as fs;
 use num_enum::FromPrimitive;
 use thiserror::Error;
 
+pub use backend::{HardwareBackend, SoftwareBackend, TdxBackend};
 pub use cc_eventlog as eventlog;
+pub use cc_eventlog::{RUNTIME_EVENT_TYPE, RUNTIME_RTMR};
+pub use report::{ParsedTdxReport, ReportMacStruct, ReportType, TdInfo, TeeTcbInfo};
+
+mod backend;
+mod report;
 
 pub type Result<T> = std::result::Result<T, TdxAttestError>;
 
@@ -109,21 +116,21 @@ pub fn get_report(report_data: &TdxReportData) -> Result<TdxReport> {
     Ok(report)
 }
 
-pub fn log_rtmr_event(log: &TdxEventLog) -> anyhow::Result<()> {
-    // Append to event log
-    let logline = serde_json::to_string(&log).context("Failed to serialize event log")?;
-
-    let logfile_path = std::path::Path::new(eventlog::RUNTIME_EVENT_LOG_FILE);
+pub(crate) fn open_event_log(logfile_path: &Path) -> anyhow::Result<fs::File> {
     let logfile_dir = logfile_path
         .parent()
         .context("Failed to get event log directory")?;
     fs::create_dir_all(logfile_dir).context("Failed to create event log directory")?;
 
-    let mut logfile = fs::OpenOptions::new()
+    fs::OpenOptions::new()
         .append(true)
         .create(true)
         .open(logfile_path)
-        .context("Failed to open event log file")?;
+        .context("Failed to open event log file")
+}
+
+pub(crate) fn write_event_log(logfile: &mut fs::File, log: &TdxEventLog) -> anyhow::Result<()> {
+    let logline = serde_json::to_string(&log).context("Failed to serialize event log")?;
     logfile
         .write_all(logline.as_bytes())
         .context("Failed to write to event log file")?;
@@ -133,6 +140,22 @@ pub fn log_rtmr_event(log: &TdxEventLog) -> anyhow::Result<()> {
     Ok(())
 }
 
+pub fn log_rtmr_event(log: &TdxEventLog) -> anyhow::Result<()> {
+    // Append to event log
+    let logfile_path = Path::new(eventlog::RUNTIME_EVENT_LOG_FILE);
+    write_event_log(&mut open_event_log(logfile_path)?, log)
+}
+
+/// Measures a named runtime event: extends RTMR3 with the event digest and appends the event to the runtime
+/// event log so that verifiers can replay it.
+///
+/// The event log file is locked for the whole operation, so concurrent measurements (also from other processes)
+/// can't end up in the log in a different order than they were extended. Note that if appending fails after the
+/// extension the log can't be replayed anymore, which is why the error is loud about it.
+pub fn measure_event(event_name: &str, payload: &[u8]) -> anyhow::Result<TdxEventLog> {
+    HardwareBackend::default().measure_event(event_name, payload)
+}
+
 pub fn extend_rtmr(index: u32, event_type: u32, digest: [u8; 48]) -> Result<()> {
     let event = tdx_rtmr_event_t {
         version: 1,
@@ -0,0 +1,146 @@
+//! Typed view of the TDREPORT returned by [`crate::get_report`].
+//!
+//! The layout is defined by the TDX module ABI: a 256 bytes `REPORTMACSTRUCT`, the 239 bytes `TEE_TCB_INFO`
+//! describing the TDX module, 17 reserved bytes and the 512 bytes `TDINFO` holding the TD's own measurements.
+//! Unlike a quote the report is only MACed for the local platform, but it's enough for a guest to look at its
+//! own measurements without going through quote generation.
+//!
+
+use scale::Decode;
+
+use crate::TdxReport;
+
+/// `REPORTTYPE`, e.g `tee_type` is 0x81 for TDX.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Decode)]
+pub struct ReportType {
+    pub tee_type: u8,
+    pub subtype: u8,
+    pub version: u8,
+    _reserved: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Decode)]
+pub struct ReportMacStruct {
+    pub report_type: ReportType,
+    _reserved1: [u8; 12],
+    pub cpu_svn: [u8; 16],
+    /// SHA-384 of [`TeeTcbInfo`].
+    pub tee_tcb_info_hash: [u8; 48],
+    /// SHA-384 of [`TdInfo`].
+    pub tee_info_hash: [u8; 48],
+    pub report_data: [u8; 64],
+    _reserved2: [u8; 32],
+    pub mac: [u8; 32],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Decode)]
+pub struct TeeTcbInfo {
+    /// Bitmap of the valid fields.
+    pub valid: [u8; 8],
+    pub tee_tcb_svn: [u8; 16],
+    /// Measurement of the TDX module.
+    pub mr_seam: [u8; 48],
+    /// Zero for the Intel TDX module.
+    pub mr_signer_seam: [u8; 48],
+    pub attributes: [u8; 8],
+    /// Only set by TDX module 1.5 and later.
+    pub tee_tcb_svn2: [u8; 16],
+    _reserved: [u8; 95],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Decode)]
+pub struct TdInfo {
+    /// TD attributes, bit 0 is set for debug TDs.
+    pub attributes: [u8; 8],
+    pub xfam: [u8; 8],
+    pub mr_td: [u8; 48],
+    pub mr_config_id: [u8; 48],
+    pub mr_owner: [u8; 48],
+    pub mr_owner_config: [u8; 48],
+    pub rtmrs: [[u8; 48]; 4],
+    /// Only set by TDX module 1.5 and later.
+    pub servtd_hash: [u8; 48],
+    _reserved: [u8; 64],
+}
+
+impl TdInfo {
+    /// Debug TDs can be inspected by the host, they must never be trusted with secrets.
+    pub fn is_debug(&self) -> bool {
+        self.attributes[0] & 1 != 0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Decode)]
+pub struct ParsedTdxReport {
+    pub report_mac: ReportMacStruct,
+    pub tee_tcb_info: TeeTcbInfo,
+    _reserved: [u8; 17],
+    pub td_info: TdInfo,
+}
+
+impl TdxReport {
+    pub fn parse(&self) -> ParsedTdxReport {
+        // NB: the structures add up to the report size, decoding fixed size arrays can't fail.
+        ParsedTdxReport::decode(&mut &self.0[..]).expect("TDREPORT layout matches its size")
+    }
+
+    pub fn report_data(&self) -> [u8; 64] {
+        self.parse().report_mac.report_data
+    }
+
+    pub fn mr_td(&self) -> [u8; 48] {
+        self.parse().td_info.mr_td
+    }
+
+    pub fn rtmrs(&self) -> [[u8; 48]; 4] {
+        self.parse().td_info.rtmrs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sys::TDX_REPORT_SIZE, SoftwareBackend, TdxBackend};
+
+    #[test]
+    fn parses_fields_at_their_offsets() {
+        let mut bytes = [0; TDX_REPORT_SIZE as usize];
+        bytes[0] = 0x81;
+        bytes[16..32].fill(1);
+        bytes[128..192].fill(2);
+        bytes[224..256].fill(3);
+        bytes[256 + 24..256 + 72].fill(4);
+        bytes[512] = 1;
+        bytes[512 + 8..512 + 16].fill(5);
+        bytes[512 + 16..512 + 64].fill(6);
+        bytes[512 + 64..512 + 112].fill(7);
+        bytes[512 + 112..512 + 160].fill(8);
+        bytes[512 + 160..512 + 208].fill(9);
+        bytes[512 + 400..512 + 448].fill(10);
+
+        let report = TdxReport(bytes).parse();
+        assert_eq!(report.report_mac.report_type.tee_type, 0x81);
+        assert_eq!(report.report_mac.cpu_svn, [1; 16]);
+        assert_eq!(report.report_mac.report_data, [2; 64]);
+        assert_eq!(report.report_mac.mac, [3; 32]);
+        assert_eq!(report.tee_tcb_info.mr_seam, [4; 48]);
+        assert!(report.td_info.is_debug());
+        assert_eq!(report.td_info.xfam, [5; 8]);
+        assert_eq!(report.td_info.mr_td, [6; 48]);
+        assert_eq!(report.td_info.mr_config_id, [7; 48]);
+        assert_eq!(report.td_info.mr_owner, [8; 48]);
+        assert_eq!(report.td_info.mr_owner_config, [9; 48]);
+        assert_eq!(report.td_info.servtd_hash, [10; 48]);
+    }
+
+    #[test]
+    fn parses_software_report() {
+        let backend = SoftwareBackend::new();
+        backend.measure_event("app-id", b"new-york").unwrap();
+        let report = backend.get_report(&[7; 64]).unwrap();
+
+        assert_eq!(report.report_data(), [7; 64]);
+        assert_eq!(report.rtmrs(), backend.rtmrs());
+        assert!(!report.parse().td_info.is_debug());
+    }
+}
@@ -1,4 +1,5 @@
 use base64::{prelude::BASE64_STANDARD, Engine};
+use dstack_core::{TcbStatus, TdMeasurements, VerifiedQuote};
 use serde::Deserialize;
 
 #[derive(Debug, Deserialize)]
@@ -87,12 +88,176 @@ pub struct PckCertificateChainData {
     pub pck_cert_chain: String,
 }
 
+/// Decodes a base64 field of the quote body.
+fn decode_field<const N: usize>(field: &str, name: &str) -> anyhow::Result<[u8; N]> {
+    BASE64_STANDARD
+        .decode(field)?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("{} is not {} bytes", name, N))
+}
+
 impl QuoteVerificationResult {
-    pub fn get_appdata(&self) -> [u8; 32] {
-        BASE64_STANDARD
-            .decode(&self.td_quote_body.report_data)
-            .unwrap()[0..32]
+    /// Returns the full 64 bytes of report data.
+    pub fn get_report_data(&self) -> anyhow::Result<[u8; 64]> {
+        decode_field(&self.td_quote_body.report_data, "report data")
+    }
+
+    /// Returns the MRTD, i.e the measurement of the initial TD contents (firmware).
+    pub fn get_mrtd(&self) -> anyhow::Result<[u8; 48]> {
+        decode_field(&self.td_quote_body.mr_td, "mrtd")
+    }
+
+    /// Returns RTMR0-3, e.g to replay an event log against.
+    pub fn get_rtmrs(&self) -> anyhow::Result<[[u8; 48]; 4]> {
+        let rtmrs = self
+            .td_quote_body
+            .rtmrs
+            .iter()
+            .map(|rtmr| decode_field(rtmr, "rtmr"))
+            .collect::<anyhow::Result<Vec<[u8; 48]>>>()?;
+
+        rtmrs
             .try_into()
-            .unwrap()
+            .map_err(|_| anyhow::anyhow!("expected 4 rtmrs"))
+    }
+
+    /// Returns the first 32 bytes of report data, prefer [`Self::get_report_data`].
+    pub fn get_appdata(&self) -> anyhow::Result<[u8; 32]> {
+        let report_data = self.get_report_data()?;
+        Ok(report_data[..32].try_into()?)
+    }
+}
+
+impl VerifiedQuote for QuoteVerificationResult {
+    fn report_data(&self) -> anyhow::Result<[u8; 64]> {
+        self.get_report_data()
+    }
+
+    fn measurements(&self) -> anyhow::Result<TdMeasurements> {
+        Ok(TdMeasurements {
+            mr_td: self.get_mrtd()?,
+            rtmrs: self.get_rtmrs()?,
+            mr_config_id: decode_field(&self.td_quote_body.mr_config_id, "mr_config_id")?,
+            mr_owner: decode_field(&self.td_quote_body.mr_owner, "mr_owner")?,
+            mr_owner_config: decode_field(&self.td_quote_body.mr_owner_config, "mr_owner_config")?,
+        })
+    }
+
+    // NB: the verification service only returns the parsed quote, not the TCB status. Verifiers using it can't
+    // check the status, e.g new-york's ALLOWED_TCB_STATUSES doesn't apply.
+    fn tcb_status(&self) -> Option<TcbStatus> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn b64(bytes: &[u8]) -> String {
+        BASE64_STANDARD.encode(bytes)
+    }
+
+    /// A verification service response, only the quote body's values matter here.
+    fn sample(report_data: String, rtmrs: Vec<String>) -> QuoteVerificationResult {
+        serde_json::from_value(json!({
+            "header": {
+                "version": 4,
+                "attestation_key_type": 2,
+                "tee_type": 129,
+                "qe_svn": "AAA=",
+                "pce_svn": "AAA=",
+                "qe_vendor_id": "k5pyM/ecTKmUCg2zlX8GBw==",
+                "user_data": "AAAAAAAAAAAAAAAAAAAAAAAAAAA=",
+            },
+            "td_quote_body": {
+                "tee_tcb_svn": b64(&[0; 16]),
+                "mr_seam": b64(&[0; 48]),
+                "mr_signer_seam": b64(&[0; 48]),
+                "seam_attributes": b64(&[0; 8]),
+                "td_attributes": b64(&[0; 8]),
+                "xfam": b64(&[0; 8]),
+                "mr_td": b64(&[1; 48]),
+                "mr_config_id": b64(&[2; 48]),
+                "mr_owner": b64(&[3; 48]),
+                "mr_owner_config": b64(&[4; 48]),
+                "rtmrs": rtmrs,
+                "report_data": report_data,
+            },
+            "signed_data_size": 0,
+            "signed_data": {
+                "signature": "",
+                "ecdsa_attestation_key": "",
+                "certification_data": {
+                    "certificate_data_type": 6,
+                    "size": 0,
+                    "qe_report_certification_data": {
+                        "qe_report": {
+                            "cpu_svn": "",
+                            "reserved1": "",
+                            "attributes": "",
+                            "mr_enclave": "",
+                            "reserved2": "",
+                            "mr_signer": "",
+                            "reserved3": "",
+                            "isv_prod_id": 1,
+                            "isv_svn": 0,
+                            "reserved4": "",
+                            "report_data": "",
+                        },
+                        "qe_report_signature": "",
+                        "qe_auth_data": { "parsed_data_size": 0, "data": "" },
+                        "pck_certificate_chain_data": {
+                            "certificate_data_type": 5,
+                            "size": 0,
+                            "pck_cert_chain": "",
+                        },
+                    },
+                },
+            },
+            "extra_bytes": "",
+        }))
+        .unwrap()
+    }
+
+    fn rtmrs(count: u8) -> Vec<String> {
+        (0..count).map(|i| b64(&[0x10 + i; 48])).collect()
+    }
+
+    #[test]
+    fn decodes_quote_body() {
+        let report_data: Vec<u8> = (0..64).collect();
+        let result = sample(b64(&report_data), rtmrs(4));
+
+        assert_eq!(result.report_data().unwrap()[..], report_data[..]);
+        assert_eq!(result.get_appdata().unwrap()[..], report_data[..32]);
+
+        let measurements = result.measurements().unwrap();
+        assert_eq!(measurements.mr_td, [1; 48]);
+        assert_eq!(
+            measurements.rtmrs,
+            [[0x10; 48], [0x11; 48], [0x12; 48], [0x13; 48]]
+        );
+        assert_eq!(measurements.mr_config_id, [2; 48]);
+        assert_eq!(measurements.mr_owner, [3; 48]);
+        assert_eq!(measurements.mr_owner_config, [4; 48]);
+        assert_eq!(result.tcb_status(), None);
+    }
+
+    #[test]
+    fn errors_on_malformed_fields() {
+        // Not base64.
+        assert!(sample("%%".into(), rtmrs(4)).report_data().is_err());
+        // Only the first half of the report data, as older services returned it.
+        assert!(sample(b64(&[0; 32]), rtmrs(4)).report_data().is_err());
+
+        let report_data = b64(&[0; 64]);
+        assert!(sample(report_data.clone(), rtmrs(3))
+            .measurements()
+            .is_err());
+        let mut short = rtmrs(4);
+        short[3] = b64(&[0; 32]);
+        assert!(sample(report_data, short).get_rtmrs().is_err());
     }
 }
@@ -1,8 +1,12 @@
+use anyhow::{anyhow, Context};
 use async_trait::async_trait;
 use dcap_quotes::QuoteVerificationResult;
-use dstack_core::InnerAttestationHelper;
+use dstack_core::{InnerAttestationHelper, Refused, ReportData};
 use reqwest::Client;
-use sha2::{Digest, Sha256};
+
+mod mock;
+
+pub use mock::{MockAttestation, MockQuote};
 
 pub struct Attestation {}
 
@@ -16,21 +20,19 @@ impl Attestation {
 /// in a wrapped object.
 #[async_trait]
 impl InnerAttestationHelper for Attestation {
-    type Appdata = Vec<u8>;
+    type Appdata = ReportData;
     type Quote = String;
     type VerificationResult = QuoteVerificationResult;
 
     async fn get_quote(&self, appdata: Self::Appdata) -> anyhow::Result<Self::Quote> {
-        let preimage = format!("register{}", hex::encode(appdata));
-        let mut hasher = Sha256::new();
-        hasher.update(preimage);
-        let hashed = hex::encode(hasher.finalize());
+        // NB: the attestation service takes the hex-encoded report data.
+        let report_data = hex::encode(appdata.to_bytes());
 
         let client = Client::new();
         let response = client
             .get(format!(
                 "http://ns31695324.ip-141-94-163.eu:10080/attest/{}",
-                hashed
+                report_data
             ))
             .send()
             .await?
@@ -44,7 +46,7 @@ impl InnerAttestationHelper for Attestation {
     }
 
     async fn verify_quote(&self, quote: Self::Quote) -> anyhow::Result<Self::VerificationResult> {
-        let quote = hex::decode(quote)?;
+        let quote = hex::decode(quote).context(Refused)?;
         let client = Client::new();
 
         let verification_resp = client
@@ -53,6 +55,15 @@ impl InnerAttestationHelper for Attestation {
             .body(quote)
             .send()
             .await?;
+        // NB: a client error means the service didn't take the quote, anything else (e.g it being down) is worth
+        // retrying.
+        if verification_resp.status().is_client_error() {
+            return Err(anyhow!(
+                "attestation service rejected the quote: {}",
+                verification_resp.status()
+            ))
+            .context(Refused);
+        }
 
         Ok(verification_resp.json().await?)
     }
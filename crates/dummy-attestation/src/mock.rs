@@ -0,0 +1,72 @@
+//! Mock attestation for running clusters without TDX nor the attestation service, e.g several nodes on a laptop.
+//!
+//! NB: a mock quote is nothing more than the report data, so anyone can forge one. Nodes using [`MockAttestation`]
+//! accept any mock quote (and reject real ones), never use it outside of local testing.
+//!
+
+use anyhow::Context;
+use async_trait::async_trait;
+use dstack_core::{
+    InnerAttestationHelper, Refused, ReportData, TcbStatus, TdMeasurements, VerifiedQuote,
+    REPORT_DATA_SIZE,
+};
+
+const MOCK_QUOTE_PREFIX: &[u8] = b"mock-quote";
+
+#[derive(Default)]
+pub struct MockAttestation {}
+
+impl MockAttestation {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+/// A "verified" mock quote. Measurements are all zeros, which is also what an empty event log replays to.
+pub struct MockQuote {
+    report_data: [u8; REPORT_DATA_SIZE],
+}
+
+impl VerifiedQuote for MockQuote {
+    fn report_data(&self) -> anyhow::Result<[u8; 64]> {
+        Ok(self.report_data)
+    }
+
+    fn measurements(&self) -> anyhow::Result<TdMeasurements> {
+        Ok(TdMeasurements {
+            mr_td: [0; 48],
+            rtmrs: [[0; 48]; 4],
+            mr_config_id: [0; 48],
+            mr_owner: [0; 48],
+            mr_owner_config: [0; 48],
+        })
+    }
+
+    fn tcb_status(&self) -> Option<TcbStatus> {
+        None
+    }
+}
+
+#[async_trait]
+impl InnerAttestationHelper for MockAttestation {
+    type Appdata = ReportData;
+    type Quote = String;
+    type VerificationResult = MockQuote;
+
+    async fn get_quote(&self, appdata: Self::Appdata) -> anyhow::Result<Self::Quote> {
+        Ok(hex::encode(
+            [MOCK_QUOTE_PREFIX, &appdata.to_bytes()].concat(),
+        ))
+    }
+
+    async fn verify_quote(&self, quote: Self::Quote) -> anyhow::Result<Self::VerificationResult> {
+        let quote = hex::decode(quote).context(Refused)?;
+        let report_data = quote
+            .strip_prefix(MOCK_QUOTE_PREFIX)
+            .and_then(|report_data| report_data.try_into().ok())
+            .ok_or(anyhow::anyhow!("not a mock quote"))
+            .context(Refused)?;
+
+        Ok(MockQuote { report_data })
+    }
+}
@@ -0,0 +1,249 @@
+//! Collateral providers for quote verification.
+//!
+//! Verifying a DCAP quote requires collateral (TCB info, QE identity, CRLs and their issuer chains). Intel's PCS
+//! is the default source but relying on it means that every verification depends on Intel's API being up and
+//! reachable. Operators can instead point to a self-hosted PCCS or to a local directory holding collateral that
+//! was fetched ahead of time.
+//!
+//! Note: `dcap-qvl` only uses the TCB info and the QE identity (along with their issuer chains and signatures), so
+//! that's all providers fetch. CRLs aren't part of the collateral.
+//!
+
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use dcap_qvl::QuoteCollateralV3;
+use serde::{Deserialize, Serialize};
+
+#[cfg(any(test, feature = "pccs-server"))]
+pub mod pccs_server;
+
+/// Timeout used when fetching collateral over the network.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Name of the collateral file read from a [`LocalDirectory`].
+pub const COLLATERAL_FILE: &str = "collateral.json";
+
+#[async_trait]
+pub trait CollateralProvider: Send + Sync {
+    /// Returns the collateral needed to verify [`quote`] (raw bytes, not hex).
+    async fn get_collateral(&self, quote: &[u8]) -> anyhow::Result<QuoteCollateralV3>;
+}
+
+/// Fetches collateral straight from Intel's PCS.
+pub struct IntelPcs {
+    pub timeout: Duration,
+}
+
+impl Default for IntelPcs {
+    fn default() -> Self {
+        Self {
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+#[async_trait]
+impl CollateralProvider for IntelPcs {
+    async fn get_collateral(&self, quote: &[u8]) -> anyhow::Result<QuoteCollateralV3> {
+        dcap_qvl::collateral::get_collateral_from_pcs(quote, self.timeout).await
+    }
+}
+
+/// Fetches collateral from a PCCS (e.g a self-hosted caching service or `pccs_server`).
+pub struct Pccs {
+    /// Base url of the PCCS, e.g `https://localhost:8081/sgx/certification/v4`.
+    pub url: String,
+    pub timeout: Duration,
+}
+
+impl Pccs {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+#[async_trait]
+impl CollateralProvider for Pccs {
+    async fn get_collateral(&self, quote: &[u8]) -> anyhow::Result<QuoteCollateralV3> {
+        dcap_qvl::collateral::get_collateral(&self.url, quote, self.timeout).await
+    }
+}
+
+/// Reads collateral from a local directory.
+///
+/// Note: collateral is platform (FMSPC) specific, so this expects the directory to hold the collateral for the
+/// platform the cluster is running on. It also has to be refreshed by the operator before it expires.
+pub struct LocalDirectory {
+    pub path: PathBuf,
+}
+
+impl LocalDirectory {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn read(&self) -> anyhow::Result<CollateralFile> {
+        let path = self.path.join(COLLATERAL_FILE);
+        let raw = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read collateral from {}", path.display()))?;
+        serde_json::from_str(&raw).context("failed to decode collateral file")
+    }
+
+    pub fn write(&self, collateral: &CollateralFile) -> anyhow::Result<()> {
+        std::fs::create_dir_all(&self.path)?;
+        std::fs::write(
+            self.path.join(COLLATERAL_FILE),
+            serde_json::to_string_pretty(collateral)?,
+        )?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CollateralProvider for LocalDirectory {
+    async fn get_collateral(&self, _quote: &[u8]) -> anyhow::Result<QuoteCollateralV3> {
+        self.read()?.try_into()
+    }
+}
+
+/// On-disk representation of [`QuoteCollateralV3`], binary fields are hex-encoded.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CollateralFile {
+    pub tcb_info_issuer_chain: String,
+    pub tcb_info: String,
+    pub tcb_info_signature: String,
+    pub qe_identity_issuer_chain: String,
+    pub qe_identity: String,
+    pub qe_identity_signature: String,
+}
+
+impl TryFrom<CollateralFile> for QuoteCollateralV3 {
+    type Error = anyhow::Error;
+
+    fn try_from(file: CollateralFile) -> anyhow::Result<Self> {
+        let decode = |field: &str, value: String| {
+            hex::decode(value).map_err(|e| anyhow!("invalid {} in collateral file: {}", field, e))
+        };
+
+        Ok(Self {
+            tcb_info_issuer_chain: file.tcb_info_issuer_chain,
+            tcb_info: file.tcb_info,
+            tcb_info_signature: decode("tcb_info_signature", file.tcb_info_signature)?,
+            qe_identity_issuer_chain: file.qe_identity_issuer_chain,
+            qe_identity: file.qe_identity,
+            qe_identity_signature: decode("qe_identity_signature", file.qe_identity_signature)?,
+        })
+    }
+}
+
+impl From<QuoteCollateralV3> for CollateralFile {
+    fn from(collateral: QuoteCollateralV3) -> Self {
+        Self {
+            tcb_info_issuer_chain: collateral.tcb_info_issuer_chain,
+            tcb_info: collateral.tcb_info,
+            tcb_info_signature: hex::encode(collateral.tcb_info_signature),
+            qe_identity_issuer_chain: collateral.qe_identity_issuer_chain,
+            qe_identity: collateral.qe_identity,
+            qe_identity_signature: hex::encode(collateral.qe_identity_signature),
+        }
+    }
+}
+
+/// Selects the collateral provider.
+///
+/// From the environment:
+/// - `COLLATERAL_PROVIDER=pcs` (default) uses Intel's PCS.
+/// - `COLLATERAL_PROVIDER=pccs` uses the PCCS at `PCCS_URL`.
+/// - `COLLATERAL_PROVIDER=local` reads from `COLLATERAL_DIR`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(tag = "provider", rename_all = "snake_case")]
+pub enum CollateralConfig {
+    #[default]
+    Pcs,
+    Pccs {
+        url: String,
+    },
+    Local {
+        path: PathBuf,
+    },
+}
+
+impl CollateralConfig {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let provider = std::env::var("COLLATERAL_PROVIDER").unwrap_or("pcs".into());
+        let config = match provider.as_str() {
+            "pcs" => Self::Pcs,
+            "pccs" => Self::Pccs {
+                url: std::env::var("PCCS_URL").context("PCCS_URL is required for pccs")?,
+            },
+            "local" => Self::Local {
+                path: std::env::var("COLLATERAL_DIR")
+                    .context("COLLATERAL_DIR is required for local collateral")?
+                    .into(),
+            },
+            other => return Err(anyhow::anyhow!("unknown collateral provider {}", other)),
+        };
+
+        Ok(config)
+    }
+
+    pub fn into_provider(self) -> Box<dyn CollateralProvider> {
+        match self {
+            Self::Pcs => Box::new(IntelPcs::default()),
+            Self::Pccs { url } => Box::new(Pccs::new(url)),
+            Self::Local { path } => Box::new(LocalDirectory::new(path)),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn sample_collateral() -> CollateralFile {
+        CollateralFile {
+            tcb_info_issuer_chain: "-----BEGIN CERTIFICATE-----\ntcb\n-----END CERTIFICATE-----\n"
+                .into(),
+            tcb_info: r#"{"id":"TDX","version":3,"fmspc":"00806f050000"}"#.into(),
+            tcb_info_signature: "aa".repeat(64),
+            qe_identity_issuer_chain:
+                "-----BEGIN CERTIFICATE-----\nqe\n-----END CERTIFICATE-----\n".into(),
+            qe_identity: r#"{"id":"TD_QE","version":2}"#.into(),
+            qe_identity_signature: "bb".repeat(64),
+        }
+    }
+
+    #[tokio::test]
+    async fn local_directory_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("collateral-test-{}", std::process::id()));
+        let local = LocalDirectory::new(&dir);
+        local.write(&sample_collateral()).unwrap();
+
+        let collateral = local.get_collateral(&[]).await.unwrap();
+        assert_eq!(CollateralFile::from(collateral), sample_collateral());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_corrupt_collateral() {
+        let dir = std::env::temp_dir().join(format!("collateral-corrupt-{}", std::process::id()));
+        let local = LocalDirectory::new(&dir);
+        local
+            .write(&CollateralFile {
+                tcb_info_signature: "not hex".into(),
+                ..sample_collateral()
+            })
+            .unwrap();
+
+        let error = local.get_collateral(&[]).await.unwrap_err();
+        assert!(error.to_string().contains("tcb_info_signature"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
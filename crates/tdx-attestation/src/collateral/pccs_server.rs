@@ -0,0 +1,149 @@
+//! Minimal local stand-in for a PCCS.
+//!
+//! Serves a single [`CollateralFile`] through the subset of the PCS/PCCS v4 API that is needed to fetch quote
+//! collateral (`/tcb` and `/qe/identity`), so that [`super::Pccs`] can be exercised without reaching Intel (tests,
+//! air-gapped clusters). Both the `/sgx/certification/v4` and `/tdx/certification/v4` prefixes are accepted.
+//! Responses are shaped like PCCS ones: JSON bodies with hex signatures and url-encoded issuer chain headers.
+//!
+//! Note: there is no FMSPC selection, whatever is in the collateral file is returned. Only built with the
+//! `pccs-server` feature.
+
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+
+use warp::{
+    http::{Response, StatusCode},
+    hyper::Body,
+    path::FullPath,
+    Filter,
+};
+
+use super::CollateralFile;
+
+pub const TCB_INFO_ISSUER_CHAIN_HEADER: &str = "TCB-Info-Issuer-Chain";
+pub const SGX_TCB_INFO_ISSUER_CHAIN_HEADER: &str = "SGX-TCB-Info-Issuer-Chain";
+pub const QE_IDENTITY_ISSUER_CHAIN_HEADER: &str = "SGX-Enclave-Identity-Issuer-Chain";
+
+pub fn routes(
+    collateral: CollateralFile,
+) -> impl Filter<Extract = (Response<Body>,), Error = warp::Rejection> + Clone {
+    let collateral = Arc::new(collateral);
+    warp::get()
+        .and(warp::path::full())
+        .and(warp::any().map(move || collateral.clone()))
+        .and_then(|path: FullPath, collateral: Arc<CollateralFile>| async move {
+            Ok::<_, Infallible>(respond(path.as_str(), &collateral))
+        })
+}
+
+/// Serves [`collateral`] on [`addr`] until the returned future is dropped.
+pub async fn serve(collateral: CollateralFile, addr: impl Into<SocketAddr>) {
+    warp::serve(routes(collateral)).run(addr).await
+}
+
+/// Binds the server on an ephemeral localhost port, returning the base url to pass to [`super::Pccs`] and the
+/// future driving the server.
+pub fn bind_ephemeral(
+    collateral: CollateralFile,
+) -> (String, impl std::future::Future<Output = ()> + 'static) {
+    let (addr, server) = warp::serve(routes(collateral)).bind_ephemeral(([127, 0, 0, 1], 0));
+    (format!("http://{}/sgx/certification/v4", addr), server)
+}
+
+fn respond(path: &str, collateral: &CollateralFile) -> Response<Body> {
+    let endpoint = path
+        .trim_start_matches("/sgx/certification/v4")
+        .trim_start_matches("/tdx/certification/v4");
+
+    let response = match endpoint {
+        "/tcb" => {
+            let chain = urlencoding::encode(&collateral.tcb_info_issuer_chain).into_owned();
+            Response::builder()
+                .header(TCB_INFO_ISSUER_CHAIN_HEADER, &chain)
+                .header(SGX_TCB_INFO_ISSUER_CHAIN_HEADER, &chain)
+                .header("Content-Type", "application/json")
+                .body(Body::from(format!(
+                    r#"{{"tcbInfo":{},"signature":"{}"}}"#,
+                    collateral.tcb_info, collateral.tcb_info_signature
+                )))
+        }
+        "/qe/identity" => Response::builder()
+            .header(
+                QE_IDENTITY_ISSUER_CHAIN_HEADER,
+                urlencoding::encode(&collateral.qe_identity_issuer_chain).into_owned(),
+            )
+            .header("Content-Type", "application/json")
+            .body(Body::from(format!(
+                r#"{{"enclaveIdentity":{},"signature":"{}"}}"#,
+                collateral.qe_identity, collateral.qe_identity_signature
+            ))),
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty()),
+    };
+
+    response.unwrap_or_else(|_| {
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        response
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collateral::tests::sample_collateral;
+
+    #[tokio::test]
+    async fn serves_collateral() {
+        let collateral = sample_collateral();
+        let (url, server) = bind_ephemeral(collateral.clone());
+        tokio::spawn(server);
+
+        let client = reqwest::Client::new();
+        let tcb = client
+            .get(format!("{}/tcb?fmspc=00806f050000", url))
+            .send()
+            .await
+            .unwrap();
+        let chain = urlencoding::decode(
+            tcb.headers()[TCB_INFO_ISSUER_CHAIN_HEADER]
+                .to_str()
+                .unwrap(),
+        )
+        .unwrap()
+        .into_owned();
+        assert_eq!(chain, collateral.tcb_info_issuer_chain);
+
+        let body: serde_json::Value = tcb.json().await.unwrap();
+        assert_eq!(
+            body["tcbInfo"],
+            serde_json::from_str::<serde_json::Value>(&collateral.tcb_info).unwrap()
+        );
+        assert_eq!(body["signature"], collateral.tcb_info_signature);
+
+        let qe_identity: serde_json::Value = client
+            .get(format!("{}/qe/identity", url))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(qe_identity["signature"], collateral.qe_identity_signature);
+
+        // CRLs aren't part of the collateral.
+        let pck_crl = client
+            .get(format!("{}/pckcrl?ca=platform", url))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(pck_crl.status(), reqwest::StatusCode::NOT_FOUND);
+
+        let missing = client
+            .get(format!("{}/unknown", url))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(missing.status(), reqwest::StatusCode::NOT_FOUND);
+    }
+}
@@ -1,101 +1,217 @@
-//! Note, defaults to tsm only attestation through
-
-use std::time::Duration;
+//! TDX attestation helpers. Quotes are obtained through configfs-tsm (`tsm` feature, default) or the `tdx_attest`
+//! driver (`c_driver` feature), the provider being selected at runtime (see [`quote::detect`]).
 
+use anyhow::Context;
 use async_trait::async_trait;
-use dstack_core::InnerAttestationHelper;
-use sha2::{Digest, Sha256};
-
-pub struct Attestation {}
+use collateral::{CollateralConfig, CollateralProvider};
+use dcap_qvl::{
+    quote::{Report, TDReport10},
+    verify::VerifiedReport,
+};
+use dstack_core::{
+    InnerAttestationHelper, Refused, ReportData, TcbStatus, TdMeasurements, VerifiedQuote,
+};
+use quote::{QuoteProvider, UnavailableQuoteProvider};
+
+pub mod collateral;
+pub mod quote;
+
+pub struct Attestation {
+    quotes: Box<dyn QuoteProvider>,
+    collateral: Box<dyn CollateralProvider>,
+}
 
 impl Attestation {
+    /// Same as [`Attestation::try_new`], kept for existing callers.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the collateral provider configured in the environment is invalid.
     pub fn new() -> Self {
-        Self {}
+        Self::try_new().expect("invalid collateral provider configuration")
+    }
+
+    /// Detects the quote provider and uses the collateral provider configured in the environment, see
+    /// [`quote::detect`] and [`CollateralConfig::from_env`].
+    ///
+    /// Note: if no quote provider is found quote generation will error (with the reason detection failed), but
+    /// verification still works. A misconfigured collateral provider is an error rather than silently verifying
+    /// against another source.
+    pub fn try_new() -> anyhow::Result<Self> {
+        let quotes = quote::detect().unwrap_or_else(|e| {
+            Box::new(UnavailableQuoteProvider {
+                reason: e.to_string(),
+            })
+        });
+        let config = CollateralConfig::from_env()?;
+
+        Ok(Self::with_providers(quotes, config.into_provider()))
+    }
+
+    pub fn with_providers(
+        quotes: Box<dyn QuoteProvider>,
+        collateral: Box<dyn CollateralProvider>,
+    ) -> Self {
+        Self { quotes, collateral }
+    }
+
+    /// e.g for callers to log which provider was detected, see [`QuoteProvider::name`].
+    pub fn quote_provider(&self) -> &dyn QuoteProvider {
+        self.quotes.as_ref()
     }
 }
 
-#[cfg(feature="tsm_only")]
-mod tsm_att {
-    use tsm_client::{make_client, report};
+impl Default for Attestation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-    use super::*;
+/// Report verified with `dcap-qvl` against the collateral.
+#[derive(Clone, Debug)]
+pub struct VerifiedTdxQuote(pub VerifiedReport);
 
-    #[async_trait]
-    impl InnerAttestationHelper for Attestation {
-        type Appdata = Vec<u8>;
-        type Quote = String;
-        type VerificationResult = dcap_qvl::verify::VerifiedReport;
-
-        async fn get_quote(&self, appdata: Self::Appdata) -> anyhow::Result<Self::Quote> {
-            let preimage = format!("register{}", hex::encode(appdata));
-            let mut hasher = Sha256::new();
-            hasher.update(preimage);
-            let hashed: Vec<u8> = hasher.finalize().to_vec();
-            let mut padded_report_data = [0_u8; 64];
-            padded_report_data[..hashed.len()].copy_from_slice(&hashed);
-
-            let quote = {
-                let client = make_client()?;
-                let request = report::Request {
-                    in_blob: padded_report_data.to_vec(),
-                    privilege: None,
-                    get_aux_blob: false
-                };
-                let mut report = report::create(client, request)?;
-                report.get()?.out_blob
-            };
-
-            let hex_quote = hex::encode(quote);            
-            Ok(hex_quote)
+impl VerifiedTdxQuote {
+    fn td_report(&self) -> anyhow::Result<&TDReport10> {
+        match &self.0.report {
+            Report::TD10(report) => Ok(report),
+            Report::TD15(report) => Ok(&report.base),
+            Report::SgxEnclave(_) => Err(anyhow::anyhow!("not a TD quote")),
         }
+    }
+}
 
-        async fn verify_quote(&self, quote: Self::Quote) -> anyhow::Result<Self::VerificationResult> {
-            let quote = hex::decode(quote)?;
-            
-            // we're just relying on intel's API. We can change whenever we want.
-            let collateral = dcap_qvl::collateral::get_collateral_from_pcs(&quote, Duration::from_secs(15)).await?;
-            let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
-            let tcb = dcap_qvl::verify::verify(&quote, &collateral, now).map_err(|e| anyhow::anyhow!(e as u32))?;
+impl VerifiedQuote for VerifiedTdxQuote {
+    fn report_data(&self) -> anyhow::Result<[u8; 64]> {
+        Ok(self.td_report()?.report_data)
+    }
 
-            Ok(tcb)
-        }
+    fn measurements(&self) -> anyhow::Result<TdMeasurements> {
+        let report = self.td_report()?;
+        Ok(TdMeasurements {
+            mr_td: report.mr_td,
+            rtmrs: [report.rt_mr0, report.rt_mr1, report.rt_mr2, report.rt_mr3],
+            mr_config_id: report.mr_config_id,
+            mr_owner: report.mr_owner,
+            mr_owner_config: report.mr_owner_config,
+        })
+    }
+
+    fn tcb_status(&self) -> Option<TcbStatus> {
+        Some(TcbStatus::parse(&self.0.status))
     }
 }
 
-#[cfg(feature="full_c_driver")]
-mod full_driver {
-    use super::*;
+/// Whether [`error`] can only come from the quote itself, i.e the node should be refused.
+///
+/// Note: the rest is retried rather than refused. Expired TCB info or an FMSPC mismatch mean our collateral is stale
+/// or for another platform, and signature, certificate chain and decoding errors are raised the same way whether
+/// they come from the quote or from the collateral. NB: every variant is listed so that new ones have to be
+/// classified here.
+fn is_quote_error(error: &dcap_qvl::Error) -> bool {
+    use dcap_qvl::Error;
+
+    match error {
+        Error::UnsupportedDCAPQuoteVersion
+        | Error::UnsupportedDCAPAttestationKeyType
+        | Error::UnsupportedQuoteAuthData
+        | Error::UnsupportedDCAPPckCertFormat
+        | Error::LeafCertificateParsingError
+        | Error::CertificateChainIsTooShort
+        | Error::IntelExtensionCertificateDecodingError
+        | Error::IntelExtensionAmbiguity
+        | Error::CpuSvnLengthMismatch
+        | Error::CpuSvnDecodingError
+        | Error::PceSvnLengthMismatch
+        | Error::PceSvnDecodingError
+        | Error::FmspcLengthMismatch
+        | Error::FmspcDecodingError
+        | Error::QEReportHashMismatch
+        | Error::IsvEnclaveReportSignatureIsInvalid
+        | Error::OidIsMissing => true,
+        Error::InvalidCertificate
+        | Error::InvalidSignature
+        | Error::CodecError
+        | Error::TCBInfoExpired
+        | Error::KeyLengthIsInvalid
+        | Error::PublicKeyIsInvalid
+        | Error::RsaSignatureIsInvalid
+        | Error::DerEncodingError
+        | Error::CertificateChainIsInvalid
+        | Error::FmspcMismatch
+        | Error::DerDecodingError => false,
+    }
+}
 
-    #[async_trait]
-    impl InnerAttestationHelper for Attestation {
-        type Appdata = Vec<u8>;
-        type Quote = String;
-        type VerificationResult = dcap_qvl::verify::VerifiedReport;
-
-        async fn get_quote(&self, appdata: Self::Appdata) -> anyhow::Result<Self::Quote> {
-            let preimage = format!("register{}", hex::encode(appdata));
-            let mut hasher = Sha256::new();
-            hasher.update(preimage);
-            let hashed: Vec<u8> = hasher.finalize().to_vec();
-            let mut padded_report_data = [0_u8; 64];
-            padded_report_data[..hashed.len()].copy_from_slice(&hashed);
-
-            let (_, quote) = tdx_attest::get_quote(&padded_report_data, None)?;
-            let hex_quote = hex::encode(quote);
-            println!("Hex Output: {}", hex_quote);
-            
-            Ok(hex_quote)
-        }
+#[async_trait]
+impl InnerAttestationHelper for Attestation {
+    type Appdata = ReportData;
+    type Quote = String;
+    type VerificationResult = VerifiedTdxQuote;
 
-        async fn verify_quote(&self, quote: Self::Quote) -> anyhow::Result<Self::VerificationResult> {
-            let quote = hex::decode(quote)?;
-            
-            // we're just relying on intel's API. We can change whenever we want.
-            let collateral = dcap_qvl::collateral::get_collateral_from_pcs(&quote, Duration::from_secs(15)).await?;
-            let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
-            let tcb = dcap_qvl::verify::verify(&quote, &collateral, now).map_err(|e| anyhow::anyhow!(e as u32))?;
+    async fn get_quote(&self, appdata: Self::Appdata) -> anyhow::Result<Self::Quote> {
+        let quote = self.quotes.get_quote(&appdata.to_bytes())?;
+        let hex_quote = hex::encode(quote);
+        Ok(hex_quote)
+    }
 
-            Ok(tcb)
-        }
+    async fn verify_quote(&self, quote: Self::Quote) -> anyhow::Result<Self::VerificationResult> {
+        let quote = hex::decode(quote).context(Refused)?;
+
+        // NB: failing to get collateral is on us (or the PCS), not the quote, so it's worth retrying.
+        let collateral = self.collateral.get_collateral(&quote).await?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let tcb = dcap_qvl::verify::verify(&quote, &collateral, now).map_err(|e| {
+            let error = anyhow::anyhow!("quote verification failed: {:?}", e);
+            if is_quote_error(&e) {
+                error.context(Refused)
+            } else {
+                error
+            }
+        })?;
+
+        Ok(VerifiedTdxQuote(tcb))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use collateral::LocalDirectory;
+
+    #[tokio::test]
+    async fn refuses_bad_quotes_but_not_missing_collateral() {
+        let dir = std::env::temp_dir().join(format!("attestation-test-{}", std::process::id()));
+        let attestation = Attestation::with_providers(
+            Box::new(UnavailableQuoteProvider {
+                reason: "test".into(),
+            }),
+            Box::new(LocalDirectory::new(&dir)),
+        );
+
+        // No collateral to verify against yet, which is worth retrying.
+        let error = attestation.verify_quote("00".into()).await.unwrap_err();
+        assert!(!Refused::is(&error));
+
+        let error = attestation
+            .verify_quote("not hex".into())
+            .await
+            .unwrap_err();
+        assert!(Refused::is(&error));
+    }
+
+    #[test]
+    fn only_refuses_quote_errors() {
+        use dcap_qvl::Error;
+
+        assert!(is_quote_error(&Error::QEReportHashMismatch));
+        // Stale collateral or collateral for another platform.
+        assert!(!is_quote_error(&Error::TCBInfoExpired));
+        assert!(!is_quote_error(&Error::FmspcMismatch));
+        // Collateral chains are checked the same way.
+        assert!(!is_quote_error(&Error::CertificateChainIsInvalid));
     }
 }
@@ -0,0 +1,188 @@
+//! Quote providers.
+//!
+//! A guest can get its quote either through configfs-tsm (upstream kernel interface) or through the `tdx_attest`
+//! C driver (`/dev/tdx_guest` + vsock/QGS). Both are compiled in when their feature is enabled and the one to use
+//! is picked at runtime, see [`detect`].
+//!
+
+use std::path::Path;
+
+/// configfs-tsm report interface.
+pub const TSM_REPORT_PATH: &str = "/sys/kernel/config/tsm/report";
+/// TDX guest device used by the `tdx_attest` driver.
+pub const TDX_GUEST_DEVICE: &str = "/dev/tdx_guest";
+
+pub trait QuoteProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Returns the raw quote over [`report_data`].
+    fn get_quote(&self, report_data: &[u8; 64]) -> anyhow::Result<Vec<u8>>;
+}
+
+#[cfg(feature = "tsm")]
+pub struct TsmQuoteProvider;
+
+#[cfg(feature = "tsm")]
+impl QuoteProvider for TsmQuoteProvider {
+    fn name(&self) -> &'static str {
+        "tsm"
+    }
+
+    fn get_quote(&self, report_data: &[u8; 64]) -> anyhow::Result<Vec<u8>> {
+        use tsm_client::{make_client, report};
+
+        let client = make_client()?;
+        let request = report::Request {
+            in_blob: report_data.to_vec(),
+            privilege: None,
+            get_aux_blob: false,
+        };
+        let mut report = report::create(client, request)?;
+        Ok(report.get()?.out_blob)
+    }
+}
+
+#[cfg(feature = "c_driver")]
+pub struct TdxAttestQuoteProvider;
+
+#[cfg(feature = "c_driver")]
+impl QuoteProvider for TdxAttestQuoteProvider {
+    fn name(&self) -> &'static str {
+        "tdx_attest"
+    }
+
+    fn get_quote(&self, report_data: &[u8; 64]) -> anyhow::Result<Vec<u8>> {
+        let (_, quote) = tdx_attest::get_quote(report_data, None)?;
+        Ok(quote)
+    }
+}
+
+/// Mock provider for development and tests. Produces a quote with a v4 TDX header and body carrying the report
+/// data but without any signature data, i.e it will never pass verification.
+pub struct MockQuoteProvider;
+
+impl MockQuoteProvider {
+    pub const HEADER_SIZE: usize = 48;
+    pub const BODY_SIZE: usize = 584;
+    pub const REPORT_DATA_OFFSET: usize = Self::HEADER_SIZE + 520;
+
+    /// Reads the report data back from a quote (mocked or not).
+    pub fn report_data(quote: &[u8]) -> anyhow::Result<[u8; 64]> {
+        quote
+            .get(Self::REPORT_DATA_OFFSET..Self::REPORT_DATA_OFFSET + 64)
+            .and_then(|data| data.try_into().ok())
+            .ok_or(anyhow::anyhow!("quote is too short"))
+    }
+}
+
+impl QuoteProvider for MockQuoteProvider {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn get_quote(&self, report_data: &[u8; 64]) -> anyhow::Result<Vec<u8>> {
+        let mut quote = vec![0; Self::HEADER_SIZE + Self::BODY_SIZE];
+        // version 4, ECDSA-256 attestation key, TDX tee type.
+        quote[0..2].copy_from_slice(&4_u16.to_le_bytes());
+        quote[2..4].copy_from_slice(&2_u16.to_le_bytes());
+        quote[4..8].copy_from_slice(&0x81_u32.to_le_bytes());
+        quote[Self::REPORT_DATA_OFFSET..Self::REPORT_DATA_OFFSET + 64]
+            .copy_from_slice(report_data);
+
+        Ok(quote)
+    }
+}
+
+/// Provider returned when nothing could be detected, errors on every request. Allows running verification-only
+/// workloads on machines that can't produce quotes.
+pub struct UnavailableQuoteProvider {
+    pub reason: String,
+}
+
+impl QuoteProvider for UnavailableQuoteProvider {
+    fn name(&self) -> &'static str {
+        "unavailable"
+    }
+
+    fn get_quote(&self, _report_data: &[u8; 64]) -> anyhow::Result<Vec<u8>> {
+        Err(anyhow::anyhow!("no quote provider available: {}", self.reason))
+    }
+}
+
+/// Builds a provider by name (`tsm`, `tdx_attest` or `mock`).
+pub fn from_name(name: &str) -> anyhow::Result<Box<dyn QuoteProvider>> {
+    match name {
+        #[cfg(feature = "tsm")]
+        "tsm" => Ok(Box::new(TsmQuoteProvider)),
+        #[cfg(feature = "c_driver")]
+        "tdx_attest" => Ok(Box::new(TdxAttestQuoteProvider)),
+        "mock" => Ok(Box::new(MockQuoteProvider)),
+        other => Err(anyhow::anyhow!(
+            "quote provider {} is unknown or not compiled in",
+            other
+        )),
+    }
+}
+
+/// Picks the quote provider.
+///
+/// `QUOTE_PROVIDER` (`tsm`, `tdx_attest` or `mock`) takes precedence, otherwise configfs-tsm is used if present
+/// and then the `tdx_attest` driver if the guest device exists. The mock provider is never auto-detected.
+pub fn detect() -> anyhow::Result<Box<dyn QuoteProvider>> {
+    if let Ok(name) = std::env::var("QUOTE_PROVIDER") {
+        return from_name(&name);
+    }
+
+    detect_with(Path::new(TSM_REPORT_PATH), Path::new(TDX_GUEST_DEVICE))
+}
+
+fn detect_with(tsm_path: &Path, device_path: &Path) -> anyhow::Result<Box<dyn QuoteProvider>> {
+    if tsm_path.exists() {
+        if let Ok(provider) = from_name("tsm") {
+            return Ok(provider);
+        }
+    }
+
+    if device_path.exists() {
+        if let Ok(provider) = from_name("tdx_attest") {
+            return Ok(provider);
+        }
+    }
+
+    Err(anyhow::anyhow!(
+        "neither {} nor {} are usable",
+        tsm_path.display(),
+        device_path.display()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mock_quote_carries_report_data() {
+        let report_data = [7; 64];
+        let quote = MockQuoteProvider.get_quote(&report_data).unwrap();
+
+        assert_eq!(
+            quote.len(),
+            MockQuoteProvider::HEADER_SIZE + MockQuoteProvider::BODY_SIZE
+        );
+        assert_eq!(MockQuoteProvider::report_data(&quote).unwrap(), report_data);
+    }
+
+    #[test]
+    fn detection_without_devices_fails() {
+        let missing = Path::new("/nonexistent/tdx");
+        assert!(detect_with(missing, missing).is_err());
+    }
+
+    #[cfg(feature = "tsm")]
+    #[test]
+    fn detection_prefers_tsm() {
+        let present = std::env::temp_dir();
+        let provider = detect_with(&present, &present).unwrap();
+        assert_eq!(provider.name(), "tsm");
+    }
+}
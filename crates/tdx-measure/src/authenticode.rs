@@ -0,0 +1,165 @@
+//! Authenticode hash of PE/COFF images.
+//!
+//! TDVF measures EFI applications (e.g the kernel's EFI stub) into RTMR1 with their authenticode hash rather than
+//! a plain hash of the file: the checksum and the certificate table are skipped so that signing an image doesn't
+//! change its measurement.
+//!
+
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha384};
+
+const PE_SIGNATURE: &[u8; 4] = b"PE\0\0";
+const PE32_MAGIC: u16 = 0x10b;
+const PE32_PLUS_MAGIC: u16 = 0x20b;
+const COFF_HEADER_SIZE: usize = 20;
+const SECTION_HEADER_SIZE: usize = 40;
+/// Index of the certificate table in the data directories.
+const CERTIFICATE_TABLE: usize = 4;
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    let bytes = data.get(offset..offset + 2).context("image is truncated")?;
+    Ok(u16::from_le_bytes(bytes.try_into()?))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    let bytes = data.get(offset..offset + 4).context("image is truncated")?;
+    Ok(u32::from_le_bytes(bytes.try_into()?))
+}
+
+/// Computes the SHA-384 authenticode hash of [`image`], following the PE/COFF specification.
+pub fn authenticode_sha384(image: &[u8]) -> Result<[u8; 48]> {
+    let pe = read_u32(image, 0x3c)? as usize;
+    if image.get(pe..pe + 4) != Some(PE_SIGNATURE) {
+        bail!("not a PE image");
+    }
+    let coff = pe + 4;
+    let number_of_sections = read_u16(image, coff + 2)? as usize;
+    let optional_header_size = read_u16(image, coff + 16)? as usize;
+    let optional = coff + COFF_HEADER_SIZE;
+
+    let (rva_count_offset, data_directories) = match read_u16(image, optional)? {
+        PE32_MAGIC => (optional + 92, optional + 96),
+        PE32_PLUS_MAGIC => (optional + 108, optional + 112),
+        magic => bail!("unknown optional header magic {:#x}", magic),
+    };
+    let checksum = optional + 64;
+    let size_of_headers = read_u32(image, optional + 60)? as usize;
+    if size_of_headers > image.len() || size_of_headers < checksum + 4 {
+        bail!("invalid size of headers");
+    }
+
+    let mut hasher = Sha384::new();
+    hasher.update(&image[..checksum]);
+
+    let mut certificate_size = 0;
+    if read_u32(image, rva_count_offset)? as usize > CERTIFICATE_TABLE {
+        let certificate_table = data_directories + CERTIFICATE_TABLE * 8;
+        if certificate_table + 8 > size_of_headers {
+            bail!("invalid certificate table entry");
+        }
+        certificate_size = read_u32(image, certificate_table + 4)? as usize;
+        hasher.update(&image[checksum + 4..certificate_table]);
+        hasher.update(&image[certificate_table + 8..size_of_headers]);
+    } else {
+        hasher.update(&image[checksum + 4..size_of_headers]);
+    }
+
+    // Sections are hashed in file order.
+    let section_table = optional + optional_header_size;
+    let mut sections = vec![];
+    for index in 0..number_of_sections {
+        let header = section_table + index * SECTION_HEADER_SIZE;
+        let size = read_u32(image, header + 16)? as usize;
+        let offset = read_u32(image, header + 20)? as usize;
+        if size > 0 {
+            sections.push((offset, size));
+        }
+    }
+    sections.sort();
+
+    let mut hashed = size_of_headers;
+    for (offset, size) in sections {
+        let data = image
+            .get(offset..offset + size)
+            .context("section is out of the image")?;
+        hasher.update(data);
+        hashed += size;
+    }
+
+    // Trailing data, minus the certificates which are at the end of the image.
+    let trailing_end = image
+        .len()
+        .checked_sub(certificate_size)
+        .context("invalid certificate table size")?;
+    if trailing_end > hashed {
+        hasher.update(&image[hashed..trailing_end]);
+    }
+
+    Ok(hasher.finalize().into())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// PE32+ image with a single section and some trailing data.
+    pub(crate) fn sample_image() -> Vec<u8> {
+        let pe = 0x40;
+        let optional = pe + 4 + COFF_HEADER_SIZE;
+        let optional_header_size = 112 + 16 * 8;
+        let size_of_headers = 0x200;
+
+        let mut image = vec![0_u8; size_of_headers];
+        image[..2].copy_from_slice(b"MZ");
+        image[0x3c..0x40].copy_from_slice(&(pe as u32).to_le_bytes());
+        image[pe..pe + 4].copy_from_slice(PE_SIGNATURE);
+        image[pe + 6..pe + 8].copy_from_slice(&1_u16.to_le_bytes());
+        image[pe + 20..pe + 22].copy_from_slice(&(optional_header_size as u16).to_le_bytes());
+        image[optional..optional + 2].copy_from_slice(&PE32_PLUS_MAGIC.to_le_bytes());
+        image[optional + 60..optional + 64]
+            .copy_from_slice(&(size_of_headers as u32).to_le_bytes());
+        image[optional + 108..optional + 112].copy_from_slice(&16_u32.to_le_bytes());
+
+        let section = optional + optional_header_size;
+        image[section..section + 6].copy_from_slice(b".text\0");
+        image[section + 16..section + 20].copy_from_slice(&0x200_u32.to_le_bytes());
+        image[section + 20..section + 24].copy_from_slice(&(size_of_headers as u32).to_le_bytes());
+
+        image.extend([0x90; 0x200]);
+        image.extend(b"trailing");
+        image
+    }
+
+    #[test]
+    fn skips_checksum_and_certificates() {
+        let image = sample_image();
+        let hash = authenticode_sha384(&image).unwrap();
+        assert_ne!(hash, <[u8; 48]>::from(Sha384::digest(&image)));
+
+        // The checksum isn't covered.
+        let optional = 0x40 + 4 + COFF_HEADER_SIZE;
+        let mut patched = image.clone();
+        patched[optional + 64] = 0xff;
+        assert_eq!(authenticode_sha384(&patched).unwrap(), hash);
+
+        // Neither are the certificate table entry and the certificates appended to the image.
+        let certificate_table = optional + 112 + CERTIFICATE_TABLE * 8;
+        let mut signed = image.clone();
+        signed[certificate_table..certificate_table + 4]
+            .copy_from_slice(&(image.len() as u32).to_le_bytes());
+        signed[certificate_table + 4..certificate_table + 8].copy_from_slice(&16_u32.to_le_bytes());
+        signed.extend([0xcc; 16]);
+        assert_eq!(authenticode_sha384(&signed).unwrap(), hash);
+
+        // But the sections and trailing data are.
+        let mut modified = image.clone();
+        *modified.last_mut().unwrap() = 0;
+        assert_ne!(authenticode_sha384(&modified).unwrap(), hash);
+    }
+
+    #[test]
+    fn rejects_non_pe_images() {
+        assert!(authenticode_sha384(&[0; 0x100]).is_err());
+        assert!(authenticode_sha384(b"MZ").is_err());
+    }
+}
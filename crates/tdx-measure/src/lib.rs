@@ -0,0 +1,275 @@
+//! Expected measurements of TDX guest images.
+//!
+//! Allowlists (see [`cc_eventlog::EventLogPolicy`]) need the MRTD and RTMR values of an image before any node
+//! runs it. This recomputes them from the image's inputs:
+//!
+//! - MRTD from the TDVF firmware, see [`tdvf::mrtd`].
+//! - RTMR0 from the event log of a reference boot. It covers the TD HOB, ACPI tables and boot variables which
+//!   depend on the VMM and its configuration rather than on the image, so it can't be computed from the inputs.
+//! - RTMR1 from the kernel, measured by TDVF with its authenticode hash when direct booting.
+//! - RTMR2 from the kernel command line and initrd, only reported when at least one of them is given (set an empty
+//!   [`ImageInputs::cmdline`] for kernels booted without either).
+//! - RTMR3 from the runtime events the guest measures at startup (e.g `new-york`'s config).
+//!
+//! Note: this assumes direct boot (QEMU's `-kernel`, `-initrd` and `-append`), images booting through a
+//! bootloader measure more events. NB: QEMU patches the kernel's setup header before the firmware measures it,
+//! [`ImageInputs::kernel`] must be the image as the firmware sees it.
+//!
+
+use anyhow::{Context, Result};
+use cc_eventlog::{
+    replay_rtmrs, EventLogPolicy, Rtmr, TdxEventLog, EV_EFI_ACTION,
+    EV_EFI_BOOT_SERVICES_APPLICATION, EV_EVENT_TAG, EV_SEPARATOR, RTMR_COUNT, RUNTIME_EVENT_TYPE,
+    RUNTIME_RTMR,
+};
+use sha2::{Digest, Sha384};
+
+pub mod authenticode;
+pub mod tdvf;
+
+/// Actions TDVF logs into RTMR1 around starting the kernel.
+const CALLING_EFI_APPLICATION: &str = "Calling EFI Application from Boot Option";
+const EXIT_BOOT_SERVICES_INVOCATION: &str = "Exit Boot Services Invocation";
+const EXIT_BOOT_SERVICES_SUCCEEDED: &str = "Exit Boot Services Returned with Success";
+
+/// Inputs of a guest image. Registers whose inputs are missing are left out of [`Measurements`].
+#[derive(Clone, Debug, Default)]
+pub struct ImageInputs {
+    /// TDVF firmware image.
+    pub firmware: Option<Vec<u8>>,
+    /// Event log of a reference boot of the same firmware and VM configuration, only its RTMR0 events are used.
+    pub reference_event_logs: Option<Vec<TdxEventLog>>,
+    /// PE/COFF kernel image.
+    pub kernel: Option<Vec<u8>>,
+    pub initrd: Option<Vec<u8>>,
+    pub cmdline: Option<String>,
+    /// Named events measured into RTMR3 at runtime, in order.
+    pub runtime_events: Vec<(String, Vec<u8>)>,
+}
+
+/// Expected MRTD and RTMRs, `None` when they couldn't be computed from the inputs.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Measurements {
+    pub mrtd: Option<[u8; 48]>,
+    pub rtmrs: [Option<Rtmr>; RTMR_COUNT],
+}
+
+fn sha384(data: &[u8]) -> [u8; 48] {
+    Sha384::digest(data).into()
+}
+
+/// Boot time event with a precomputed digest, boot time events aren't named.
+fn boot_event(imr: u32, event_type: u32, digest: [u8; 48], event_payload: Vec<u8>) -> TdxEventLog {
+    TdxEventLog {
+        imr,
+        event_type,
+        digest,
+        event: String::new(),
+        event_payload,
+        extra_digests: vec![],
+    }
+}
+
+fn action(imr: u32, text: &str) -> TdxEventLog {
+    boot_event(imr, EV_EFI_ACTION, sha384(text.as_bytes()), text.into())
+}
+
+impl ImageInputs {
+    /// RTMR0 events of the reference boot followed by the events the firmware logs when direct booting
+    /// [`Self::kernel`].
+    pub fn boot_event_logs(&self) -> Result<Vec<TdxEventLog>> {
+        let mut event_logs: Vec<_> = self
+            .reference_event_logs
+            .iter()
+            .flatten()
+            .filter(|e| e.imr == 0)
+            .cloned()
+            .collect();
+
+        let Some(kernel) = &self.kernel else {
+            return Ok(event_logs);
+        };
+
+        // UEFI_IMAGE_LOAD_EVENT, the load address isn't known ahead of time but it's not part of the digest.
+        let mut image_load = vec![0; 24];
+        image_load[8..16].copy_from_slice(&(kernel.len() as u64).to_le_bytes());
+        image_load.extend(0_u64.to_le_bytes());
+        event_logs.push(boot_event(
+            1,
+            EV_EFI_BOOT_SERVICES_APPLICATION,
+            authenticode::authenticode_sha384(kernel).context("Failed to hash kernel")?,
+            image_load,
+        ));
+        event_logs.push(action(1, CALLING_EFI_APPLICATION));
+        event_logs.push(boot_event(1, EV_SEPARATOR, sha384(&[0; 4]), vec![0; 4]));
+        event_logs.push(action(1, EXIT_BOOT_SERVICES_INVOCATION));
+        event_logs.push(action(1, EXIT_BOOT_SERVICES_SUCCEEDED));
+
+        // The command line is measured as the UTF-16 load options of the kernel, to which QEMU adds the initrd.
+        let mut cmdline = self.cmdline.clone().unwrap_or_default();
+        if self.initrd.is_some() {
+            cmdline.push_str(" initrd=initrd");
+        }
+        let mut load_options: Vec<u8> = cmdline.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        load_options.extend([0, 0]);
+        event_logs.push(boot_event(
+            2,
+            EV_EVENT_TAG,
+            sha384(&load_options),
+            load_options,
+        ));
+        if let Some(initrd) = &self.initrd {
+            event_logs.push(boot_event(
+                2,
+                EV_EVENT_TAG,
+                sha384(initrd),
+                b"Linux initrd".to_vec(),
+            ));
+        }
+
+        Ok(event_logs)
+    }
+
+    pub fn runtime_event_logs(&self) -> Vec<TdxEventLog> {
+        self.runtime_events
+            .iter()
+            .map(|(name, payload)| {
+                TdxEventLog::new(
+                    RUNTIME_RTMR,
+                    RUNTIME_EVENT_TYPE,
+                    name.clone(),
+                    payload.clone(),
+                )
+            })
+            .collect()
+    }
+
+    pub fn measure(&self) -> Result<Measurements> {
+        let mrtd = self
+            .firmware
+            .as_deref()
+            .map(tdvf::mrtd)
+            .transpose()
+            .context("Failed to compute MRTD")?;
+
+        let mut event_logs = self.boot_event_logs()?;
+        event_logs.extend(self.runtime_event_logs());
+        let replayed = replay_rtmrs(&event_logs)?;
+
+        let known = [
+            self.reference_event_logs.is_some(),
+            self.kernel.is_some(),
+            // Without either, RTMR2 would silently pin an empty command line.
+            self.kernel.is_some() && (self.cmdline.is_some() || self.initrd.is_some()),
+            !self.runtime_events.is_empty(),
+        ];
+        let rtmrs = std::array::from_fn(|index| known[index].then_some(replayed[index]));
+
+        Ok(Measurements { mrtd, rtmrs })
+    }
+}
+
+impl Measurements {
+    /// Pins the MRTD and the RTMRs below [`up_to`] in [`policy`].
+    ///
+    /// NB: a guest's config usually ends up measured into RTMR3 and may include the policy itself, in which case
+    /// RTMR3 can't be pinned and the runtime events should be checked through rules instead.
+    pub fn apply_to(&self, policy: &mut EventLogPolicy, up_to: usize) {
+        if let Some(mrtd) = &self.mrtd {
+            policy.mrtd = Some(hex::encode(mrtd));
+        }
+        for (index, rtmr) in self.rtmrs.iter().enumerate().take(up_to) {
+            if let Some(rtmr) = rtmr {
+                policy.rtmrs.insert(index as u32, hex::encode(rtmr));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cc_eventlog::EventLogs;
+
+    fn sample_event_logs() -> Vec<TdxEventLog> {
+        let boot_time_data =
+            include_bytes!("../../attestation-driver/cc-eventlog/samples/ccel.bin");
+        EventLogs::decode(&mut boot_time_data.as_slice())
+            .unwrap()
+            .into_tdx_event_logs()
+            .unwrap()
+    }
+
+    #[test]
+    fn boot_events_match_tdvf() {
+        let sample = sample_event_logs();
+        let inputs = ImageInputs {
+            reference_event_logs: Some(sample.clone()),
+            kernel: Some(authenticode::tests::sample_image()),
+            ..Default::default()
+        };
+        let event_logs = inputs.boot_event_logs().unwrap();
+
+        // RTMR0 is taken as is, RTMR1 only differs by the kernel.
+        let digests = |event_logs: &[TdxEventLog], imr| {
+            event_logs
+                .iter()
+                .filter(|e| e.imr == imr)
+                .map(|e| e.digest)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(digests(&event_logs, 0), digests(&sample, 0));
+        assert_eq!(digests(&event_logs, 1)[1..], digests(&sample, 1)[1..]);
+    }
+
+    #[test]
+    fn measures_inputs() {
+        let inputs = ImageInputs {
+            firmware: Some(tdvf::tests::sample_firmware()),
+            kernel: Some(authenticode::tests::sample_image()),
+            initrd: Some(b"initrd".to_vec()),
+            cmdline: Some("console=ttyS0".into()),
+            runtime_events: vec![("app-id".into(), b"new-york".to_vec())],
+            ..Default::default()
+        };
+        let measurements = inputs.measure().unwrap();
+        assert!(measurements.mrtd.is_some());
+        assert_eq!(measurements.rtmrs[0], None);
+
+        // The calculated event log replays to the expected registers.
+        let mut event_logs = inputs.boot_event_logs().unwrap();
+        event_logs.extend(inputs.runtime_event_logs());
+        let replayed = replay_rtmrs(&event_logs).unwrap();
+        assert_eq!(measurements.rtmrs[1..], replayed.map(Some)[1..]);
+
+        let mut policy = EventLogPolicy::default();
+        measurements.apply_to(&mut policy, RUNTIME_RTMR as usize);
+        assert_eq!(policy.rtmrs.len(), 2);
+        policy.check(&event_logs).unwrap();
+        policy.check_mrtd(&measurements.mrtd.unwrap()).unwrap();
+
+        // A different cmdline changes RTMR2.
+        let other = ImageInputs {
+            cmdline: Some("console=hvc0".into()),
+            ..inputs.clone()
+        };
+        assert_ne!(other.measure().unwrap().rtmrs[2], measurements.rtmrs[2]);
+        assert_eq!(other.measure().unwrap().rtmrs[1], measurements.rtmrs[1]);
+
+        // RTMR2 isn't known without a cmdline nor an initrd.
+        let kernel_only = ImageInputs {
+            initrd: None,
+            cmdline: None,
+            ..inputs.clone()
+        };
+        let kernel_only = kernel_only.measure().unwrap();
+        assert_eq!(kernel_only.rtmrs[2], None);
+        assert_eq!(kernel_only.rtmrs[1], measurements.rtmrs[1]);
+        let empty_cmdline = ImageInputs {
+            initrd: None,
+            cmdline: Some(String::new()),
+            ..inputs
+        };
+        assert!(empty_cmdline.measure().unwrap().rtmrs[2].is_some());
+    }
+}
@@ -0,0 +1,249 @@
+//! MRTD computation from a TDVF (OVMF built for TDX) image.
+//!
+//! The VMM copies the firmware sections listed in the TDX metadata into the TD with `TDH.MEM.PAGE.ADD`, and
+//! measures those marked `MR_EXTEND` with `TDH.MR.EXTEND` in 256 bytes chunks. MRTD is a single SHA-384 over
+//! what the TDX module logs for each of these calls, so it can be recomputed from the image alone.
+//!
+//! Note: sections with the `PAGE_AUG` attribute are accepted by the guest later on and aren't part of MRTD.
+//!
+
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha384};
+
+/// `96b582de-1fb2-45f7-baea-a366c55a082d`, footer of the GUIDed table at the end of OVMF images.
+const TABLE_FOOTER_GUID: [u8; 16] = [
+    0xde, 0x82, 0xb5, 0x96, 0xb2, 0x1f, 0xf7, 0x45, 0xba, 0xea, 0xa3, 0x66, 0xc5, 0x5a, 0x08, 0x2d,
+];
+/// `e47a6535-984a-4798-865e-4685a7bf8ec2`, entry holding the offset of the TDX metadata from the end of the image.
+const TDX_METADATA_OFFSET_GUID: [u8; 16] = [
+    0x35, 0x65, 0x7a, 0xe4, 0x4a, 0x98, 0x98, 0x47, 0x86, 0x5e, 0x46, 0x85, 0xa7, 0xbf, 0x8e, 0xc2,
+];
+/// The GUIDed table ends right before the last 32 bytes (reset vector) of the image.
+const TABLE_END_OFFSET: usize = 0x20;
+const TDVF_SIGNATURE: &[u8; 4] = b"TDVF";
+const SECTION_SIZE: usize = 32;
+const PAGE_SIZE: u64 = 0x1000;
+const EXTEND_CHUNK_SIZE: u64 = 256;
+
+pub const ATTRIBUTE_MR_EXTEND: u32 = 0x1;
+pub const ATTRIBUTE_PAGE_AUG: u32 = 0x2;
+
+/// `TDVF_SECTION` as defined by the TDX Virtual Firmware Design Guide.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TdvfSection {
+    /// Offset of the section's data in the image.
+    pub data_offset: u32,
+    pub raw_data_size: u32,
+    /// Guest physical address the section is loaded at.
+    pub memory_address: u64,
+    pub memory_data_size: u64,
+    /// BFV, CFV, TD_HOB, TempMem, ...
+    pub section_type: u32,
+    pub attributes: u32,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    let bytes = data.get(offset..offset + 2).context("image is truncated")?;
+    Ok(u16::from_le_bytes(bytes.try_into()?))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    let bytes = data.get(offset..offset + 4).context("image is truncated")?;
+    Ok(u32::from_le_bytes(bytes.try_into()?))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64> {
+    let bytes = data.get(offset..offset + 8).context("image is truncated")?;
+    Ok(u64::from_le_bytes(bytes.try_into()?))
+}
+
+/// Walks the GUIDed table backwards (entries are `data || u16 length || guid`) looking for [`guid`].
+fn find_table_entry<'a>(firmware: &'a [u8], guid: &[u8; 16]) -> Result<&'a [u8]> {
+    let table_end = firmware
+        .len()
+        .checked_sub(TABLE_END_OFFSET)
+        .context("image is too small")?;
+    let footer = table_end.checked_sub(18).context("image is too small")?;
+    if firmware[footer + 2..table_end] != TABLE_FOOTER_GUID {
+        bail!("GUIDed table footer not found, not an OVMF image?");
+    }
+    let table_start = table_end
+        .checked_sub(read_u16(firmware, footer)? as usize)
+        .context("invalid GUIDed table size")?;
+
+    let mut cursor = footer;
+    while cursor > table_start {
+        let entry_guid = firmware
+            .get(cursor.saturating_sub(16)..cursor)
+            .context("invalid GUIDed table entry")?;
+        let length = read_u16(firmware, cursor.saturating_sub(18))? as usize;
+        let data_start = cursor
+            .checked_sub(length)
+            .filter(|start| *start >= table_start && length >= 18)
+            .context("invalid GUIDed table entry length")?;
+        if entry_guid == guid {
+            return Ok(&firmware[data_start..cursor - 18]);
+        }
+        cursor = data_start;
+    }
+
+    bail!("GUIDed table entry not found")
+}
+
+/// Parses the sections of the TDX metadata of [`firmware`].
+pub fn parse_sections(firmware: &[u8]) -> Result<Vec<TdvfSection>> {
+    let entry = find_table_entry(firmware, &TDX_METADATA_OFFSET_GUID)
+        .context("TDX metadata not found, not a TDVF image?")?;
+    let offset = read_u32(entry, 0)? as usize;
+    let metadata = firmware
+        .len()
+        .checked_sub(offset)
+        .context("invalid TDX metadata offset")?;
+
+    if firmware.get(metadata..metadata + 4) != Some(TDVF_SIGNATURE) {
+        bail!("invalid TDX metadata signature");
+    }
+    // Signature, length, version then the number of sections.
+    let count = read_u32(firmware, metadata + 12)? as usize;
+
+    let mut sections = Vec::with_capacity(count);
+    for index in 0..count {
+        let section = metadata + 16 + index * SECTION_SIZE;
+        sections.push(TdvfSection {
+            data_offset: read_u32(firmware, section)?,
+            raw_data_size: read_u32(firmware, section + 4)?,
+            memory_address: read_u64(firmware, section + 8)?,
+            memory_data_size: read_u64(firmware, section + 16)?,
+            section_type: read_u32(firmware, section + 24)?,
+            attributes: read_u32(firmware, section + 28)?,
+        });
+    }
+
+    Ok(sections)
+}
+
+/// 128 bytes block the TDX module hashes for each operation: the operation name followed by the GPA.
+fn operation_block(operation: &[u8], gpa: u64) -> [u8; 128] {
+    let mut block = [0; 128];
+    block[..operation.len()].copy_from_slice(operation);
+    block[16..24].copy_from_slice(&gpa.to_le_bytes());
+    block
+}
+
+/// Computes the MRTD the TDX module will report for a TD booted with [`firmware`].
+pub fn mrtd(firmware: &[u8]) -> Result<[u8; 48]> {
+    let mut hasher = Sha384::new();
+    for section in parse_sections(firmware)? {
+        if section.attributes & ATTRIBUTE_PAGE_AUG != 0 {
+            continue;
+        }
+        if !section.memory_data_size.is_multiple_of(PAGE_SIZE) {
+            bail!(
+                "section at {:#x} isn't page aligned",
+                section.memory_address
+            );
+        }
+
+        // Data past the raw data (e.g for TD_HOB and TempMem sections) is zeroed.
+        let data_start = section.data_offset as usize;
+        let data = firmware
+            .get(data_start..data_start + section.raw_data_size as usize)
+            .context("section data is out of the image")?;
+
+        for page in (0..section.memory_data_size).step_by(PAGE_SIZE as usize) {
+            let gpa = section.memory_address + page;
+            hasher.update(operation_block(b"MEM.PAGE.ADD", gpa));
+
+            if section.attributes & ATTRIBUTE_MR_EXTEND == 0 {
+                continue;
+            }
+            for chunk in (page..page + PAGE_SIZE).step_by(EXTEND_CHUNK_SIZE as usize) {
+                let mut chunk_data = [0; EXTEND_CHUNK_SIZE as usize];
+                if let Some(available) = data.get(chunk as usize..) {
+                    let len = available.len().min(chunk_data.len());
+                    chunk_data[..len].copy_from_slice(&available[..len]);
+                }
+                hasher.update(operation_block(
+                    b"MR.EXTEND",
+                    section.memory_address + chunk,
+                ));
+                hasher.update(chunk_data);
+            }
+        }
+    }
+
+    Ok(hasher.finalize().into())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Minimal TDVF image: a 4K BFV measured at 0xfffff000, a TD_HOB only added and a PAGE_AUG section.
+    pub(crate) fn sample_firmware() -> Vec<u8> {
+        let mut firmware = vec![0_u8; 0x3000];
+        firmware[..0x1000].fill(0xaa);
+
+        let metadata = 0x1000;
+        firmware[metadata..metadata + 4].copy_from_slice(TDVF_SIGNATURE);
+        firmware[metadata + 12..metadata + 16].copy_from_slice(&3_u32.to_le_bytes());
+        let sections: [(u32, u32, u64, u64, u32, u32); 3] = [
+            (0, 0x1000, 0xfffff000, 0x1000, 0, ATTRIBUTE_MR_EXTEND),
+            (0, 0, 0x809000, 0x2000, 2, 0),
+            (0, 0, 0x800000, 0x1000, 4, ATTRIBUTE_PAGE_AUG),
+        ];
+        for (index, section) in sections.iter().enumerate() {
+            let start = metadata + 16 + index * SECTION_SIZE;
+            let mut bytes = vec![];
+            bytes.extend(section.0.to_le_bytes());
+            bytes.extend(section.1.to_le_bytes());
+            bytes.extend(section.2.to_le_bytes());
+            bytes.extend(section.3.to_le_bytes());
+            bytes.extend(section.4.to_le_bytes());
+            bytes.extend(section.5.to_le_bytes());
+            firmware[start..start + SECTION_SIZE].copy_from_slice(&bytes);
+        }
+
+        // GUIDed table: the metadata offset entry followed by the footer.
+        let mut table = vec![];
+        table.extend(((firmware.len() - metadata) as u32).to_le_bytes());
+        table.extend(22_u16.to_le_bytes());
+        table.extend(TDX_METADATA_OFFSET_GUID);
+        table.extend(40_u16.to_le_bytes());
+        table.extend(TABLE_FOOTER_GUID);
+        let table_end = firmware.len() - TABLE_END_OFFSET;
+        firmware[table_end - table.len()..table_end].copy_from_slice(&table);
+
+        firmware
+    }
+
+    #[test]
+    fn parses_metadata() {
+        let sections = parse_sections(&sample_firmware()).unwrap();
+        assert_eq!(sections.len(), 3);
+        assert_eq!(sections[0].memory_address, 0xfffff000);
+        assert_eq!(sections[1].section_type, 2);
+        assert_eq!(sections[2].attributes, ATTRIBUTE_PAGE_AUG);
+    }
+
+    #[test]
+    fn computes_mrtd() {
+        let mut expected = Sha384::new();
+        expected.update(operation_block(b"MEM.PAGE.ADD", 0xfffff000));
+        for chunk in 0..16 {
+            expected.update(operation_block(b"MR.EXTEND", 0xfffff000 + chunk * 256));
+            expected.update([0xaa; 256]);
+        }
+        expected.update(operation_block(b"MEM.PAGE.ADD", 0x809000));
+        expected.update(operation_block(b"MEM.PAGE.ADD", 0x80a000));
+        let expected: [u8; 48] = expected.finalize().into();
+
+        assert_eq!(mrtd(&sample_firmware()).unwrap(), expected);
+    }
+
+    #[test]
+    fn rejects_other_images() {
+        assert!(mrtd(&[0; 0x1000]).is_err());
+        assert!(mrtd(&[]).is_err());
+    }
+}
@@ -0,0 +1,124 @@
+//! Chain-agnostic interface to the coordination layer, i.e wherever nodes post their quotes and pick up the encrypted
+//! shared secret. For example new-york uses a Stellar cluster contract, but the same workflow can run against another
+//! chain or a local store without changing the host and guest services.
+//!
+//! Note: the coordination layer is not trusted. Quotes read from it are verified by the onboarding node and encrypted
+//! messages only decrypt if they were encrypted by a cluster member, implementations only have to make the data
+//! available. Layers should still authenticate onboard messages (e.g the cluster contract checks a signature from the
+//! cluster's signing key) so that newcomers don't have to go through spam, but newcomers don't rely on it.
+//!
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// A request to join the cluster posted by a new node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Registration<Q, P, E> {
+    pub quote: Q,
+    /// The pubkey the shared secret should be encrypted to.
+    pub pubkey: P,
+    pub event_log: E,
+}
+
+/// What the bootstrapper posted when creating the cluster.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bootstrap<Q, P> {
+    /// Binds [`shared_pubkey`] and [`signing_pubkey`], see [`CoordinationLayer::get_bootstrap`].
+    pub quote: Q,
+    pub shared_pubkey: P,
+    pub signing_pubkey: P,
+}
+
+/// A batch of items along with the cursor to resume from.
+#[derive(Clone, Debug)]
+pub struct Page<T, C> {
+    /// Each item comes with its own position so that callers can persist their progress item by item, resuming
+    /// from an item's position skips it.
+    pub items: Vec<(C, T)>,
+    /// `None` if nothing was ever returned.
+    pub cursor: Option<C>,
+}
+
+#[async_trait]
+pub trait CoordinationLayer {
+    type Quote: Send + Sync;
+    type Pubkey: Send + Sync;
+    type EventLog: Send + Sync;
+    type EncryptedMessage: Send + Sync;
+    /// Something recent and unpredictable to bind into quotes, e.g a ledger hash.
+    type Challenge: Send + Sync;
+    /// Position in the stream of registrations, e.g a ledger sequence or an event id.
+    /// Callers may persist it, e.g to resume onboarding after a restart.
+    type Cursor: Clone + Serialize + DeserializeOwned + Send + Sync;
+    /// What posting returns once it went through, e.g the transaction hash and ledger it was included in.
+    type Receipt: Serialize + Send + Sync;
+
+    /// Creates the cluster with [`shared_pubkey`] as the pubkey of the shared secret. Onboard messages have to be
+    /// signed by [`signing_pubkey`]'s key, which only cluster members hold.
+    async fn bootstrap(
+        &self,
+        quote: Self::Quote,
+        shared_pubkey: Self::Pubkey,
+        signing_pubkey: Self::Pubkey,
+    ) -> anyhow::Result<Self::Receipt>;
+
+    async fn register(
+        &self,
+        quote: Self::Quote,
+        pubkey: Self::Pubkey,
+        event_log: Self::EventLog,
+    ) -> anyhow::Result<Self::Receipt>;
+
+    /// Posts the shared secret encrypted to [`pubkey`], along with the member's signature.
+    async fn onboard(
+        &self,
+        pubkey: Self::Pubkey,
+        message: Self::EncryptedMessage,
+    ) -> anyhow::Result<Self::Receipt>;
+
+    /// Returns the registrations posted after [`after`], or all of them when `None`. Callers keep the returned
+    /// cursor to only get new registrations on the next call.
+    async fn pending_registrations(
+        &self,
+        after: Option<Self::Cursor>,
+    ) -> anyhow::Result<Page<Registration<Self::Quote, Self::Pubkey, Self::EventLog>, Self::Cursor>>;
+
+    /// Returns every message posted for [`pubkey`] in the order they were posted, empty if it wasn't onboarded yet.
+    /// Several members may onboard the same node and the layer can't tell which messages decrypt, so callers should
+    /// try them all.
+    async fn get_onboarding(
+        &self,
+        pubkey: &Self::Pubkey,
+    ) -> anyhow::Result<Vec<Self::EncryptedMessage>>;
+
+    /// Pubkeys of the nodes holding the shared secret in the order they joined, starting with the bootstrapper.
+    /// Implementations may leave out members they know are gone, e.g a node that registered again with a new key.
+    ///
+    /// NB: this is only as trustworthy as the layer's onboard messages, it's meant for splitting work between
+    /// members rather than deciding who to trust.
+    async fn members(&self) -> anyhow::Result<Vec<Self::Pubkey>>;
+
+    /// `None` until the cluster is bootstrapped.
+    async fn shared_pubkey(&self) -> anyhow::Result<Option<Self::Pubkey>>;
+
+    /// The bootstrap quote along with the pubkeys the layer holds for the cluster, `None` until the cluster is
+    /// bootstrapped.
+    ///
+    /// NB: the layer doesn't check the quote, joining nodes have to verify it binds the pubkeys before trusting
+    /// the cluster (e.g that onboard messages are checked against a key only members hold).
+    async fn get_bootstrap(
+        &self,
+    ) -> anyhow::Result<Option<Bootstrap<Self::Quote, Self::Pubkey>>>;
+
+    /// Returns the [`limit`] most recent challenges, newest first.
+    async fn recent_challenges(&self, limit: u32) -> anyhow::Result<Vec<Self::Challenge>>;
+
+    async fn latest_challenge(&self) -> anyhow::Result<Self::Challenge> {
+        self.recent_challenges(1)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or(anyhow!("no challenge available"))
+    }
+}
@@ -3,16 +3,20 @@
 
 use async_trait::async_trait;
 
+use crate::VerifiedQuote;
+
 #[async_trait]
 pub trait InnerAttestationHelper {
     // TBD: are these the best constraints? Likely not.
     // TBD: make everything async?? Likely not
     type Appdata: Send + Sync;
     type Quote: Send + Sync;
-    type VerificationResult;
+    type VerificationResult: VerifiedQuote + Send;
 
     async fn get_quote(&self, appdata: Self::Appdata) -> anyhow::Result<Self::Quote>;
 
+    /// Note: errors meaning the quote itself is invalid (rather than e.g the verifier being unreachable) should
+    /// carry [`crate::Refused`] so that onboarding doesn't retry it forever.
     async fn verify_quote(&self, quote: Self::Quote) -> anyhow::Result<Self::VerificationResult>;
 }
 
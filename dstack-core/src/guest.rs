@@ -11,24 +11,54 @@
 
 pub mod paths;
 
+use std::fmt;
+
 use async_trait::async_trait;
 use serde::{de::DeserializeOwned, Serialize};
 
+/// Context to attach to [`GuestServiceInner::onboard_new_node`] errors that mean the joining node doesn't qualify
+/// (e.g its quote doesn't verify, its challenge is stale or its event log doesn't satisfy the policy), as opposed to
+/// errors worth retrying (e.g the chain couldn't be reached), i.e `Err(e.context(Refused))`.
+///
+/// Note: the guest paths reply `403 Forbidden` to refusals and `500 Internal Server Error` to any other error, so
+/// that hosts know whether to give up on the registration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Refused;
+
+impl fmt::Display for Refused {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "joining node refused")
+    }
+}
+
+impl Refused {
+    /// Whether [`error`] is a refusal, wherever the context was attached.
+    pub fn is(error: &anyhow::Error) -> bool {
+        error.downcast_ref::<Refused>().is_some()
+    }
+}
+
 #[async_trait]
 pub trait GuestServiceInner: TdxOnlyGuestServiceInner {
     type Pubkey: Send + Sync + DeserializeOwned + Serialize;
     type EncryptedMessage: Send + Sync + Serialize;
     type Quote: Send + Sync + DeserializeOwned;
+    type EventLog: Send + Sync + DeserializeOwned;
     type SharedKey;
 
     async fn get_secret(&self) -> anyhow::Result<Self::SharedKey>;
 
     async fn replicate_thread(&self) -> anyhow::Result<()>;
 
+    /// Note: [`event_log`] is the joining node's event log as posted along with its quote. It isn't trusted until
+    /// replayed against the RTMRs of the verified quote.
+    ///
+    /// Note: errors that refuse the node rather than failing to check it carry [`Refused`].
     async fn onboard_new_node(
         &self,
         quote: Self::Quote,
         pubkeys: Vec<Self::Pubkey>,
+        event_log: Self::EventLog,
     ) -> anyhow::Result<Self::EncryptedMessage>;
 }
 
@@ -44,3 +74,18 @@ pub trait TdxOnlyGuestServiceInner {
 
     async fn get_associated_key(&self) -> anyhow::Result<Self::AssociatedKey>;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context};
+
+    #[test]
+    fn tells_refusals_apart() {
+        let refused: anyhow::Result<()> = Err(anyhow!("stale challenge")).context(Refused);
+        let refused = refused.context("while onboarding").unwrap_err();
+        assert!(Refused::is(&refused));
+
+        assert!(!Refused::is(&anyhow!("rpc unreachable")));
+    }
+}
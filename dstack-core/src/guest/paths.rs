@@ -1,6 +1,11 @@
-use super::GuestServiceInner;
+use super::{GuestServiceInner, Refused};
 use std::sync::Arc;
-use warp::{reject::Rejection, reply::Json, Filter};
+use warp::{
+    http::StatusCode,
+    reject::Rejection,
+    reply::{Json, WithStatus},
+    Filter,
+};
 
 pub(crate) fn with_impl<H>(
     guest_internal: Arc<H>,
@@ -24,6 +29,7 @@ pub mod requests {
     pub struct OnboardArgs<H: GuestServiceInner> {
         pub quote: H::Quote,
         pub pubkeys: Vec<H::Pubkey>,
+        pub event_log: H::EventLog,
     }
 
     #[derive(Deserialize, Serialize)]
@@ -59,16 +65,29 @@ impl<H: GuestServiceInner + Send + Sync> GuestPaths<H> {
             .and_then(
                 |request: requests::OnboardArgs<H>, guest_impl: Arc<H>| async move {
                     match guest_impl
-                        .onboard_new_node(request.quote, request.pubkeys)
+                        .onboard_new_node(request.quote, request.pubkeys, request.event_log)
                         .await
                     {
                         Ok(encrypted) => {
-                            return Ok::<Json, Rejection>(warp::reply::json(&encrypted))
+                            return Ok::<WithStatus<Json>, Rejection>(warp::reply::with_status(
+                                warp::reply::json(&encrypted),
+                                StatusCode::OK,
+                            ))
                         }
                         Err(e) => {
-                            return Ok(warp::reply::json(&serde_json::json!({
-                                "error": format!("{:?} while onbnoarding in inner guest impl", e)
-                            })))
+                            // NB: hosts give up on refused nodes and retry on anything else.
+                            let status = if Refused::is(&e) {
+                                StatusCode::FORBIDDEN
+                            } else {
+                                StatusCode::INTERNAL_SERVER_ERROR
+                            };
+
+                            return Ok(warp::reply::with_status(
+                                warp::reply::json(&serde_json::json!({
+                                    "error": format!("{:?} while onbnoarding in inner guest impl", e)
+                                })),
+                                status,
+                            ));
                         }
                     }
                 },
@@ -16,6 +16,17 @@ pub trait HostServiceInner {
     type Quote: DeserializeOwned + Serialize + Send + Sync;
     type Pubkey: DeserializeOwned + Serialize + Send + Sync;
     type Signature: DeserializeOwned + Serialize + Send + Sync;
+    type Challenge: DeserializeOwned + Serialize + Send + Sync;
+    type EventLog: DeserializeOwned + Serialize + Send + Sync;
+    /// Returned to the guest once its register or bootstrap request was posted, e.g a transaction receipt.
+    type Receipt: Serialize + Send + Sync;
+
+    /// Returns a freshness challenge for the guest to bind into its quote's report data (e.g a recent ledger hash
+    /// of the coordination chain or a nonce from existing members).
+    ///
+    /// The host is not trusted to provide an honest challenge: verifiers must independently check that the
+    /// challenge bound in a quote is recent and reject stale ones, which prevents old quotes from being replayed.
+    async fn get_challenge(&self) -> anyhow::Result<Self::Challenge>;
 
     /// Performs the registering request.
     ///
@@ -24,6 +35,9 @@ pub trait HostServiceInner {
     /// [`pubkeys`] is a vector of public keys (addresses and/or pubkeys if you're familiar with amiller/dstack-vm). The idea
     /// is that each TDX implementor will want to have different ways and layers for working with signatures various public keys.
     /// [`signatures`] is a vector of signatures also passed from the TDX. Reasoning is the same as the above.
+    /// [`event_log`] is the guest's event log. It should be made available along with the quote so that verifiers can
+    /// replay it against the quote's RTMRs and decide based on individual events rather than opaque measurements, e.g
+    /// by posting a commitment to it and serving it through [`HostServiceInner::event_log`].
     ///
     /// For example, the amiller/dstack-vm impl includes two pubkeys in the appdata (pubkey of privkey and myaddr of myPriv). mypriv signs
     /// the register_appdata for the host address and uses it as sig to pass to the host verification. The pubkey on the other hand
@@ -36,17 +50,26 @@ pub trait HostServiceInner {
         quote: Self::Quote,
         pubkeys: Vec<Self::Pubkey>,
         signatures: Vec<Self::Signature>,
-    ) -> anyhow::Result<()>;
+        event_log: Self::EventLog,
+    ) -> anyhow::Result<Self::Receipt>;
 
     /// Handles the actual creation of a cluster contract (i.e a contract configured with the pubkey of the shared secret).
     ///
     /// [`self`] is for global state (e.g orchestrator contract id, secret key used to submit transactions, etc).
     /// [`quote`] is the "genesis" quote. No one has to check against this quote, but it shuold be audited from other nodes that intend
     /// to join before they actually join the quorum
-    async fn bootstrap(&self, quote: Self::Quote, pubkeys: Vec<Self::Pubkey>)
-        -> anyhow::Result<()>;
+    async fn bootstrap(
+        &self,
+        quote: Self::Quote,
+        pubkeys: Vec<Self::Pubkey>,
+    ) -> anyhow::Result<Self::Receipt>;
 
     async fn onboard_thread(&self) -> anyhow::Result<()>;
+
+    /// Returns the event log our guest registered [`pubkey`] with, `None` if it didn't. This is for implementations
+    /// that don't post whole event logs (they may be too big for the coordination layer), other nodes fetch them
+    /// from the registering host.
+    async fn event_log(&self, pubkey: Self::Pubkey) -> anyhow::Result<Option<Self::EventLog>>;
 }
 
 #[async_trait]
@@ -1,6 +1,10 @@
 use super::HostServiceInner;
 use std::sync::Arc;
-use warp::{reject::Rejection, reply::WithStatus, Filter};
+use warp::{
+    reject::Rejection,
+    reply::{Json, WithStatus},
+    Filter,
+};
 
 pub(crate) fn with_impl<H>(
     host_internal: Arc<H>,
@@ -31,6 +35,12 @@ pub mod requests {
         pub quote: H::Quote,
         pub pubkeys: Vec<H::Pubkey>,
         pub signatures: Vec<H::Signature>,
+        pub event_log: H::EventLog,
+    }
+
+    #[derive(Deserialize, Serialize)]
+    pub struct EventLogArgs<H: HostServiceInner> {
+        pub pubkey: H::Pubkey,
     }
 }
 
@@ -51,6 +61,28 @@ impl<H: HostServiceInner + Send + Sync> HostPaths<H> {
             .map(|| format!("Live"))
     }
 
+    pub fn challenge(
+        &self,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path("challenge")
+            .and(warp::get())
+            .and(with_impl(self.inner_host.clone()))
+            .and_then(|host_impl: Arc<H>| async move {
+                match host_impl.get_challenge().await {
+                    Ok(challenge) => Ok::<WithStatus<Json>, Rejection>(warp::reply::with_status(
+                        warp::reply::json(&challenge),
+                        warp::http::StatusCode::OK,
+                    )),
+                    Err(e) => Ok(warp::reply::with_status(
+                        warp::reply::json(&serde_json::json!({
+                            "error": format!("{:?} while getting challenge in inner host impl", e)
+                        })),
+                        warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+                    )),
+                }
+            })
+    }
+
     pub fn bootstrap(
         &self,
     ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
@@ -61,15 +93,17 @@ impl<H: HostServiceInner + Send + Sync> HostPaths<H> {
             .and_then(
                 |request: requests::BootstrapArgs<H>, host_impl: Arc<H>| async move {
                     match host_impl.bootstrap(request.quote, request.pubkeys).await {
-                        Ok(_) => {
-                            return Ok::<WithStatus<String>, Rejection>(warp::reply::with_status(
-                                "success".into(),
+                        Ok(receipt) => {
+                            return Ok::<WithStatus<Json>, Rejection>(warp::reply::with_status(
+                                warp::reply::json(&receipt),
                                 warp::http::StatusCode::CREATED,
                             ))
                         }
                         Err(e) => {
                             return Ok(warp::reply::with_status(
-                                format!("error while bootstrapping in inner host impl {:?}", e),
+                                warp::reply::json(&serde_json::json!({
+                                    "error": format!("{:?} while bootstrapping in inner host impl", e)
+                                })),
                                 warp::http::StatusCode::INTERNAL_SERVER_ERROR,
                             ))
                         }
@@ -88,18 +122,25 @@ impl<H: HostServiceInner + Send + Sync> HostPaths<H> {
             .and_then(
                 |request: requests::RegisterArgs<H>, host_impl: Arc<H>| async move {
                     match host_impl
-                        .register(request.quote, request.pubkeys, request.signatures)
+                        .register(
+                            request.quote,
+                            request.pubkeys,
+                            request.signatures,
+                            request.event_log,
+                        )
                         .await
                     {
-                        Ok(_) => {
-                            return Ok::<WithStatus<String>, Rejection>(warp::reply::with_status(
-                                "success".into(),
+                        Ok(receipt) => {
+                            return Ok::<WithStatus<Json>, Rejection>(warp::reply::with_status(
+                                warp::reply::json(&receipt),
                                 warp::http::StatusCode::CREATED,
                             ))
                         }
                         Err(e) => {
                             return Ok(warp::reply::with_status(
-                                format!("error while registering in inner host impl {:?}", e),
+                                warp::reply::json(&serde_json::json!({
+                                    "error": format!("{:?} while registering in inner host impl", e)
+                                })),
                                 warp::http::StatusCode::INTERNAL_SERVER_ERROR,
                             ))
                         }
@@ -107,4 +148,37 @@ impl<H: HostServiceInner + Send + Sync> HostPaths<H> {
                 },
             )
     }
+
+    pub fn event_log(
+        &self,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path("event_log")
+            .and(warp::post())
+            .and(warp::body::json())
+            .and(with_impl(self.inner_host.clone()))
+            .and_then(
+                |request: requests::EventLogArgs<H>, host_impl: Arc<H>| async move {
+                    match host_impl.event_log(request.pubkey).await {
+                        Ok(Some(event_log)) => Ok::<WithStatus<Json>, Rejection>(
+                            warp::reply::with_status(
+                                warp::reply::json(&event_log),
+                                warp::http::StatusCode::OK,
+                            ),
+                        ),
+                        Ok(None) => Ok(warp::reply::with_status(
+                            warp::reply::json(&serde_json::json!({
+                                "error": "no event log registered for this pubkey"
+                            })),
+                            warp::http::StatusCode::NOT_FOUND,
+                        )),
+                        Err(e) => Ok(warp::reply::with_status(
+                            warp::reply::json(&serde_json::json!({
+                                "error": format!("{:?} while getting event log in inner host impl", e)
+                            })),
+                            warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+                        )),
+                    }
+                },
+            )
+    }
 }
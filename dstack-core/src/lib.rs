@@ -1,8 +1,16 @@
+mod coordination;
 mod crypto;
 mod guest;
 mod host;
+mod report_data;
 mod types;
+mod verified_quote;
 
+pub use coordination::{Bootstrap, CoordinationLayer, Page, Registration};
 pub use crypto::{InnerAttestationHelper, InnerCryptoHelper};
-pub use guest::{paths as guest_paths, GuestServiceInner, TdxOnlyGuestServiceInner};
+pub use guest::{paths as guest_paths, GuestServiceInner, Refused, TdxOnlyGuestServiceInner};
 pub use host::{paths as host_paths, HostServiceInner, HostServiceInnerCryptoHelper};
+pub use report_data::{
+    Purpose, ReportData, ReportDataBuilder, REPORT_DATA_SIZE, REPORT_DATA_VERSION,
+};
+pub use verified_quote::{TcbStatus, TdMeasurements, VerifiedQuote};
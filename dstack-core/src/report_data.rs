@@ -0,0 +1,230 @@
+//! Structured TDX report data.
+//!
+//! The 64 bytes of report data are the only thing a guest can bind to its quote, so instead of hashing an
+//! implementation-specific preimage into half of it we use a versioned layout that both the quote generation
+//! and the quote verification side build through the same helpers:
+//!
+//! | offset | size | field                                              |
+//! |--------|------|----------------------------------------------------|
+//! | 0      | 1    | layout version ([`REPORT_DATA_VERSION`])           |
+//! | 1      | 1    | purpose tag ([`Purpose`])                          |
+//! | 2      | 2    | reserved, zero                                     |
+//! | 4      | 16   | cluster id, `sha256(cluster)[..16]`                |
+//! | 20     | 32   | node pubkey hash, `sha256(pubkey)`                 |
+//! | 52     | 12   | freshness value, `sha256(challenge)[..12]` or zero |
+//!
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub const REPORT_DATA_VERSION: u8 = 1;
+pub const REPORT_DATA_SIZE: usize = 64;
+
+const CLUSTER_ID_SIZE: usize = 16;
+const FRESHNESS_SIZE: usize = 12;
+
+/// What the quote is being generated for. Prevents e.g a bootstrap quote from being replayed as a register one.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Purpose {
+    Bootstrap = 1,
+    Register = 2,
+}
+
+impl TryFrom<u8> for Purpose {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u8) -> anyhow::Result<Self> {
+        match value {
+            1 => Ok(Self::Bootstrap),
+            2 => Ok(Self::Register),
+            other => Err(anyhow!("unknown report data purpose {}", other)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReportData {
+    pub version: u8,
+    pub purpose: Purpose,
+    pub cluster_id: [u8; CLUSTER_ID_SIZE],
+    pub node_pubkey_hash: [u8; 32],
+    pub freshness: [u8; FRESHNESS_SIZE],
+}
+
+impl ReportData {
+    pub fn builder(purpose: Purpose) -> ReportDataBuilder {
+        ReportDataBuilder::new(purpose)
+    }
+
+    pub fn to_bytes(&self) -> [u8; REPORT_DATA_SIZE] {
+        let mut bytes = [0; REPORT_DATA_SIZE];
+        bytes[0] = self.version;
+        bytes[1] = self.purpose as u8;
+        bytes[4..20].copy_from_slice(&self.cluster_id);
+        bytes[20..52].copy_from_slice(&self.node_pubkey_hash);
+        bytes[52..64].copy_from_slice(&self.freshness);
+        bytes
+    }
+
+    /// Parses report data as found in a verified quote.
+    pub fn parse(bytes: &[u8]) -> anyhow::Result<Self> {
+        let bytes: &[u8; REPORT_DATA_SIZE] = bytes
+            .try_into()
+            .map_err(|_| anyhow!("report data must be {} bytes", REPORT_DATA_SIZE))?;
+
+        if bytes[0] != REPORT_DATA_VERSION {
+            return Err(anyhow!("unsupported report data version {}", bytes[0]));
+        }
+        if bytes[2..4] != [0, 0] {
+            return Err(anyhow!("reserved report data bytes are not zero"));
+        }
+
+        Ok(Self {
+            version: bytes[0],
+            purpose: Purpose::try_from(bytes[1])?,
+            cluster_id: bytes[4..20].try_into()?,
+            node_pubkey_hash: bytes[20..52].try_into()?,
+            freshness: bytes[52..64].try_into()?,
+        })
+    }
+
+    /// Freshness value that [`ReportDataBuilder::freshness`] derives from [`challenge`]. Useful for verifiers that
+    /// look for the challenge a quote was bound to among a set of acceptable ones.
+    pub fn freshness_for(challenge: impl AsRef<[u8]>) -> [u8; FRESHNESS_SIZE] {
+        Sha256::digest(challenge.as_ref())[..FRESHNESS_SIZE]
+            .try_into()
+            .expect("digest is larger than the freshness value")
+    }
+
+    /// Checks that [`self`] (usually parsed from a quote) is what the verifier [`expected`], reporting the first
+    /// field that differs.
+    pub fn ensure_matches(&self, expected: &ReportData) -> anyhow::Result<()> {
+        if self.purpose != expected.purpose {
+            return Err(anyhow!(
+                "report data purpose mismatch: expected {:?}, got {:?}",
+                expected.purpose,
+                self.purpose
+            ));
+        }
+        if self.cluster_id != expected.cluster_id {
+            return Err(anyhow!("report data is bound to another cluster"));
+        }
+        if self.node_pubkey_hash != expected.node_pubkey_hash {
+            return Err(anyhow!("report data is bound to another node pubkey"));
+        }
+        if self.freshness != expected.freshness {
+            return Err(anyhow!("report data freshness value mismatch"));
+        }
+
+        Ok(())
+    }
+}
+
+pub struct ReportDataBuilder {
+    purpose: Purpose,
+    cluster_id: [u8; CLUSTER_ID_SIZE],
+    node_pubkey_hash: [u8; 32],
+    freshness: [u8; FRESHNESS_SIZE],
+}
+
+impl ReportDataBuilder {
+    pub fn new(purpose: Purpose) -> Self {
+        Self {
+            purpose,
+            cluster_id: [0; CLUSTER_ID_SIZE],
+            node_pubkey_hash: [0; 32],
+            freshness: [0; FRESHNESS_SIZE],
+        }
+    }
+
+    /// Binds the cluster identifier (e.g the cluster contract address).
+    pub fn cluster_id(mut self, cluster: impl AsRef<[u8]>) -> Self {
+        self.cluster_id
+            .copy_from_slice(&Sha256::digest(cluster.as_ref())[..CLUSTER_ID_SIZE]);
+        self
+    }
+
+    /// Binds the public key the node wants to receive the shared secret to.
+    pub fn node_pubkey(mut self, pubkey: impl AsRef<[u8]>) -> Self {
+        self.node_pubkey_hash = Sha256::digest(pubkey.as_ref()).into();
+        self
+    }
+
+    /// Binds a freshness challenge (e.g a nonce or recent chain state). Left zeroed if not set.
+    pub fn freshness(mut self, challenge: impl AsRef<[u8]>) -> Self {
+        self.freshness = ReportData::freshness_for(challenge);
+        self
+    }
+
+    pub fn build(self) -> ReportData {
+        ReportData {
+            version: REPORT_DATA_VERSION,
+            purpose: self.purpose,
+            cluster_id: self.cluster_id,
+            node_pubkey_hash: self.node_pubkey_hash,
+            freshness: self.freshness,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> ReportData {
+        ReportData::builder(Purpose::Register)
+            .cluster_id([1; 32])
+            .node_pubkey([2; 32])
+            .freshness(b"challenge")
+            .build()
+    }
+
+    #[test]
+    fn roundtrip() {
+        let report_data = sample();
+        let bytes = report_data.to_bytes();
+
+        assert_eq!(bytes[0], REPORT_DATA_VERSION);
+        assert_eq!(bytes[1], Purpose::Register as u8);
+        assert_eq!(ReportData::parse(&bytes).unwrap(), report_data);
+        assert_eq!(
+            report_data.freshness,
+            ReportData::freshness_for(b"challenge")
+        );
+    }
+
+    #[test]
+    fn rejects_mismatches() {
+        let report_data = sample();
+        report_data.ensure_matches(&sample()).unwrap();
+
+        let other_node = ReportData::builder(Purpose::Register)
+            .cluster_id([1; 32])
+            .node_pubkey([3; 32])
+            .freshness(b"challenge")
+            .build();
+        assert!(report_data.ensure_matches(&other_node).is_err());
+
+        let bootstrap = ReportData::builder(Purpose::Bootstrap)
+            .cluster_id([1; 32])
+            .node_pubkey([2; 32])
+            .freshness(b"challenge")
+            .build();
+        assert!(report_data.ensure_matches(&bootstrap).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        let mut bytes = sample().to_bytes();
+        bytes[0] = 0;
+        assert!(ReportData::parse(&bytes).is_err());
+
+        let mut bytes = sample().to_bytes();
+        bytes[1] = 9;
+        assert!(ReportData::parse(&bytes).is_err());
+
+        assert!(ReportData::parse(&[0; 32]).is_err());
+    }
+}
@@ -0,0 +1,123 @@
+//! Common view of verified quotes.
+//!
+//! Each attestation backend returns its own verification result (e.g a parsed quote from a verification service or
+//! a `dcap-qvl` report), implementing [`VerifiedQuote`] for them lets onboarding logic check report data and
+//! measurements regardless of which backend is in use.
+//!
+
+/// TCB status of the platform that generated the quote, as reported by the verifier. See Intel's TCB info
+/// documentation for the meaning of each status.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TcbStatus {
+    UpToDate,
+    SwHardeningNeeded,
+    ConfigurationNeeded,
+    ConfigurationAndSwHardeningNeeded,
+    OutOfDate,
+    OutOfDateConfigurationNeeded,
+    Revoked,
+    /// Statuses we don't know about.
+    Other(String),
+}
+
+impl TcbStatus {
+    pub fn parse(status: &str) -> Self {
+        match status {
+            "UpToDate" => Self::UpToDate,
+            "SWHardeningNeeded" => Self::SwHardeningNeeded,
+            "ConfigurationNeeded" => Self::ConfigurationNeeded,
+            "ConfigurationAndSWHardeningNeeded" => Self::ConfigurationAndSwHardeningNeeded,
+            "OutOfDate" => Self::OutOfDate,
+            "OutOfDateConfigurationNeeded" => Self::OutOfDateConfigurationNeeded,
+            "Revoked" => Self::Revoked,
+            other => Self::Other(other.to_string()),
+        }
+    }
+
+    /// Name as in Intel's TCB info, the inverse of [`TcbStatus::parse`].
+    pub fn name(&self) -> &str {
+        match self {
+            Self::UpToDate => "UpToDate",
+            Self::SwHardeningNeeded => "SWHardeningNeeded",
+            Self::ConfigurationNeeded => "ConfigurationNeeded",
+            Self::ConfigurationAndSwHardeningNeeded => "ConfigurationAndSWHardeningNeeded",
+            Self::OutOfDate => "OutOfDate",
+            Self::OutOfDateConfigurationNeeded => "OutOfDateConfigurationNeeded",
+            Self::Revoked => "Revoked",
+            Self::Other(other) => other,
+        }
+    }
+
+    /// Parses a comma-separated list of statuses, e.g `UpToDate,SWHardeningNeeded`.
+    pub fn parse_list(statuses: &str) -> Vec<Self> {
+        statuses
+            .split(',')
+            .map(str::trim)
+            .filter(|status| !status.is_empty())
+            .map(Self::parse)
+            .collect()
+    }
+
+    /// Statuses verifiers should accept unless configured otherwise: every known status but `Revoked`.
+    pub fn default_allowed() -> Vec<Self> {
+        vec![
+            Self::UpToDate,
+            Self::SwHardeningNeeded,
+            Self::ConfigurationNeeded,
+            Self::ConfigurationAndSwHardeningNeeded,
+            Self::OutOfDate,
+            Self::OutOfDateConfigurationNeeded,
+        ]
+    }
+
+    pub fn is_up_to_date(&self) -> bool {
+        *self == Self::UpToDate
+    }
+}
+
+/// Measurements of the TD that generated the quote.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TdMeasurements {
+    /// Initial TD contents, i.e the firmware.
+    pub mr_td: [u8; 48],
+    pub rtmrs: [[u8; 48]; 4],
+    pub mr_config_id: [u8; 48],
+    pub mr_owner: [u8; 48],
+    pub mr_owner_config: [u8; 48],
+}
+
+pub trait VerifiedQuote {
+    /// The full 64 bytes of report data, see [`crate::ReportData::parse`].
+    fn report_data(&self) -> anyhow::Result<[u8; 64]>;
+
+    fn measurements(&self) -> anyhow::Result<TdMeasurements>;
+
+    /// `None` when the backend doesn't report it (e.g quotes that were only parsed by a remote service).
+    fn tcb_status(&self) -> Option<TcbStatus>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tcb_status() {
+        assert!(TcbStatus::parse("UpToDate").is_up_to_date());
+        assert_eq!(
+            TcbStatus::parse("SWHardeningNeeded"),
+            TcbStatus::SwHardeningNeeded
+        );
+        assert_eq!(
+            TcbStatus::parse("Unknown"),
+            TcbStatus::Other("Unknown".into())
+        );
+        assert_eq!(
+            TcbStatus::parse_list("UpToDate, SWHardeningNeeded,"),
+            vec![TcbStatus::UpToDate, TcbStatus::SwHardeningNeeded]
+        );
+        assert!(!TcbStatus::default_allowed().contains(&TcbStatus::Revoked));
+        for status in TcbStatus::default_allowed() {
+            assert_eq!(TcbStatus::parse(status.name()), status);
+        }
+    }
+}
@@ -0,0 +1,42 @@
+#!/bin/bash
+
+# Runs a 3-node cluster on this machine with the local coordination backend and mock attestation. Node N's host
+# listens on 800N and its guest on 303N, logs are written to $DIR/{host,guest}-N.log.
+
+set -e
+
+DIR="${1:-$(mktemp -d)}"
+CLUSTER_FILE="$DIR/cluster.jsonl"
+NODES=3
+
+cargo build --release --bin host --bin guest
+trap 'kill $(jobs -p) 2>/dev/null' EXIT
+
+start_node() {
+  local node="$1"
+  local pubkey="$2"
+
+  LOCAL_CLUSTER="$CLUSTER_FILE" ONBOARD_CURSOR="$DIR/cursor-$node.json" PORT="800$node" GUEST="localhost:303$node" \
+    ../target/release/host > "$DIR/host-$node.log" 2>&1 &
+  env ${pubkey:+PUBKEY="$pubkey"} LOCAL_CLUSTER="$CLUSTER_FILE" MOCK_ATTESTATION=1 PORT="303$node" HOST="localhost:800$node" \
+    ../target/release/guest > "$DIR/guest-$node.log" 2>&1 &
+}
+
+echo "Cluster events are written to $CLUSTER_FILE"
+start_node 0 ""
+
+until grep -q '"event":"boot"' "$CLUSTER_FILE" 2>/dev/null; do
+  sleep 1
+done
+SHARED_PUBKEY=$(grep -o '"shared_pubkey":"[0-9a-f]*"' "$CLUSTER_FILE" | cut -d'"' -f4)
+echo "Bootstrapped with shared pubkey $SHARED_PUBKEY"
+
+for node in $(seq 1 $((NODES - 1))); do
+  start_node "$node" "$SHARED_PUBKEY"
+done
+
+until [ "$(grep -c '"event":"onboard"' "$CLUSTER_FILE")" -ge $((NODES - 1)) ]; do
+  sleep 1
+done
+echo "All nodes onboarded, check the guests' logs for their secret. Press ctrl-c to stop the cluster."
+wait
@@ -1,7 +1,8 @@
 use std::{env, sync::Arc};
 
 use dstack_core::{guest_paths, GuestServiceInner};
-use new_york::GuestServices;
+use dummy_attestation::{Attestation, MockAttestation};
+use new_york::{Coordination, GuestAttestation, GuestServices, LocalCoordination, Stellar};
 use warp::Filter;
 
 // Note: as you'll notice, the pattern for setting the secret is really bad, will have to find a good way to deal
@@ -11,14 +12,70 @@ use warp::Filter;
 #[tokio::main]
 async fn main() {
     // NB: depending on what your requirements around measurements are you might need to hardcode these as build vars.
-    let cluster_string = env::var("CLUSTER").unwrap();
-    let maybe_expected_shared_pubkey = env::var("PUBKEY");
-    
-    let cluster_contract = stellar_strkey::Contract::from_string(&cluster_string)
-        .unwrap()
-        .0;
+    // With LOCAL_CLUSTER the cluster is coordinated through that file instead (see `LocalCoordination`) and CLUSTER
+    // is optional.
+    let local_cluster = env::var("LOCAL_CLUSTER");
+    let cluster_contract = match env::var("CLUSTER") {
+        Ok(cluster_string) => {
+            stellar_strkey::Contract::from_string(&cluster_string)
+                .unwrap()
+                .0
+        }
+        Err(_) if local_cluster.is_ok() => [0; 32],
+        Err(e) => panic!("CLUSTER: {}", e),
+    };
+
+    match local_cluster {
+        Ok(path) => with_attestation(cluster_contract, LocalCoordination::file(path)).await,
+        Err(_) => match Stellar::read_only(cluster_contract) {
+            Ok(stellar) => with_attestation(cluster_contract, stellar).await,
+            Err(e) => {
+                eprintln!("Couldn't start the guest: {:?}", e);
+                std::process::exit(1);
+            }
+        },
+    }
+}
+
+// NB: MOCK_ATTESTATION makes quotes forgeable, it's only meant for local clusters. TDX_ATTESTATION verifies quotes
+// locally against DCAP collateral (see `tdx_attestation::collateral`) instead of through the attestation service.
+async fn with_attestation<C: Coordination + 'static>(cluster_contract: [u8; 32], coordination: C) {
+    if env::var("MOCK_ATTESTATION").is_ok() {
+        run(GuestServices::with_helpers(
+            cluster_contract,
+            coordination,
+            MockAttestation::new(),
+        ))
+        .await
+    } else if env::var("TDX_ATTESTATION").is_ok() {
+        // A misconfigured collateral provider is fatal, a missing quote provider only matters once we need a quote.
+        let attestation = tdx_attestation::Attestation::try_new().unwrap();
+        println!(
+            "Using {} quote provider",
+            attestation.quote_provider().name()
+        );
+        run(GuestServices::with_helpers(
+            cluster_contract,
+            coordination,
+            attestation,
+        ))
+        .await
+    } else {
+        run(GuestServices::with_helpers(
+            cluster_contract,
+            coordination,
+            Attestation::new(),
+        ))
+        .await
+    }
+}
 
-    let mut guest_internal = GuestServices::new(cluster_contract);
+async fn run<C, A>(mut guest_internal: GuestServices<C, A>)
+where
+    C: Coordination + 'static,
+    A: GuestAttestation + 'static,
+{
+    let maybe_expected_shared_pubkey = env::var("PUBKEY");
 
     // if operator infers PUBKEY then we want to join an already-bootstrapped cluster.
     // else we want to be bootstrapping the cluster ourselves (replay protection should be onchain).
@@ -31,26 +88,43 @@ async fn main() {
         guest_internal.set_expected_public(bytes).await;
     }
 
+    // NB: like the rest of the config, the policy should be part of the measured pod config, otherwise the host
+    // could swap it for a permissive one.
+    if let Ok(policy_path) = env::var("EVENTLOG_POLICY") {
+        let policy = serde_json::from_slice(&std::fs::read(policy_path).unwrap()).unwrap();
+        guest_internal.set_event_log_policy(policy);
+    }
+
+    #[cfg(feature = "tdx")]
+    guest_internal.measure_config().await.unwrap();
+
+    let port = env::var("PORT").map_or(3030, |port| port.parse().unwrap());
     let threadsafe = Arc::new(guest_internal);
     let replication_reference = threadsafe.clone();
-    
+
+    // NB: without the secret the guest is useless, so failing to join (or bootstrap) is fatal.
     let handle_replication = tokio::spawn(async move {
-        replication_reference.replicate_thread().await
+        if let Err(e) = replication_reference.replicate_thread().await {
+            eprintln!("Replication failed: {:?}", e);
+            std::process::exit(1);
+        }
     });
 
-    let guest_paths: guest_paths::GuestPaths<GuestServices> =
+    let guest_paths: guest_paths::GuestPaths<GuestServices<C, A>> =
         guest_paths::GuestPaths::new(threadsafe);
 
     let _ = tokio::join!(
         handle_replication,
         warp::serve(
-            guest_paths.onboard_new_node()
-            .or(guest_paths.status())
-            // NB: this endpoint is sensitive since it allows anyone who can reach it to construct a valid shared key. 
-            // It's important the implementor makes sure that this connection is only available within the deployed pod. 
-            // This allows for the quote to hold the measurements of the expected pod config and prevents new pods or 
-            // the host environment to retrieve the shared secret.
-            .or(guest_paths.get_derived_key()))
-            .run(([0, 0, 0, 0], 3030)),
+            guest_paths
+                .onboard_new_node()
+                .or(guest_paths.status())
+                // NB: this endpoint is sensitive since it allows anyone who can reach it to construct a valid shared key.
+                // It's important the implementor makes sure that this connection is only available within the deployed pod.
+                // This allows for the quote to hold the measurements of the expected pod config and prevents new pods or
+                // the host environment to retrieve the shared secret.
+                .or(guest_paths.get_derived_key())
+        )
+        .run(([0, 0, 0, 0], port)),
     );
 }
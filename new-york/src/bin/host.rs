@@ -1,11 +1,37 @@
 use std::{env, sync::Arc};
 
 use dstack_core::{host_paths, HostServiceInner};
-use new_york::HostServices;
+use new_york::{Coordination, HostServices, LocalCoordination};
 use warp::Filter;
 
+// Note: with LOCAL_CLUSTER set to a file path the cluster is coordinated through that file instead of Stellar (see
+// `LocalCoordination`), so CLUSTER and SECRET aren't needed.
+
 #[tokio::main]
 async fn main() {
+    let port = env::var("PORT").map_or(8000, |port| port.parse().unwrap());
+    let local_cluster = env::var("LOCAL_CLUSTER");
+
+    // NB: ENDPOINT is posted with our guest's registration for other members to fetch its event log from, so it has
+    // to be reachable from their machines. Only local clusters default to this one.
+    let endpoint = match env::var("ENDPOINT") {
+        Ok(endpoint) => endpoint,
+        Err(_) if local_cluster.is_ok() => format!("localhost:{}", port),
+        Err(e) => panic!("ENDPOINT: {}", e),
+    };
+
+    if let Ok(local_cluster) = local_cluster {
+        let mut host = HostServices::with_coordination(
+            LocalCoordination::file(local_cluster).with_host(endpoint.clone()),
+            endpoint,
+        )
+        .unwrap_or_else(fail);
+        // Every member runs on this machine.
+        host.allow_private_endpoints = true;
+        run(host, port).await;
+        return;
+    }
+
     let cluster_string = env::var("CLUSTER").unwrap();
     let cluster_contract = stellar_strkey::Contract::from_string(&cluster_string)
         .unwrap()
@@ -15,7 +41,17 @@ async fn main() {
         .unwrap()
         .0;
 
-    let host_internal = HostServices::new(cluster_contract, stellar_secret);
+    let host = HostServices::new(cluster_contract, stellar_secret, endpoint).unwrap_or_else(fail);
+    run(host, port).await;
+}
+
+/// Reports why the host couldn't start, e.g a malformed setting.
+fn fail(error: anyhow::Error) -> ! {
+    eprintln!("Couldn't start the host: {:?}", error);
+    std::process::exit(1)
+}
+
+async fn run<C: Coordination + 'static>(host_internal: HostServices<C>, port: u16) {
     let threadsafe = Arc::new(host_internal);
 
     // Note: differently from the guest replicatoor thread which needs to recover the shared
@@ -29,8 +65,10 @@ async fn main() {
             host_paths
                 .bootstrap()
                 .or(host_paths.register())
+                .or(host_paths.event_log())
+                .or(host_paths.challenge())
                 .or(host_paths.status())
         )
-        .run(([0, 0, 0, 0], 8000))
+        .run(([0, 0, 0, 0], port))
     );
 }
@@ -0,0 +1,94 @@
+use std::env;
+
+use cc_eventlog::{EventLogPolicy, EventLogs, EventRule, RUNTIME_RTMR};
+use new_york::GuestServices;
+use tdx_measure::ImageInputs;
+
+// Computes the measurements of a new-york guest ahead of deployment and writes a policy pinning them.
+//
+// The guest config is read from the same env variables as the guest (CLUSTER, PUBKEY, HOST, EVENTLOG_POLICY
+// and ALLOWED_TCB_STATUSES), the image from FIRMWARE, KERNEL, INITRD and CMDLINE. REFERENCE_CCEL is the CCEL of a
+// reference boot of the same VM config, needed for RTMR0. The policy is written to OUTPUT or printed, the printed
+// RTMR3 is the one of a guest running with that policy (i.e with EVENTLOG_POLICY pointing to OUTPUT).
+
+fn read_optional(var: &str) -> Option<Vec<u8>> {
+    env::var(var).ok().map(|path| std::fs::read(path).unwrap())
+}
+
+#[tokio::main]
+async fn main() {
+    let cluster_string = env::var("CLUSTER").unwrap();
+    let cluster_contract = stellar_strkey::Contract::from_string(&cluster_string)
+        .unwrap()
+        .0;
+
+    let mut guest_internal = GuestServices::new(cluster_contract).unwrap();
+    if let Ok(expected_shared_pubkey) = env::var("PUBKEY") {
+        let bytes = hex::decode(expected_shared_pubkey)
+            .unwrap()
+            .try_into()
+            .unwrap();
+        guest_internal.set_expected_public(bytes).await;
+    }
+
+    let mut policy = EventLogPolicy::default();
+    if let Ok(policy_path) = env::var("EVENTLOG_POLICY") {
+        policy = serde_json::from_slice(&std::fs::read(policy_path).unwrap()).unwrap();
+    }
+
+    let mut inputs = ImageInputs {
+        firmware: read_optional("FIRMWARE"),
+        reference_event_logs: read_optional("REFERENCE_CCEL").map(|ccel| {
+            EventLogs::decode(&mut ccel.as_slice())
+                .unwrap()
+                .into_tdx_event_logs()
+                .unwrap()
+        }),
+        kernel: read_optional("KERNEL"),
+        initrd: read_optional("INITRD"),
+        cmdline: env::var("CMDLINE").ok(),
+        runtime_events: vec![],
+    };
+
+    // NB: RTMR3 covers the config, which includes the policy, so it can't be pinned by the policy itself. The
+    // cluster contract is pinned through a rule instead, the shared pubkey differs between the bootstrapper
+    // and the other nodes.
+    inputs
+        .measure()
+        .unwrap()
+        .apply_to(&mut policy, RUNTIME_RTMR as usize);
+    policy.rules.retain(|rule| rule.event != "cluster-contract");
+    policy.rules.push(EventRule {
+        imr: RUNTIME_RTMR,
+        event: "cluster-contract".into(),
+        allowed_payloads: vec![hex::encode(
+            stellar_strkey::Contract(cluster_contract).to_string(),
+        )],
+        required: true,
+    });
+
+    // Guests run with the policy written out, so that's the one their config (and RTMR3) covers.
+    guest_internal.set_event_log_policy(policy.clone());
+    inputs.runtime_events = guest_internal
+        .config_events()
+        .await
+        .into_iter()
+        .map(|(name, payload)| (name.to_string(), payload))
+        .collect();
+
+    let measurements = inputs.measure().unwrap();
+    if let Some(mrtd) = measurements.mrtd {
+        eprintln!("mrtd: {}", hex::encode(mrtd));
+    }
+    for (index, rtmr) in measurements.rtmrs.iter().enumerate() {
+        if let Some(rtmr) = rtmr {
+            eprintln!("rtmr{}: {}", index, hex::encode(rtmr));
+        }
+    }
+
+    let policy = serde_json::to_string_pretty(&policy).unwrap();
+    match env::var("OUTPUT") {
+        Ok(path) => std::fs::write(path, policy).unwrap(),
+        Err(_) => println!("{}", policy),
+    }
+}
@@ -0,0 +1,280 @@
+//! Picks which members respond to a registration, so that a newcomer isn't onboarded by every member at once
+//! (each onboarding costs a transaction fee and adds an event the newcomer has to go through).
+//!
+//! Members are ranked by hashing the newcomer's pubkey with each member's (rendezvous hashing): every member computes
+//! the same order without talking to the others and newcomers are spread evenly across members. The first
+//! `replicas` members respond right away, the next ones only if the newcomer still isn't onboarded after waiting
+//! one more timeout each, which covers members that are down or whose guest refuses. Waits are capped at
+//! [`MAX_RESPONSE_DELAY`] though: the newcomer's quote goes stale after [`crate::CHALLENGE_LIFETIME`], so every
+//! backup has to step in before then.
+//!
+//! Note: the member set comes from the coordination layer, which is untrusted. Onboard messages have to be signed by
+//! a member (see [`crate::signing`]) but a lying layer can still list fake members, which only delays onboarding by
+//! a timeout.
+//!
+//! Note: a restarted guest comes back with a fresh keypair and registers again from the same host, so the old pubkey
+//! is dead but would stay ranked (adding a timeout to every newcomer it leads). [`members`] drops a member once its
+//! host registers another pubkey. Hosts are told apart by the account that posted the registration (the contract
+//! has it authorize the call) rather than by anything self-reported like the event log endpoint, and a pubkey belongs
+//! to the first host that registered it: registering someone else's pubkey (or the bootstrapper's) is ignored, so a
+//! host can only push out its own members. NB: the bootstrap event doesn't say where the bootstrapper runs, so a
+//! restarted bootstrapper's first pubkey stays.
+//!
+
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::ClusterEvent;
+
+/// Default number of members responding right away.
+pub const DEFAULT_REPLICAS: usize = 1;
+/// Default time a member waits for the ones ranked before it.
+pub const DEFAULT_TIMEOUT_SECS: u64 = 60;
+/// Longest a member waits whatever its rank and the timeout. Half of [`crate::CHALLENGE_LIFETIME`], which leaves
+/// the rest for the registration to be seen and the onboarding to go through.
+pub const MAX_RESPONSE_DELAY: Duration =
+    Duration::from_secs(crate::CHALLENGE_LIFETIME.as_secs() / 2);
+
+/// [`members`] in the order they should respond to [`newcomer`], which is left out.
+pub fn responders(newcomer: &[u8; 32], members: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    let mut ranked: Vec<([u8; 32], [u8; 32])> = members
+        .iter()
+        .filter(|member| *member != newcomer)
+        .map(|member| {
+            let mut hasher = Sha256::new();
+            hasher.update(newcomer);
+            hasher.update(member);
+            (hasher.finalize().into(), *member)
+        })
+        .collect();
+    ranked.sort();
+    ranked.dedup();
+
+    ranked.into_iter().map(|(_, member)| member).collect()
+}
+
+/// Members out of the cluster's [`events`], see [`Membership`].
+pub fn members(events: impl IntoIterator<Item = ClusterEvent>) -> Vec<[u8; 32]> {
+    let mut membership = Membership::default();
+    for event in events {
+        membership.apply(&event);
+    }
+
+    membership.members
+}
+
+/// Members as of the events [`Membership::apply`]'d so far, in the order they joined, leaving out the ones whose
+/// host (i.e the first account that registered them) registered again under another pubkey. Malformed pubkeys are
+/// skipped.
+#[derive(Default, Clone, Debug)]
+pub struct Membership {
+    members: Vec<[u8; 32]>,
+    /// Latest pubkey registered by each host.
+    hosts: HashMap<String, [u8; 32]>,
+    /// First host that registered each pubkey.
+    owners: HashMap<[u8; 32], String>,
+    replaced: HashSet<[u8; 32]>,
+}
+
+impl Membership {
+    pub fn apply(&mut self, event: &ClusterEvent) {
+        let decode =
+            |pubkey: &str| -> Option<[u8; 32]> { hex::decode(pubkey).ok()?.try_into().ok() };
+
+        match event {
+            ClusterEvent::Boot { shared_pubkey, .. } => {
+                if let Some(pubkey) = decode(shared_pubkey) {
+                    if !self.members.contains(&pubkey) {
+                        self.members.push(pubkey);
+                    }
+                }
+            }
+            ClusterEvent::Register { pubkey, host, .. } => {
+                let Some(pubkey) = decode(pubkey) else {
+                    return;
+                };
+                // Only the pubkey's own host gets to register it again, i.e to replace it later on.
+                match self.owners.get(&pubkey) {
+                    Some(owner) if owner != host => return,
+                    Some(_) => (),
+                    // Members nobody registered are bootstrappers.
+                    None if self.members.contains(&pubkey) => return,
+                    None => {
+                        self.owners.insert(pubkey, host.clone());
+                    }
+                }
+                if let Some(previous) = self.hosts.insert(host.clone(), pubkey) {
+                    if previous != pubkey {
+                        self.replaced.insert(previous);
+                        self.members.retain(|member| *member != previous);
+                    }
+                }
+            }
+            ClusterEvent::Onboard { pubkey, .. } => {
+                if let Some(pubkey) = decode(pubkey) {
+                    if !self.replaced.contains(&pubkey) && !self.members.contains(&pubkey) {
+                        self.members.push(pubkey);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn members(&self) -> &[[u8; 32]] {
+        &self.members
+    }
+}
+
+/// How long the member at [`rank`] waits before responding, at most [`MAX_RESPONSE_DELAY`].
+pub fn response_delay(rank: usize, replicas: usize, timeout: Duration) -> Duration {
+    let backups_before = (rank + 1).saturating_sub(replicas);
+    timeout
+        .saturating_mul(backups_before.try_into().unwrap_or(u32::MAX))
+        .min(MAX_RESPONSE_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranks_members_consistently() {
+        let members: Vec<[u8; 32]> = (0..5).map(|i| [i; 32]).collect();
+        let order = responders(&[9; 32], &members);
+        assert_eq!(order.len(), 5);
+
+        let mut shuffled = members.clone();
+        shuffled.reverse();
+        assert_eq!(responders(&[9; 32], &shuffled), order);
+
+        // The newcomer never responds to itself.
+        assert!(!responders(&[2; 32], &members).contains(&[2; 32]));
+
+        // Different newcomers get different leaders.
+        let leaders: Vec<[u8; 32]> = (10..30)
+            .map(|i| responders(&[i; 32], &members)[0])
+            .collect();
+        assert!(leaders.iter().any(|leader| *leader != leaders[0]));
+    }
+
+    fn register(pubkey: u8, host: &str) -> ClusterEvent {
+        ClusterEvent::Register {
+            pubkey: hex::encode([pubkey; 32]),
+            quote: "quote".into(),
+            event_log_hash: hex::encode([0; 48]),
+            event_log_endpoint: "localhost:8000".into(),
+            host: host.into(),
+        }
+    }
+
+    fn onboard(pubkey: u8) -> ClusterEvent {
+        ClusterEvent::Onboard {
+            pubkey: hex::encode([pubkey; 32]),
+            encrypted: "00".into(),
+            signature: "00".into(),
+        }
+    }
+
+    #[test]
+    fn drops_replaced_members() {
+        let boot = ClusterEvent::Boot {
+            shared_pubkey: hex::encode([0; 32]),
+            quote: "genesis".into(),
+            signing_pubkey: hex::encode([9; 32]),
+        };
+        let events = vec![
+            boot,
+            register(1, "host-a"),
+            onboard(1),
+            register(2, "host-b"),
+            onboard(2),
+            onboard(2),
+        ];
+        assert_eq!(members(events.clone()), vec![[0; 32], [1; 32], [2; 32]]);
+
+        // host-a's guest restarted with a new key, its old one is gone even if onboarded late.
+        let restarted = [events, vec![register(3, "host-a"), onboard(1), onboard(3)]].concat();
+        assert_eq!(members(restarted.clone()), vec![[0; 32], [2; 32], [3; 32]]);
+
+        // Registering the same key again doesn't drop it.
+        let again = [restarted, vec![register(3, "host-a")]].concat();
+        assert_eq!(members(again), vec![[0; 32], [2; 32], [3; 32]]);
+    }
+
+    #[test]
+    fn keeps_members_sharing_an_endpoint() {
+        let boot = ClusterEvent::Boot {
+            shared_pubkey: hex::encode([0; 32]),
+            quote: "genesis".into(),
+            signing_pubkey: hex::encode([9; 32]),
+        };
+        // Both hosts post the same (e.g default or spoofed) endpoint, only their accounts differ.
+        let events = vec![
+            boot,
+            register(1, "host-a"),
+            onboard(1),
+            register(2, "host-b"),
+            onboard(2),
+        ];
+        assert_eq!(members(events.clone()), vec![[0; 32], [1; 32], [2; 32]]);
+
+        // Someone else registering doesn't push a member out either.
+        let spoofed = [events, vec![register(3, "spammer")]].concat();
+        assert_eq!(members(spoofed), vec![[0; 32], [1; 32], [2; 32]]);
+    }
+
+    #[test]
+    fn ignores_registrations_of_others_pubkeys() {
+        let boot = ClusterEvent::Boot {
+            shared_pubkey: hex::encode([0; 32]),
+            quote: "genesis".into(),
+            signing_pubkey: hex::encode([9; 32]),
+        };
+        let events = vec![boot, register(1, "host-a"), onboard(1)];
+
+        // Registering a member's pubkey and then another one would replace it if the member were the spammer's.
+        let evict = [
+            events.clone(),
+            vec![register(1, "spammer"), register(3, "spammer")],
+        ]
+        .concat();
+        assert_eq!(members(evict), vec![[0; 32], [1; 32]]);
+        let evict_bootstrapper = [
+            events.clone(),
+            vec![register(0, "spammer"), register(3, "spammer")],
+        ]
+        .concat();
+        assert_eq!(members(evict_bootstrapper), vec![[0; 32], [1; 32]]);
+
+        // The member's own host still replaces it.
+        let restarted = [events, vec![register(1, "spammer"), register(2, "host-a")]].concat();
+        assert_eq!(members(restarted), vec![[0; 32]]);
+    }
+
+    #[test]
+    fn delays_backups() {
+        let timeout = Duration::from_secs(60);
+        assert_eq!(response_delay(0, 1, timeout), Duration::ZERO);
+        assert_eq!(response_delay(1, 1, timeout), timeout);
+        assert_eq!(response_delay(3, 1, timeout), timeout * 3);
+        assert_eq!(response_delay(1, 2, timeout), Duration::ZERO);
+        assert_eq!(response_delay(2, 2, timeout), timeout);
+    }
+
+    #[test]
+    fn backups_respond_before_the_quote_goes_stale() {
+        assert!(MAX_RESPONSE_DELAY < crate::CHALLENGE_LIFETIME);
+
+        let timeout = Duration::from_secs(DEFAULT_TIMEOUT_SECS);
+        assert_eq!(response_delay(20, 1, timeout), MAX_RESPONSE_DELAY);
+        assert_eq!(response_delay(1000, 1, timeout), MAX_RESPONSE_DELAY);
+        assert_eq!(
+            response_delay(1, 1, Duration::from_secs(3600)),
+            MAX_RESPONSE_DELAY
+        );
+    }
+}
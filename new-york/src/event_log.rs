@@ -0,0 +1,229 @@
+//! Event logs are shipped off-chain. A TDX event log is tens of kilobytes while Soroban caps transactions and events
+//! at a few, so registrations only post a sha384 commitment to the log along with where to fetch it, i.e the
+//! registering host which keeps the logs its guest registered with (see [`crate::HostServices`]).
+//!
+//! Onboarding members fetch the log and check it against the commitment before their guest replays it against the
+//! quote. The commitment doesn't make the log any more trustworthy (the replay does), but it pins the log the
+//! registering node meant so that whoever serves it can't swap it.
+//!
+//! Note: the endpoint is chosen by whoever registers, so fetching is kept on a short leash: connecting and fetching
+//! time out, redirects aren't followed, logs bigger than [`MAX_EVENT_LOG_SIZE`] are refused and so are endpoints
+//! resolving to loopback, link-local or private addresses (unless running a local cluster), so that registrations
+//! can't have members query their own network.
+//!
+//! NB: hosts serve a log until its node is onboarded, and persist it next to `ONBOARD_CURSOR` when set so that a
+//! restart doesn't strand the registration. A registration whose host went away for good can't be onboarded until
+//! the node registers again.
+//!
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context};
+use cc_eventlog::TdxEventLog;
+use dstack_core::{host_paths, Refused};
+use reqwest::{redirect, Url};
+use sha2::{Digest, Sha384};
+
+use crate::HostServices;
+
+/// How long connecting to the registering host may take.
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long fetching a log may take overall, the onboard thread handles registrations one after the other.
+pub const FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+/// Biggest log we download, real ones are tens of kilobytes.
+pub const MAX_EVENT_LOG_SIZE: usize = 4 * 1024 * 1024;
+
+/// What registrations post instead of the event log.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EventLogRef {
+    /// sha384 of the JSON-encoded log, see [`encode`].
+    pub sha384: [u8; 48],
+    /// Host endpoint the log is served at.
+    pub endpoint: String,
+}
+
+/// The bytes a log is committed to, which are also what hosts serve.
+pub fn encode(event_log: &[TdxEventLog]) -> anyhow::Result<(Vec<u8>, [u8; 48])> {
+    let encoded = serde_json::to_vec(event_log)?;
+    let sha384 = Sha384::digest(&encoded).into();
+
+    Ok((encoded, sha384))
+}
+
+/// Whether [`ip`] is reachable from anywhere, i.e not loopback, link-local, private (including IPv6 unique local
+/// and carrier-grade NAT addresses), unspecified, broadcast or documentation.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            let shared = first == 100 && second & 0xc0 == 64;
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || shared)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(ip.into()),
+            None => {
+                let unique_local = ip.segments()[0] & 0xfe00 == 0xfc00;
+                let link_local = ip.segments()[0] & 0xffc0 == 0xfe80;
+                !(ip.is_loopback() || ip.is_unspecified() || unique_local || link_local)
+            }
+        },
+    }
+}
+
+/// Resolves the host of [`url`], refusing non-public addresses unless [`allow_private`]. The address is returned
+/// so that the request goes to the address that was checked.
+async fn resolve(url: &Url, allow_private: bool) -> anyhow::Result<SocketAddr> {
+    let port = url.port_or_known_default().unwrap_or(80);
+    let host = url
+        .host_str()
+        .ok_or(anyhow!("{} has no host", url))
+        .context(Refused)?;
+    // NB: IPv6 hosts are bracketed.
+    let addr = match host.trim_matches(['[', ']']).parse::<IpAddr>() {
+        Ok(ip) => SocketAddr::new(ip, port),
+        Err(_) => tokio::net::lookup_host((host, port))
+            .await?
+            .next()
+            .ok_or(anyhow!("{} doesn't resolve", host))?,
+    };
+    if !allow_private && !is_public(addr.ip()) {
+        return Err(anyhow!("{} resolves to non-public address {}", url, addr)).context(Refused);
+    }
+
+    Ok(addr)
+}
+
+/// Fetches the event log [`pubkey`] registered with and checks it against [`reference`]. A log that doesn't match
+/// the commitment (or is too big) and endpoints we won't query are a [`Refused`] registration, failing to reach
+/// the host is worth retrying.
+///
+/// Note: [`allow_private`] lets the endpoint resolve to any address, e.g for local clusters.
+pub async fn fetch(
+    reference: &EventLogRef,
+    pubkey: [u8; 32],
+    allow_private: bool,
+) -> anyhow::Result<Vec<TdxEventLog>> {
+    let url = Url::parse(&format!("http://{}/event_log", reference.endpoint)).context(Refused)?;
+    let addr = resolve(&url, allow_private).await?;
+    let mut client = reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(FETCH_TIMEOUT)
+        .redirect(redirect::Policy::none());
+    if let Some(domain) = url.domain() {
+        client = client.resolve(domain, addr);
+    }
+
+    let mut response = client
+        .build()?
+        .post(url)
+        .json(&host_paths::requests::EventLogArgs::<HostServices> { pubkey })
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!(
+            "{} doesn't serve an event log for {}: {}",
+            reference.endpoint,
+            hex::encode(pubkey),
+            response.status()
+        ));
+    }
+
+    let mut encoded = vec![];
+    while let Some(chunk) = response.chunk().await? {
+        if encoded.len() + chunk.len() > MAX_EVENT_LOG_SIZE {
+            return Err(anyhow!(
+                "event log served by {} is over {} bytes",
+                reference.endpoint,
+                MAX_EVENT_LOG_SIZE
+            ))
+            .context(Refused);
+        }
+        encoded.extend_from_slice(&chunk);
+    }
+    if Sha384::digest(&encoded)[..] != reference.sha384 {
+        return Err(anyhow!(
+            "event log served by {} doesn't match the registration's commitment",
+            reference.endpoint
+        ))
+        .context(Refused);
+    }
+
+    // The registering node committed to these bytes, so a log that doesn't parse won't get any better.
+    serde_json::from_slice(&encoded).context(Refused)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commits_to_served_bytes() {
+        let event_log = vec![TdxEventLog::new_str(3, 0x08000001, "app-id", "new-york")];
+        let (encoded, sha384) = encode(&event_log).unwrap();
+
+        // Hosts serve logs through warp's json reply, i.e the same encoding.
+        assert_eq!(encoded, serde_json::to_vec(&event_log).unwrap());
+        assert_eq!(sha384, <[u8; 48]>::from(Sha384::digest(&encoded)));
+        assert_ne!(sha384, encode(&[]).unwrap().1);
+    }
+
+    #[test]
+    fn only_public_addresses() {
+        for ip in [
+            "93.184.216.34",
+            "2606:2800:220:1::1",
+            "::ffff:93.184.216.34",
+        ] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.0.0.1",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn refuses_private_endpoints() {
+        let reference = |endpoint: &str| EventLogRef {
+            sha384: [0; 48],
+            endpoint: endpoint.into(),
+        };
+        for endpoint in [
+            "127.0.0.1:8000",
+            "[::1]:8000",
+            "169.254.169.254",
+            "localhost:8000",
+        ] {
+            let error = fetch(&reference(endpoint), [1; 32], false)
+                .await
+                .unwrap_err();
+            assert!(Refused::is(&error), "{}: {:?}", endpoint, error);
+        }
+
+        // Local clusters may, nothing listens there though so it's worth retrying.
+        let error = fetch(&reference("127.0.0.1:1"), [1; 32], true)
+            .await
+            .unwrap_err();
+        assert!(!Refused::is(&error));
+    }
+}
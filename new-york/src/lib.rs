@@ -6,63 +6,467 @@
 //! shared secret. The only thing this implementaion will be checking against is probably that the secret corresponds to the public key
 //! likely set as an env variable. We also infer at start time if the cluster contract was bootstrapped or not.
 //!
-use anyhow::anyhow;
+use anyhow::{anyhow, Context};
 use async_trait::async_trait;
+use cc_eventlog::{EventLogPolicy, TdxEventLog};
 use diffie_hellman::Crypto;
 use dstack_core::{
-    guest_paths, host_paths, GuestServiceInner, HostServiceInner, InnerAttestationHelper,
-    InnerCryptoHelper, TdxOnlyGuestServiceInner,
+    guest_paths, host_paths, CoordinationLayer, GuestServiceInner, HostServiceInner,
+    InnerAttestationHelper, InnerCryptoHelper, Purpose, Refused, Registration, ReportData,
+    TcbStatus, TdxOnlyGuestServiceInner, VerifiedQuote,
 };
 use dummy_attestation::Attestation;
+use reqwest::StatusCode;
 use sha2::{Digest, Sha256};
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use stellar::get_onboarded;
+use std::{
+    collections::HashMap,
+    future::Future,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, Instant},
+};
 use tokio::{sync::Mutex, time::sleep};
 
+mod election;
+mod event_log;
+mod local;
+mod signing;
 mod stellar;
 
+pub use event_log::EventLogRef;
+pub use local::{ClusterEvent, LocalCoordination};
+pub use signing::SignedMessage;
+pub use stellar::{LedgerChallenge, Stellar, StellarNetwork};
+
 // NOTE: just for ease.
 const NONCE: [u8; 12] = [0; 12];
 
-// TODO change types depending on the chain we're posting to.
-pub struct HostServices {
-    pub contract: [u8; 32],
-    pub secret: [u8; 32],
+/// How many ledgers old (~5 seconds each) the challenge bound into a register quote can be. Quotes bound to
+/// older ledgers are rejected, so a node that didn't get onboarded within this window has to register again.
+pub const MAX_CHALLENGE_AGE_LEDGERS: u32 = 120;
+
+/// Roughly how long a challenge stays fresh, i.e [`MAX_CHALLENGE_AGE_LEDGERS`] ledgers of about 5 seconds.
+pub const CHALLENGE_LIFETIME: Duration = Duration::from_secs(MAX_CHALLENGE_AGE_LEDGERS as u64 * 5);
+
+/// How many times the guest tries each step of joining the cluster before giving up, see [`with_backoff`].
+pub const JOIN_ATTEMPTS: u32 = 8;
+
+/// Runs [`step`] until it succeeds, waiting twice as long after each failure (starting at a second). Gives up with
+/// the last error after [`JOIN_ATTEMPTS`], i.e about four minutes.
+async fn with_backoff<T, Fut>(what: &str, mut step: impl FnMut() -> Fut) -> anyhow::Result<T>
+where
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    let mut delay = Duration::from_secs(1);
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        match step().await {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt >= JOIN_ATTEMPTS => {
+                return Err(e.context(format!("couldn't {} after {} attempts", what, attempt)))
+            }
+            Err(e) => {
+                eprintln!("Couldn't {}, retrying in {:?}: {:?}", what, delay, e);
+                sleep(delay).await;
+                delay *= 2;
+            }
+        }
+    }
+}
+
+/// Coordination layers new-york can run on, i.e the ones dealing with the same types as the host and guest
+/// services. See [`Stellar`].
+pub trait Coordination:
+    CoordinationLayer<
+        Quote = String,
+        Pubkey = [u8; 32],
+        EventLog = EventLogRef,
+        EncryptedMessage = SignedMessage,
+        Challenge = LedgerChallenge,
+    > + Send
+    + Sync
+{
+}
+
+impl<T> Coordination for T where
+    T: CoordinationLayer<
+            Quote = String,
+            Pubkey = [u8; 32],
+            EventLog = EventLogRef,
+            EncryptedMessage = SignedMessage,
+            Challenge = LedgerChallenge,
+        > + Send
+        + Sync
+{
+}
+
+/// Attestation helpers the guest can use, e.g [`Attestation`] or [`dummy_attestation::MockAttestation`] for local
+/// clusters.
+pub trait GuestAttestation:
+    InnerAttestationHelper<Appdata = ReportData, Quote = String> + Send + Sync
+{
+}
+
+impl<T> GuestAttestation for T where
+    T: InnerAttestationHelper<Appdata = ReportData, Quote = String> + Send + Sync
+{
+}
+
+pub struct HostServices<C = Stellar> {
+    pub coordination: C,
+    /// Where our guest's host-facing paths are served.
+    pub guest_endpoint: String,
+    /// Where other members reach us, posted with our guest's registration to fetch its event log from. Must be
+    /// reachable from the other members' machines.
+    pub endpoint: String,
+    /// Whether other members' endpoints may resolve to loopback or private addresses, only for local clusters
+    /// (see [`event_log::fetch`]).
+    pub allow_private_endpoints: bool,
+    /// Where the onboard thread persists its cursor, it starts over from the first registration otherwise.
+    pub cursor_path: Option<PathBuf>,
+    /// How many members onboard a newcomer right away, see [`election`].
+    pub onboard_replicas: usize,
+    /// How long each backup member waits for the ones ranked before it.
+    pub onboard_timeout: Duration,
+    /// How many polls a registration can fail on (e.g its event log can't be fetched) before we give up on it and
+    /// move the cursor past it.
+    pub onboard_retries: u32,
+    /// Our guest's pubkey, i.e who we are among the members. Learnt from our guest's register or bootstrap request
+    /// (or `NODE_PUBKEY`), until then we only onboard as the last backup.
+    node_pubkey: Mutex<Option<[u8; 32]>>,
+    /// Where the learnt node pubkey is persisted, next to the cursor.
+    node_pubkey_path: Option<PathBuf>,
+    /// Event logs our guest registered with by pubkey, served to the other members until the pubkey is onboarded,
+    /// see [`event_log`].
+    event_logs: Mutex<HashMap<[u8; 32], Vec<TdxEventLog>>>,
+    /// Where the served event logs are persisted, next to the cursor.
+    event_logs_path: Option<PathBuf>,
+}
+
+type PendingRegistration = Registration<String, [u8; 32], EventLogRef>;
+
+/// Default [`HostServices::onboard_retries`], i.e about ten minutes of polls which is also about as long as a
+/// registration's challenge stays fresh (see [`MAX_CHALLENGE_AGE_LEDGERS`]).
+pub const DEFAULT_ONBOARD_RETRIES: u32 = 40;
+
+/// A registration the onboard thread isn't done with yet.
+struct PendingOnboard<Cursor> {
+    position: Cursor,
+    registration: PendingRegistration,
+    /// When we first saw it, see [`election::response_delay`].
+    seen: Instant,
+    /// Polls it failed on so far.
+    failures: u32,
+}
+
+/// What the onboard thread keeps between polls.
+struct OnboardState<Cursor> {
+    /// Persisted position, every registration up to it is done with.
+    cursor: Option<Cursor>,
+    /// Position registrations were fetched up to.
+    fetched: Option<Cursor>,
+    /// Registrations fetched but not done with yet, in order.
+    pending: Vec<PendingOnboard<Cursor>>,
+}
+
+/// Parses the [`name`] environment variable, [`default`] if it isn't set.
+pub(crate) fn env_or<T>(name: &str, default: T) -> anyhow::Result<T>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse()
+            .with_context(|| format!("invalid {} {:?}", name, value)),
+        Err(_) => Ok(default),
+    }
+}
+
+/// Hex-encoded pubkeys.
+pub(crate) fn decode_pubkey(pubkey: &str) -> anyhow::Result<[u8; 32]> {
+    hex::decode(pubkey)?
+        .try_into()
+        .map_err(|_| anyhow!("invalid pubkey {}", pubkey))
+}
+
+/// Written aside and renamed so a crash never leaves a truncated file behind.
+fn write_file(path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    std::fs::write(&tmp, contents)?;
+    std::fs::rename(tmp, path)?;
+
+    Ok(())
 }
 
 impl HostServices {
-    pub fn new(contract: [u8; 32], secret: [u8; 32]) -> Self {
-        Self { contract, secret }
+    pub fn new(contract: [u8; 32], secret: [u8; 32], endpoint: String) -> anyhow::Result<Self> {
+        Self::with_coordination(Stellar::new(contract, secret)?, endpoint)
     }
 }
 
-macro_rules! continue_on_err {
-    ($expr:expr) => {
-        match $expr {
-            Ok(val) => val,
-            Err(e) => {
-                eprintln!("Error: {:?}", e);
-                continue;
+impl<C: Coordination> HostServices<C> {
+    /// [`endpoint`] is where other members fetch our guest's event log from, see [`HostServices::endpoint`].
+    ///
+    /// Fails on malformed settings or persisted state (e.g the node pubkey or event logs next to the cursor) rather
+    /// than starting with defaults.
+    pub fn with_coordination(coordination: C, endpoint: String) -> anyhow::Result<Self> {
+        let guest_endpoint = std::env::var("GUEST").unwrap_or("localhost:3030".into());
+        let cursor_path = std::env::var("ONBOARD_CURSOR").ok().map(PathBuf::from);
+        let onboard_replicas = env_or("ONBOARD_REPLICAS", election::DEFAULT_REPLICAS)?;
+        let onboard_timeout =
+            Duration::from_secs(env_or("ONBOARD_TIMEOUT", election::DEFAULT_TIMEOUT_SECS)?);
+        let onboard_retries = env_or("ONBOARD_RETRIES", DEFAULT_ONBOARD_RETRIES)?;
+        let node_pubkey_path = cursor_path.as_deref().map(Self::node_pubkey_path);
+        let node_pubkey = match (std::env::var("NODE_PUBKEY"), &node_pubkey_path) {
+            (Ok(pubkey), _) => Some(decode_pubkey(pubkey.trim()).context("invalid NODE_PUBKEY")?),
+            (Err(_), Some(path)) => match std::fs::read_to_string(path) {
+                Ok(pubkey) => Some(
+                    decode_pubkey(pubkey.trim())
+                        .with_context(|| format!("invalid node pubkey in {}", path.display()))?,
+                ),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => {
+                    return Err(e).with_context(|| format!("couldn't read {}", path.display()))
+                }
+            },
+            (Err(_), None) => None,
+        };
+        let event_logs_path = cursor_path.as_deref().map(Self::event_logs_path);
+        let event_logs = match &event_logs_path {
+            Some(path) => Self::load_event_logs(path)
+                .with_context(|| format!("couldn't load event logs from {}", path.display()))?,
+            None => HashMap::new(),
+        };
+        Ok(Self {
+            coordination,
+            guest_endpoint,
+            endpoint,
+            allow_private_endpoints: false,
+            cursor_path,
+            onboard_replicas,
+            onboard_timeout,
+            onboard_retries,
+            node_pubkey: Mutex::new(node_pubkey),
+            node_pubkey_path,
+            event_logs: Mutex::new(event_logs),
+            event_logs_path,
+        })
+    }
+
+    /// The node pubkey is kept next to the cursor, so that a restarted host still knows which member it is.
+    fn node_pubkey_path(cursor_path: &Path) -> PathBuf {
+        cursor_path.with_extension("node")
+    }
+
+    /// Served event logs are kept next to the cursor too, so that a restarted host still serves them.
+    fn event_logs_path(cursor_path: &Path) -> PathBuf {
+        cursor_path.with_extension("event_logs")
+    }
+
+    /// Logs are stored by hex-encoded pubkey.
+    fn load_event_logs(path: &Path) -> anyhow::Result<HashMap<[u8; 32], Vec<TdxEventLog>>> {
+        let bytes = match std::fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let stored: HashMap<String, Vec<TdxEventLog>> = serde_json::from_slice(&bytes)?;
+        stored
+            .into_iter()
+            .map(|(pubkey, event_log)| Ok((decode_pubkey(&pubkey)?, event_log)))
+            .collect()
+    }
+
+    fn save_event_logs(&self, event_logs: &HashMap<[u8; 32], Vec<TdxEventLog>>) {
+        let Some(path) = &self.event_logs_path else {
+            return;
+        };
+        let stored: HashMap<String, &Vec<TdxEventLog>> = event_logs
+            .iter()
+            .map(|(pubkey, event_log)| (hex::encode(pubkey), event_log))
+            .collect();
+        if let Err(e) = serde_json::to_vec(&stored)
+            .map_err(Into::into)
+            .and_then(|bytes| write_file(path, &bytes))
+        {
+            eprintln!("Couldn't persist event logs: {:?}", e);
+        }
+    }
+
+    /// Stops serving [`pubkey`]'s event log once it's onboarded, nobody needs it anymore.
+    async fn forget_event_log(&self, pubkey: &[u8; 32]) {
+        let mut event_logs = self.event_logs.lock().await;
+        if event_logs.remove(pubkey).is_some() {
+            self.save_event_logs(&event_logs);
+        }
+    }
+
+    async fn set_node_pubkey(&self, pubkey: [u8; 32]) {
+        *self.node_pubkey.lock().await = Some(pubkey);
+        if let Some(path) = &self.node_pubkey_path {
+            if let Err(e) = write_file(path, hex::encode(pubkey).as_bytes()) {
+                eprintln!("Couldn't persist node pubkey: {:?}", e);
+            }
+        }
+    }
+
+    fn load_cursor(&self) -> anyhow::Result<Option<C::Cursor>> {
+        let Some(path) = &self.cursor_path else {
+            return Ok(None);
+        };
+        match std::fs::read(path) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn save_cursor(&self, cursor: &C::Cursor) -> anyhow::Result<()> {
+        if let Some(path) = &self.cursor_path {
+            write_file(path, &serde_json::to_vec(cursor)?)?;
+        }
+
+        Ok(())
+    }
+
+    fn advance(&self, cursor: &mut Option<C::Cursor>, position: C::Cursor) {
+        if let Err(e) = self.save_cursor(&position) {
+            eprintln!("Couldn't persist onboarding cursor: {:?}", e);
+        }
+        *cursor = Some(position);
+    }
+
+    /// Has our guest verify [`registration`] and posts the secret it encrypted to the new node. Registrations the
+    /// guest refuses (e.g an invalid or stale quote) are done with, errors are worth retrying.
+    ///
+    /// Note: the event log is fetched from the registering host and checked against the registration's commitment
+    /// first, see [`event_log`].
+    async fn onboard_registration(&self, registration: &PendingRegistration) -> anyhow::Result<()> {
+        let pubkey = registration.pubkey;
+        let event_log = match event_log::fetch(
+            &registration.event_log,
+            pubkey,
+            self.allow_private_endpoints,
+        )
+        .await
+        {
+            Ok(event_log) => event_log,
+            Err(e) if Refused::is(&e) => {
+                eprintln!(
+                    "Refusing to onboard {}, skipping: {:?}",
+                    hex::encode(pubkey),
+                    e
+                );
+                return Ok(());
             }
+            Err(e) => return Err(e),
+        };
+
+        // call tdx host-facing interface.
+        let response = reqwest::Client::new()
+            .post(format!("http://{}/onboard", self.guest_endpoint))
+            .json(&guest_paths::requests::OnboardArgs::<GuestServices> {
+                quote: registration.quote.clone(),
+                pubkeys: vec![pubkey],
+                event_log,
+            })
+            .send()
+            .await?;
+        let status = response.status();
+        let response: serde_json::Value = response.json().await?;
+        let error = response.get("error").cloned().unwrap_or_default();
+        // NB: only refusals are done with, other errors (e.g our guest has no secret yet or can't reach the chain)
+        // are retried on the next poll.
+        if status == StatusCode::FORBIDDEN {
+            eprintln!(
+                "Guest refused to onboard {}, skipping: {}",
+                hex::encode(pubkey),
+                error
+            );
+            return Ok(());
+        }
+        if !status.is_success() {
+            return Err(anyhow!(
+                "guest failed to onboard {} ({}): {}",
+                hex::encode(pubkey),
+                status,
+                error
+            ));
+        }
+
+        let message: <GuestServices as GuestServiceInner>::EncryptedMessage =
+            serde_json::from_value(response)?;
+        println!(
+            "Onboarding {} with encrypted message {}",
+            hex::encode(pubkey),
+            hex::encode(&message.encrypted)
+        );
+        self.coordination.onboard(pubkey, message).await?;
+        self.forget_event_log(&pubkey).await;
+
+        Ok(())
+    }
+
+    /// Onboards [`registration`] if it's our turn, see [`election`]. Returns whether it's done with, i.e the node
+    /// was onboarded (by anyone) or our guest refused it.
+    async fn handle_registration(
+        &self,
+        registration: &PendingRegistration,
+        members: &[[u8; 32]],
+        seen: Instant,
+    ) -> anyhow::Result<bool> {
+        let pubkey = registration.pubkey;
+        if !self.coordination.get_onboarding(&pubkey).await?.is_empty() {
+            println!("{} is already onboarded", hex::encode(pubkey));
+            self.forget_event_log(&pubkey).await;
+            return Ok(true);
         }
-    };
+
+        let responders = election::responders(&pubkey, members);
+        let node_pubkey = *self.node_pubkey.lock().await;
+        let rank = node_pubkey
+            .and_then(|node_pubkey| responders.iter().position(|member| *member == node_pubkey))
+            .unwrap_or(responders.len());
+        if seen.elapsed()
+            < election::response_delay(rank, self.onboard_replicas, self.onboard_timeout)
+        {
+            return Ok(false);
+        }
+
+        self.onboard_registration(registration).await?;
+        Ok(true)
+    }
 }
 
 #[async_trait]
-impl HostServiceInner for HostServices {
+impl<C: Coordination> HostServiceInner for HostServices<C> {
     type Pubkey = [u8; 32];
     type Quote = String;
     type Signature = Vec<u8>;
+    type Challenge = LedgerChallenge;
+    type EventLog = Vec<TdxEventLog>;
+    type Receipt = C::Receipt;
+
+    async fn get_challenge(&self) -> anyhow::Result<Self::Challenge> {
+        self.coordination.latest_challenge().await
+    }
 
+    /// [`pubkeys`] are the shared pubkey and the pubkey onboard messages are signed with, see [`signing`].
     async fn bootstrap(
         &self,
         quote: Self::Quote,
         pubkeys: Vec<Self::Pubkey>,
-    ) -> anyhow::Result<()> {
-        let shared_pubkey = pubkeys[0];
-        stellar::post_bootstrap(self.contract, self.secret, quote, shared_pubkey).await?;
-
-        Ok(())
+    ) -> anyhow::Result<C::Receipt> {
+        let signing_pubkey = *pubkeys
+            .get(1)
+            .ok_or(anyhow!("missing the cluster's signing pubkey"))?;
+        self.set_node_pubkey(pubkeys[0]).await;
+        self.coordination
+            .bootstrap(quote, pubkeys[0], signing_pubkey)
+            .await
     }
 
     async fn register(
@@ -70,83 +474,176 @@ impl HostServiceInner for HostServices {
         quote: Self::Quote,
         pubkeys: Vec<Self::Pubkey>,
         _signatures: Vec<Self::Signature>,
-    ) -> anyhow::Result<()> {
-        stellar::post_register(self.contract, self.secret, quote, &pubkeys[0]).await?;
+        event_log: Self::EventLog,
+    ) -> anyhow::Result<C::Receipt> {
+        self.set_node_pubkey(pubkeys[0]).await;
+        let (_, sha384) = event_log::encode(&event_log)?;
+        {
+            let mut event_logs = self.event_logs.lock().await;
+            event_logs.insert(pubkeys[0], event_log);
+            self.save_event_logs(&event_logs);
+        }
+        self.coordination
+            .register(
+                quote,
+                pubkeys[0],
+                EventLogRef {
+                    sha384,
+                    endpoint: self.endpoint.clone(),
+                },
+            )
+            .await
+    }
 
-        Ok(())
+    async fn event_log(&self, pubkey: Self::Pubkey) -> anyhow::Result<Option<Self::EventLog>> {
+        Ok(self.event_logs.lock().await.get(&pubkey).cloned())
     }
 
+    /// Note: the cursor only moves past a registration once it's done with (see
+    /// [`HostServices::handle_registration`]) along with every registration before it, and is persisted to
+    /// [`HostServices::cursor_path`]. So registrations posted while the host was down, that failed to be onboarded
+    /// or that we're a backup for are picked up again after a restart rather than skipped. Registrations that keep
+    /// failing are only given up on after [`HostServices::onboard_retries`] polls.
     async fn onboard_thread(&self) -> anyhow::Result<()> {
         println!("Onboarding thread started");
 
-        let mut last_processed = get_timestamp();
+        let cursor = self.load_cursor()?;
+        let mut state = OnboardState {
+            fetched: cursor.clone(),
+            cursor,
+            pending: vec![],
+        };
         loop {
             println!("Checking for new onboard requests ...");
-            if let Ok(current_pending) = stellar::get_pending(self.contract).await {
-                for pending in current_pending {
-                    if pending.at_time > last_processed {
-                        let quote = pending.quote;
-                        let pubkey = pending.pubkey;
-                        let pubkey_bytes = hex::decode(&pubkey)?.try_into().unwrap();
-
-                        // call tdx host-facing interface.
-                        let client = reqwest::Client::new();
-                        let resp = continue_on_err!(
-                            client
-                                .post("http://localhost:3030/onboard")
-                                .json(&guest_paths::requests::OnboardArgs::<GuestServices> {
-                                    quote,
-                                    pubkeys: vec![pubkey_bytes],
-                                })
-                                .send()
-                                .await
-                        );
-                        let message: <GuestServices as GuestServiceInner>::EncryptedMessage =
-                            continue_on_err!(resp.json().await);
-                        println!(
-                            "Onboarding {} with encrypted message {}",
-                            pubkey,
-                            hex::encode(&message)
-                        );
-                        continue_on_err!(
-                            stellar::post_onboard(
-                                self.contract,
-                                self.secret,
-                                message,
-                                &pubkey_bytes
-                            )
-                            .await
-                        );
-                    }
+            self.poll_registrations(&mut state).await;
+            sleep(Duration::from_secs(15)).await
+        }
+    }
+}
+
+impl<C: Coordination> HostServices<C> {
+    /// One round of the onboard thread: fetches new registrations and handles every pending one.
+    async fn poll_registrations(&self, state: &mut OnboardState<C::Cursor>) {
+        match self
+            .coordination
+            .pending_registrations(state.fetched.clone())
+            .await
+        {
+            Ok(page) => {
+                let seen = Instant::now();
+                state
+                    .pending
+                    .extend(page.items.into_iter().map(|(position, registration)| {
+                        PendingOnboard {
+                            position,
+                            registration,
+                            seen,
+                            failures: 0,
+                        }
+                    }));
+                if page.cursor.is_some() {
+                    state.fetched = page.cursor;
                 }
             }
+            Err(e) => eprintln!("Couldn't fetch pending registrations: {:?}", e),
+        }
 
-            last_processed = get_timestamp();
-            sleep(Duration::from_secs(15)).await
+        let members = match self.coordination.members().await {
+            Ok(members) => members,
+            Err(e) => {
+                eprintln!("Couldn't fetch cluster members: {:?}", e);
+                return;
+            }
+        };
+
+        let mut done = vec![];
+        for pending in state.pending.iter_mut() {
+            let pubkey = hex::encode(pending.registration.pubkey);
+            done.push(
+                match self
+                    .handle_registration(&pending.registration, &members, pending.seen)
+                    .await
+                {
+                    Ok(done) => done,
+                    Err(e) => {
+                        pending.failures += 1;
+                        if pending.failures >= self.onboard_retries {
+                            eprintln!(
+                                "Couldn't onboard {}, giving up after {} attempts: {:?}",
+                                pubkey, pending.failures, e
+                            );
+                            true
+                        } else {
+                            eprintln!(
+                                "Couldn't onboard {}, retrying on next poll: {:?}",
+                                pubkey, e
+                            );
+                            false
+                        }
+                    }
+                },
+            );
         }
+
+        match done.iter().position(|done| !done) {
+            None => {
+                if let Some(fetched) = state.fetched.clone() {
+                    self.advance(&mut state.cursor, fetched);
+                }
+            }
+            Some(0) => (),
+            Some(first_pending) => {
+                let position = state.pending[first_pending - 1].position.clone();
+                self.advance(&mut state.cursor, position);
+            }
+        }
+        let mut done = done.into_iter();
+        state.pending.retain(|_| !done.next().unwrap_or(false));
     }
 }
 
-pub struct GuestServices {
+pub struct GuestServices<C = Stellar, A = Attestation> {
     // Implementor's configs including helper objects.
     host_endpoint: String,
     cluster_contract: [u8; 32],
     shared_public: Mutex<Option<[u8; 32]>>,
     shared_secret: Mutex<Option<[u8; 32]>>,
-    attestation: Attestation,
+    event_log_policy: Option<EventLogPolicy>,
+    /// TCB statuses joining nodes' platforms may have, see [`TcbStatus::default_allowed`].
+    allowed_tcb_statuses: Vec<TcbStatus>,
+    attestation: A,
     crypto: Crypto,
+    coordination: C,
 }
 
 impl GuestServices {
-    pub fn new(cluster_contract: [u8; 32]) -> Self {
+    /// Fails if Stellar isn't configured properly, see [`Stellar::read_only`].
+    pub fn new(cluster_contract: [u8; 32]) -> anyhow::Result<Self> {
+        Ok(Self::with_helpers(
+            cluster_contract,
+            Stellar::read_only(cluster_contract)?,
+            Attestation::new(),
+        ))
+    }
+}
+
+impl<C: Coordination, A: GuestAttestation> GuestServices<C, A> {
+    pub fn with_helpers(cluster_contract: [u8; 32], coordination: C, attestation: A) -> Self {
         let host_address = std::env::var("HOST").unwrap_or("host.containers.internal:8000".into());
+        let allowed_tcb_statuses = std::env::var("ALLOWED_TCB_STATUSES")
+            .map_or(TcbStatus::default_allowed(), |statuses| {
+                TcbStatus::parse_list(&statuses)
+            });
         Self {
             host_endpoint: host_address,
             cluster_contract,
             shared_public: Mutex::new(None),
             shared_secret: Mutex::new(None),
-            attestation: Attestation::new(),
+            event_log_policy: None,
+            allowed_tcb_statuses,
+            attestation,
             crypto: Crypto::new(),
+            coordination,
         }
     }
 
@@ -154,17 +651,134 @@ impl GuestServices {
         *self.shared_public.lock().await = Some(public)
     }
 
+    /// When set, joining nodes must ship an event log that replays to their quote's RTMRs and satisfies
+    /// [`policy`]. Without a policy event logs are ignored.
+    pub fn set_event_log_policy(&mut self, policy: EventLogPolicy) {
+        self.event_log_policy = Some(policy)
+    }
+
+    /// Checks that the cluster was bootstrapped by a TD holding [`shared_pubkey`]'s secret: the bootstrap quote must
+    /// verify and bind [`shared_pubkey`] along with the signing pubkey the coordination layer checks onboard messages
+    /// against. Otherwise the host could have set a signing key of its own and posted whatever it wants for us.
+    ///
+    /// NB: the bootstrap challenge is long gone by the time nodes join, so the quote's freshness isn't checked.
+    pub async fn verify_bootstrap(&self, shared_pubkey: [u8; 32]) -> anyhow::Result<()> {
+        let bootstrap = self
+            .coordination
+            .get_bootstrap()
+            .await?
+            .ok_or(anyhow!("cluster isn't bootstrapped yet"))?;
+        if bootstrap.shared_pubkey != shared_pubkey {
+            return Err(anyhow!(
+                "cluster was bootstrapped with shared pubkey {}",
+                hex::encode(bootstrap.shared_pubkey)
+            ));
+        }
+
+        let verify = self.attestation.verify_quote(bootstrap.quote).await?;
+        if let Some(status) = verify.tcb_status() {
+            if !self.allowed_tcb_statuses.contains(&status) {
+                return Err(anyhow!(
+                    "bootstrapper's tcb status {:?} isn't allowed",
+                    status
+                ));
+            }
+        }
+        let got = ReportData::parse(&verify.report_data()?)?;
+        let expected = ReportData {
+            freshness: got.freshness,
+            ..ReportData::builder(Purpose::Bootstrap)
+                .cluster_id(self.cluster_contract)
+                .node_pubkey([shared_pubkey.as_slice(), &bootstrap.signing_pubkey].concat())
+                .build()
+        };
+        got.ensure_matches(&expected)
+            .context("bootstrap quote doesn't bind the cluster's pubkeys")?;
+
+        Ok(())
+    }
+
+    /// Gets a quote for [`purpose`] binding [`pubkeys`] to a fresh challenge from the host.
+    async fn fresh_quote(
+        &self,
+        client: &reqwest::Client,
+        purpose: Purpose,
+        pubkeys: &[u8],
+    ) -> anyhow::Result<String> {
+        // The challenge comes from the untrusted host, but nodes verifying our quote check on their own
+        // that the ledger is recent.
+        let challenge: LedgerChallenge = with_backoff("fetch a challenge", || async move {
+            Ok(client
+                .get(format!("http://{}/challenge", self.host_endpoint))
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?)
+        })
+        .await?;
+        let report_data = ReportData::builder(purpose)
+            .cluster_id(self.cluster_contract)
+            .node_pubkey(pubkeys.to_vec())
+            .freshness(hex::decode(&challenge.hash)?)
+            .build();
+
+        self.attestation.get_quote(report_data).await
+    }
+
+    /// The cluster contract, the expected shared pubkey (empty when bootstrapping) and the rest of the config,
+    /// in the order [`Self::measure_config`] measures them. Also used to compute the expected RTMR3 ahead of
+    /// deployment (see `bin/measure.rs`).
+    pub async fn config_events(&self) -> Vec<(&'static str, Vec<u8>)> {
+        let shared_public = self.shared_public.lock().await.map(hex::encode);
+        let config = serde_json::json!({
+            "host": self.host_endpoint,
+            "event_log_policy": self.event_log_policy,
+            "allowed_tcb_statuses": self
+                .allowed_tcb_statuses
+                .iter()
+                .map(TcbStatus::name)
+                .collect::<Vec<_>>(),
+        });
+
+        vec![
+            (
+                "cluster-contract",
+                stellar_strkey::Contract(self.cluster_contract)
+                    .to_string()
+                    .into_bytes(),
+            ),
+            (
+                "shared-pubkey",
+                shared_public.unwrap_or_default().into_bytes(),
+            ),
+            ("config", config.to_string().into_bytes()),
+        ]
+    }
+
+    /// Measures [`Self::config_events`] into RTMR3, so that they show up in our quote's event log. Must be called
+    /// before replicating.
+    #[cfg(feature = "tdx")]
+    pub async fn measure_config(&self) -> anyhow::Result<()> {
+        for (name, payload) in self.config_events().await {
+            tdx_attest::measure_event(name, &payload)?;
+        }
+
+        Ok(())
+    }
+
     pub async fn set_secret(&mut self, secret: [u8; 32]) {
         *self.shared_secret.lock().await = Some(secret)
     }
 }
 
 #[async_trait]
-impl GuestServiceInner for GuestServices {
+impl<C: Coordination, A: GuestAttestation> GuestServiceInner for GuestServices<C, A> {
     type Pubkey = [u8; 32];
-    type EncryptedMessage = Vec<u8>;
+    type EncryptedMessage = SignedMessage;
     type SharedKey = [u8; 32];
     type Quote = String;
+    type EventLog = Vec<TdxEventLog>;
 
     // Note: the implementor decides for themselves how they want the secret to be stored in
     // [`self`]
@@ -172,15 +786,17 @@ impl GuestServiceInner for GuestServices {
         self.shared_secret.lock().await.ok_or(anyhow!("").into())
     }
 
+    /// Network steps (fetching the challenge, verifying the bootstrap quote and posting through the host) are
+    /// retried with backoff, see [`with_backoff`].
+    ///
+    /// Members refuse register quotes once their challenge is older than [`MAX_CHALLENGE_AGE_LEDGERS`], so when
+    /// we aren't onboarded within [`CHALLENGE_LIFETIME`] we register again with a fresh quote (and the same pubkey,
+    /// so that onboard messages for the previous registration still count).
     async fn replicate_thread(&self) -> anyhow::Result<()> {
         println!("Replicating ...");
         let client = reqwest::Client::new();
 
         let (my_pubkey, my_secret) = self.crypto.get_keypair()?;
-        let quote = self
-            .attestation
-            .get_quote(my_pubkey.as_bytes().to_vec())
-            .await?;
         let shared_secret;
 
         // Note: whether to bootstrap is operator inferred not chain-inferred.
@@ -188,61 +804,118 @@ impl GuestServiceInner for GuestServices {
             let lock = self.shared_public.lock().await;
             lock.clone()
         };
-        
-        if maybe_pubkey.is_some() {
-            let expected_shared_pubkey_bytes = maybe_pubkey.unwrap();
+
+        // When bootstrapping our secret becomes the shared secret, the cluster's signing pubkey is bound into the
+        // quote along with the shared pubkey so that the host can't swap it for its own.
+        let signing_pubkey = signing::signing_pubkey(my_secret.as_bytes());
+        let (purpose, bound_pubkeys) = if maybe_pubkey.is_some() {
+            (Purpose::Register, my_pubkey.as_bytes().to_vec())
+        } else {
+            (
+                Purpose::Bootstrap,
+                [my_pubkey.as_bytes().as_slice(), &signing_pubkey].concat(),
+            )
+        };
+        let client = &client;
+
+        if let Some(expected_shared_pubkey_bytes) = maybe_pubkey {
             // We need to register
-            let request_onboard = client
-                .post(format!("http://{}/register", self.host_endpoint))
-                .json(&host_paths::requests::RegisterArgs::<HostServices> {
-                    quote,
-                    pubkeys: vec![my_pubkey.as_bytes().clone()],
-                    signatures: vec![],
+            // Note: the coordination layer is untrusted, but the bootstrap quote tells us whether the cluster is the
+            // one we expect and whether its signing key is only held by members.
+            with_backoff("verify the bootstrap quote", || {
+                self.verify_bootstrap(expected_shared_pubkey_bytes)
+            })
+            .await?;
+            println!("Bootstrap quote verified.");
+            // NB: the event log is untrusted on its own, verifiers replay it against our quote.
+            let event_log = cc_eventlog::read_event_logs().unwrap_or_else(|e| {
+                eprintln!("Couldn't read event log, registering without it: {:?}", e);
+                vec![]
+            });
+            let (event_log, my_pubkey) = (&event_log, &my_pubkey);
+            'register: loop {
+                // NB: measured from before fetching the challenge, so the registration is given up on a bit early
+                // rather than late.
+                let fetched = Instant::now();
+                let quote = &self.fresh_quote(client, purpose, &bound_pubkeys).await?;
+                let request_onboard = with_backoff("register", || async move {
+                    Ok(client
+                        .post(format!("http://{}/register", self.host_endpoint))
+                        .json(&host_paths::requests::RegisterArgs::<HostServices> {
+                            quote: quote.clone(),
+                            pubkeys: vec![my_pubkey.as_bytes().clone()],
+                            signatures: vec![],
+                            event_log: event_log.clone(),
+                        })
+                        .send()
+                        .await?
+                        .error_for_status()?
+                        .text()
+                        .await?)
                 })
-                .send()
-                .await?
-                .text()
                 .await?;
-            println!("Got response {}", request_onboard);
-            loop {
-                if let Ok(encrypted_encoded) =
-                    get_onboarded(self.cluster_contract, my_pubkey.as_bytes()).await
-                {
-                    // NOTE: this is bad rn because any malicious user can spam the comms network and
-                    // send invalid shared keys to prevent new nodes from joining. This is easily avoidable
-                    // with some extra code. It might also be good to abstract the public key checking.
-                    println!("Found encrypted message for this node, processing ...");
-                    let encrypted_raw = hex::decode(encrypted_encoded)?;
-                    let decrypted = self.crypto.decrypt_secret(
-                        NONCE,
-                        encrypted_raw,
-                        vec![expected_shared_pubkey_bytes.into()],
-                        vec![my_secret.clone()],
-                    )?;
-                    let shared_secret_bytes = decrypted.as_bytes();
-                    // note: we don't need to explicitly check the obtained shared secret because thanks to diffie
-                    // hellman constraints + TDX and replication guarantees (if the encrypted secret was not signed with the shared secret
-                    // then the decoding would fail due to a diff in the p2p shared secret, if it was signed by the secret
-                    // we know that it was a cluster-trusted TD so we know the message is indeed the encrypted shared secret).
-                    shared_secret = *shared_secret_bytes;
-                    break;
-                } else {
-                    println!("Didn't hear from cluster contract yet, waiting 5 seconds");
-                    sleep(Duration::from_secs(5)).await;
+                println!("Registered with receipt {}", request_onboard);
+                loop {
+                    let messages = self
+                        .coordination
+                        .get_onboarding(my_pubkey.as_bytes())
+                        .await
+                        .unwrap_or_else(|e| {
+                            eprintln!("Couldn't read onboard messages: {:?}", e);
+                            vec![]
+                        });
+                    // NOTE: the coordination layer should only take onboard messages signed by members, but it's
+                    // untrusted so we try every message until one decrypts rather than trusting the first one.
+                    let decrypted = messages.into_iter().find_map(|message| {
+                        println!("Found encrypted message for this node, processing ...");
+                        self.crypto
+                            .decrypt_secret(
+                                NONCE,
+                                message.encrypted,
+                                vec![expected_shared_pubkey_bytes.into()],
+                                vec![my_secret.clone()],
+                            )
+                            .map_err(|e| {
+                                eprintln!("Skipping message that doesn't decrypt: {:?}", e)
+                            })
+                            .ok()
+                    });
+                    if let Some(decrypted) = decrypted {
+                        // note: we don't need to explicitly check the obtained shared secret because thanks to diffie
+                        // hellman constraints + TDX and replication guarantees (if the encrypted secret was not signed with the shared secret
+                        // then the decoding would fail due to a diff in the p2p shared secret, if it was signed by the secret
+                        // we know that it was a cluster-trusted TD so we know the message is indeed the encrypted shared secret).
+                        shared_secret = *decrypted.as_bytes();
+                        break 'register;
+                    } else if fetched.elapsed() >= CHALLENGE_LIFETIME {
+                        println!(
+                            "Wasn't onboarded before our challenge went stale, registering again"
+                        );
+                        continue 'register;
+                    } else {
+                        println!("Didn't hear from cluster contract yet, waiting 5 seconds");
+                        sleep(Duration::from_secs(5)).await;
+                    }
                 }
             }
         } else {
             // We need to bootstrap
-            let request_bootstrap = client
-                .post(format!("http://{}/bootstrap", self.host_endpoint))
-                .json(&host_paths::requests::BootstrapArgs::<HostServices> {
-                    quote,
-                    pubkeys: vec![my_pubkey.as_bytes().clone()],
-                })
-                .send()
-                .await?
-                .text()
-                .await?;
+            let quote = &self.fresh_quote(client, purpose, &bound_pubkeys).await?;
+            let my_pubkey = &my_pubkey;
+            let request_bootstrap = with_backoff("bootstrap", || async move {
+                Ok(client
+                    .post(format!("http://{}/bootstrap", self.host_endpoint))
+                    .json(&host_paths::requests::BootstrapArgs::<HostServices> {
+                        quote: quote.clone(),
+                        pubkeys: vec![my_pubkey.as_bytes().clone(), signing_pubkey],
+                    })
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .text()
+                    .await?)
+            })
+            .await?;
             println!(
                 "Bootstrapping cluster contract with shared public key {}: {}",
                 hex::encode(my_pubkey.as_bytes()),
@@ -255,41 +928,87 @@ impl GuestServiceInner for GuestServices {
         Ok(())
     }
 
-    /// Verifies the provided quote ensuring that [`pubkeys[0]`] is within the quote, if that
-    /// succeeds (i.e secretkey is held only in tdx) then it encrypts the shared secret to
-    /// [`pubkeys[0]`].
+    /// Verifies the provided quote ensuring that [`pubkeys[0]`] is within the quote and that the quote is
+    /// bound to one of the last [`MAX_CHALLENGE_AGE_LEDGERS`] ledgers, if that succeeds (i.e secretkey is held
+    /// only in tdx and the quote is not a replay) then it encrypts the shared secret to [`pubkeys[0]`].
+    ///
+    /// The platform's TCB status must be one of [`GuestServices::allowed_tcb_statuses`]. NB: backends that don't
+    /// report it (e.g the dcap-quotes verification service) aren't checked.
+    ///
+    /// If an event log policy is set, [`event_log`] is also replayed against the quote's RTMRs and checked
+    /// against the policy.
+    ///
+    /// The encrypted secret is signed with the cluster's signing key, see [`signing`].
     async fn onboard_new_node(
         &self,
         quote: Self::Quote,
         pubkeys: Vec<Self::Pubkey>,
+        event_log: Self::EventLog,
     ) -> anyhow::Result<Self::EncryptedMessage> {
+        // NB: whatever is wrong with the quote itself refuses the node, the rest (e.g the chain being unreachable)
+        // is retried by the host.
         let verify = self.attestation.verify_quote(quote).await?;
         println!("Got verification result.");
-        let expected_appdata: [u8; 32] = {
-            let preimage = format!("register{}", hex::encode(pubkeys[0].to_vec()));
-            let mut hasher = Sha256::new();
-            hasher.update(preimage);
-            hasher.finalize().into()
-        };
-        let got_appdata = verify.get_appdata();
+        match verify.tcb_status() {
+            Some(status) if !self.allowed_tcb_statuses.contains(&status) => {
+                return Err(anyhow!("tcb status {:?} isn't allowed", status)).context(Refused);
+            }
+            Some(_) => (),
+            None => println!("Verifier doesn't report the tcb status, not checking it."),
+        }
+        let got = verify
+            .report_data()
+            .and_then(|report_data| ReportData::parse(&report_data))
+            .context(Refused)?;
+
+        let recent = self
+            .coordination
+            .recent_challenges(MAX_CHALLENGE_AGE_LEDGERS)
+            .await?;
+        let challenge = recent
+            .iter()
+            .filter_map(|ledger| hex::decode(&ledger.hash).ok())
+            .find(|hash| ReportData::freshness_for(hash) == got.freshness)
+            .ok_or(anyhow!("quote challenge is stale or unknown"))
+            .context(Refused)?;
 
-        if expected_appdata != got_appdata {
-            return Err(anyhow!("").into());
+        let expected = ReportData::builder(Purpose::Register)
+            .cluster_id(self.cluster_contract)
+            .node_pubkey(pubkeys[0])
+            .freshness(challenge)
+            .build();
+        got.ensure_matches(&expected).context(Refused)?;
+
+        if let Some(policy) = &self.event_log_policy {
+            verify
+                .measurements()
+                .and_then(|measurements| {
+                    cc_eventlog::verify_rtmrs(&event_log, &measurements.rtmrs)?;
+                    policy.check_mrtd(&measurements.mr_td)?;
+                    policy.check(&event_log)
+                })
+                .context(Refused)?;
+            println!("Event log replayed and matches policy.");
         }
 
         println!("Encrypting secret.");
+        let shared_secret = self
+            .shared_secret
+            .lock()
+            .await
+            .ok_or(anyhow!("no shared secret yet"))?;
         let encrypted = self.crypto.encrypt_secret(
             NONCE,
-            self.shared_secret.lock().await.ok_or(anyhow!(""))?.into(),
+            shared_secret.into(),
             pubkeys.iter().map(|p| (*p).into()).collect(),
         )?;
-        Ok(encrypted)
+        SignedMessage::sign(&shared_secret, &pubkeys[0], encrypted)
     }
 }
 
 /// NON host-facing paths here.
 #[async_trait]
-impl TdxOnlyGuestServiceInner for GuestServices {
+impl<C: Coordination, A: GuestAttestation> TdxOnlyGuestServiceInner for GuestServices<C, A> {
     type Tag = String;
     type DerivedKey = String;
     type AssociatedKey = ();
@@ -312,14 +1031,5 @@ impl TdxOnlyGuestServiceInner for GuestServices {
     }
 }
 
-fn get_timestamp() -> i64 {
-    let start = SystemTime::now();
-    let since_the_epoch = start
-        .duration_since(UNIX_EPOCH)
-        .expect("Time went backwards");
-
-    since_the_epoch.as_secs() as i64
-}
-
 #[cfg(test)]
 mod test;
@@ -0,0 +1,706 @@
+//! Local coordination layer, for running a cluster on a single machine without Stellar, Mercury nor Horizon.
+//!
+//! It keeps the same events the simple-cluster contract publishes (`boot`, `register` and `onboard`) with the same
+//! rules: the cluster can only be bootstrapped once, nothing can be registered or onboarded before that and onboard
+//! messages must be signed by the cluster's signing key (see [`crate::signing`]). Events
+//! are either kept in memory (e.g for tests, clones share the same cluster) or appended as JSON lines to a file so
+//! that several host and guest processes can share it.
+//!
+//! Note: there are no ledgers locally, challenges are derived from the time (a new one every
+//! [`CHALLENGE_INTERVAL_SECS`]) and are predictable. That's fine for testing the workflow but offers no replay
+//! protection.
+//!
+
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use dstack_core::{Bootstrap, CoordinationLayer, Page, Registration};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{decode_pubkey, election, EventLogRef, LedgerChallenge, SignedMessage};
+
+/// Roughly a Stellar ledger.
+pub const CHALLENGE_INTERVAL_SECS: u64 = 5;
+
+/// Pubkeys, encrypted messages and signatures are hex-encoded.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ClusterEvent {
+    Boot {
+        shared_pubkey: String,
+        quote: String,
+        signing_pubkey: String,
+    },
+    Register {
+        pubkey: String,
+        quote: String,
+        /// sha384 of the event log, see [`crate::event_log`].
+        event_log_hash: String,
+        event_log_endpoint: String,
+        /// Who posted the registration, i.e the registering host's account on Stellar (see [`crate::election`]).
+        host: String,
+    },
+    Onboard {
+        pubkey: String,
+        encrypted: String,
+        signature: String,
+    },
+}
+
+#[derive(Clone)]
+enum Store {
+    Memory(Arc<Mutex<Vec<ClusterEvent>>>),
+    File(PathBuf),
+}
+
+#[derive(Clone)]
+pub struct LocalCoordination {
+    store: Store,
+    /// Who our registrations are posted by, see [`LocalCoordination::with_host`].
+    host: String,
+}
+
+/// Default [`LocalCoordination::with_host`].
+pub const DEFAULT_HOST: &str = "local";
+
+impl LocalCoordination {
+    pub fn in_memory() -> Self {
+        Self {
+            store: Store::Memory(Arc::new(Mutex::new(vec![]))),
+            host: DEFAULT_HOST.into(),
+        }
+    }
+
+    /// Events are appended to [`path`], which is created if missing.
+    ///
+    /// NB: appends are single writes so concurrent processes don't interleave events, but checks like "already
+    /// bootstrapped" aren't atomic with the append.
+    pub fn file(path: impl Into<PathBuf>) -> Self {
+        Self {
+            store: Store::File(path.into()),
+            host: DEFAULT_HOST.into(),
+        }
+    }
+
+    /// Registrations are posted as `host`, what the source account is on Stellar. Hosts sharing a cluster should
+    /// each have their own, otherwise they replace each other's members.
+    ///
+    /// NB: nothing authenticates it locally.
+    pub fn with_host(self, host: impl Into<String>) -> Self {
+        Self {
+            host: host.into(),
+            ..self
+        }
+    }
+
+    pub fn events(&self) -> anyhow::Result<Vec<ClusterEvent>> {
+        match &self.store {
+            Store::Memory(events) => Ok(events.lock().unwrap().clone()),
+            Store::File(path) => {
+                let content = match std::fs::read_to_string(path) {
+                    Ok(content) => content,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+                    Err(e) => return Err(e.into()),
+                };
+                content
+                    .lines()
+                    .filter(|line| !line.is_empty())
+                    .map(|line| Ok(serde_json::from_str(line)?))
+                    .collect()
+            }
+        }
+    }
+
+    fn append(&self, event: ClusterEvent) -> anyhow::Result<()> {
+        match &self.store {
+            Store::Memory(events) => events.lock().unwrap().push(event),
+            Store::File(path) => {
+                let mut line = serde_json::to_string(&event)?;
+                line.push('\n');
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)?
+                    .write_all(line.as_bytes())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn ensure_bootstrapped(&self) -> anyhow::Result<()> {
+        self.read_signing_pubkey()?;
+        Ok(())
+    }
+
+    fn read_bootstrap(&self) -> anyhow::Result<Option<Bootstrap<String, [u8; 32]>>> {
+        for event in self.events()? {
+            if let ClusterEvent::Boot {
+                shared_pubkey,
+                quote,
+                signing_pubkey,
+            } = event
+            {
+                return Ok(Some(Bootstrap {
+                    quote,
+                    shared_pubkey: decode_pubkey(&shared_pubkey)?,
+                    signing_pubkey: decode_pubkey(&signing_pubkey)?,
+                }));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn read_shared_pubkey(&self) -> anyhow::Result<Option<[u8; 32]>> {
+        Ok(self
+            .read_bootstrap()?
+            .map(|bootstrap| bootstrap.shared_pubkey))
+    }
+
+    fn read_signing_pubkey(&self) -> anyhow::Result<[u8; 32]> {
+        self.read_bootstrap()?
+            .map(|bootstrap| bootstrap.signing_pubkey)
+            .ok_or(anyhow!("cluster is not bootstrapped"))
+    }
+}
+
+fn challenge(sequence: u32) -> LedgerChallenge {
+    let mut hasher = Sha256::new();
+    hasher.update(b"new-york-local-challenge");
+    hasher.update(sequence.to_le_bytes());
+
+    LedgerChallenge {
+        sequence,
+        hash: hex::encode(hasher.finalize()),
+    }
+}
+
+#[async_trait]
+impl CoordinationLayer for LocalCoordination {
+    type Quote = String;
+    type Pubkey = [u8; 32];
+    type EventLog = EventLogRef;
+    type EncryptedMessage = SignedMessage;
+    type Challenge = LedgerChallenge;
+    /// Index of the last seen event.
+    type Cursor = usize;
+    /// Appends take effect immediately, there's nothing to report.
+    type Receipt = ();
+
+    async fn bootstrap(
+        &self,
+        quote: String,
+        shared_pubkey: [u8; 32],
+        signing_pubkey: [u8; 32],
+    ) -> anyhow::Result<()> {
+        if self.read_shared_pubkey()?.is_some() {
+            return Err(anyhow!("cluster is already bootstrapped"));
+        }
+
+        self.append(ClusterEvent::Boot {
+            shared_pubkey: hex::encode(shared_pubkey),
+            quote,
+            signing_pubkey: hex::encode(signing_pubkey),
+        })
+    }
+
+    async fn register(
+        &self,
+        quote: String,
+        pubkey: [u8; 32],
+        event_log: EventLogRef,
+    ) -> anyhow::Result<()> {
+        self.ensure_bootstrapped()?;
+        self.append(ClusterEvent::Register {
+            pubkey: hex::encode(pubkey),
+            quote,
+            event_log_hash: hex::encode(event_log.sha384),
+            event_log_endpoint: event_log.endpoint,
+            host: self.host.clone(),
+        })
+    }
+
+    async fn onboard(&self, pubkey: [u8; 32], message: SignedMessage) -> anyhow::Result<()> {
+        message.verify(&self.read_signing_pubkey()?, &pubkey)?;
+        self.append(ClusterEvent::Onboard {
+            pubkey: hex::encode(pubkey),
+            encrypted: hex::encode(message.encrypted),
+            signature: hex::encode(message.signature),
+        })
+    }
+
+    async fn pending_registrations(
+        &self,
+        after: Option<usize>,
+    ) -> anyhow::Result<Page<Registration<String, [u8; 32], EventLogRef>, usize>> {
+        let events = self.events()?;
+        let start = after.map_or(0, |after| after + 1);
+
+        let mut items = vec![];
+        for (index, event) in events.iter().enumerate().skip(start) {
+            if let ClusterEvent::Register {
+                pubkey,
+                quote,
+                event_log_hash,
+                event_log_endpoint,
+                ..
+            } = event
+            {
+                items.push((
+                    index,
+                    Registration {
+                        quote: quote.clone(),
+                        pubkey: decode_pubkey(pubkey)?,
+                        event_log: EventLogRef {
+                            sha384: hex::decode(event_log_hash)?
+                                .try_into()
+                                .map_err(|_| anyhow!("invalid event log hash"))?,
+                            endpoint: event_log_endpoint.clone(),
+                        },
+                    },
+                ));
+            }
+        }
+
+        Ok(Page {
+            items,
+            cursor: events.len().checked_sub(1).max(after),
+        })
+    }
+
+    async fn get_onboarding(&self, pubkey: &[u8; 32]) -> anyhow::Result<Vec<SignedMessage>> {
+        let mut messages = vec![];
+        for event in self.events()? {
+            if let ClusterEvent::Onboard {
+                pubkey: onboarded,
+                encrypted,
+                signature,
+            } = event
+            {
+                if onboarded == hex::encode(pubkey) {
+                    messages.push(SignedMessage {
+                        encrypted: hex::decode(encrypted)?,
+                        signature: hex::decode(signature)?,
+                    });
+                }
+            }
+        }
+
+        Ok(messages)
+    }
+
+    async fn members(&self) -> anyhow::Result<Vec<[u8; 32]>> {
+        Ok(election::members(self.events()?))
+    }
+
+    async fn shared_pubkey(&self) -> anyhow::Result<Option<[u8; 32]>> {
+        self.read_shared_pubkey()
+    }
+
+    async fn get_bootstrap(&self) -> anyhow::Result<Option<Bootstrap<String, [u8; 32]>>> {
+        self.read_bootstrap()
+    }
+
+    async fn recent_challenges(&self, limit: u32) -> anyhow::Result<Vec<LedgerChallenge>> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let latest = (now / CHALLENGE_INTERVAL_SECS) as u32;
+
+        Ok((0..limit.min(latest + 1))
+            .map(|age| challenge(latest - age))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        signing::signing_pubkey, GuestServices, HostServices, OnboardState,
+        MAX_CHALLENGE_AGE_LEDGERS, NONCE,
+    };
+    use cc_eventlog::TdxEventLog;
+    use diffie_hellman::Crypto;
+    use dstack_core::{
+        GuestServiceInner, HostServiceInner, InnerAttestationHelper, InnerCryptoHelper, Purpose,
+        Refused, ReportData,
+    };
+    use dummy_attestation::MockAttestation;
+    use std::time::Instant;
+    use warp::Filter;
+
+    /// Shared secret of the test clusters, only used for signing.
+    const SECRET: [u8; 32] = [9; 32];
+
+    async fn bootstrap(local: &LocalCoordination) {
+        local
+            .bootstrap("genesis".into(), [0; 32], signing_pubkey(&SECRET))
+            .await
+            .unwrap();
+    }
+
+    fn event_log() -> EventLogRef {
+        EventLogRef {
+            sha384: [1; 48],
+            endpoint: "localhost:8000".into(),
+        }
+    }
+
+    fn signed(pubkey: [u8; 32], encrypted: Vec<u8>) -> SignedMessage {
+        SignedMessage::sign(&SECRET, &pubkey, encrypted).unwrap()
+    }
+
+    #[tokio::test]
+    async fn follows_contract_rules() {
+        let local = LocalCoordination::in_memory();
+        assert!(local
+            .register("quote".into(), [1; 32], event_log())
+            .await
+            .is_err());
+        assert!(local
+            .onboard([1; 32], signed([1; 32], vec![1]))
+            .await
+            .is_err());
+        assert_eq!(local.shared_pubkey().await.unwrap(), None);
+
+        bootstrap(&local).await;
+        assert!(local
+            .bootstrap("genesis".into(), [2; 32], signing_pubkey(&[2; 32]))
+            .await
+            .is_err());
+        assert_eq!(local.shared_pubkey().await.unwrap(), Some([0; 32]));
+
+        local
+            .register("quote".into(), [1; 32], event_log())
+            .await
+            .unwrap();
+        assert!(local.get_onboarding(&[1; 32]).await.unwrap().is_empty());
+        local
+            .onboard([1; 32], signed([1; 32], vec![1, 2]))
+            .await
+            .unwrap();
+        assert_eq!(
+            local.get_onboarding(&[1; 32]).await.unwrap(),
+            vec![signed([1; 32], vec![1, 2])]
+        );
+
+        // Onboard messages not signed by a member are refused.
+        let spam = SignedMessage::sign(&[2; 32], &[1; 32], vec![3]).unwrap();
+        assert!(local.onboard([1; 32], spam).await.is_err());
+        // Nor can a member's signature be reused for another node.
+        assert!(local
+            .onboard([3; 32], signed([1; 32], vec![1, 2]))
+            .await
+            .is_err());
+        assert_eq!(local.get_onboarding(&[1; 32]).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn pages_registrations() {
+        let local = LocalCoordination::in_memory();
+        let page = local.pending_registrations(None).await.unwrap();
+        assert!(page.items.is_empty());
+        assert_eq!(page.cursor, None);
+
+        bootstrap(&local).await;
+        local
+            .register("first".into(), [1; 32], event_log())
+            .await
+            .unwrap();
+        let page = local.pending_registrations(None).await.unwrap();
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.items[0].1.pubkey, [1; 32]);
+
+        local
+            .onboard([1; 32], signed([1; 32], vec![1]))
+            .await
+            .unwrap();
+        local
+            .register("second".into(), [2; 32], event_log())
+            .await
+            .unwrap();
+        let next = local.pending_registrations(page.cursor).await.unwrap();
+        assert_eq!(next.items.len(), 1);
+        assert_eq!(next.items[0].1.quote, "second");
+        // Resuming from an item's position skips it.
+        let after_second = local
+            .pending_registrations(Some(next.items[0].0))
+            .await
+            .unwrap();
+        assert!(after_second.items.is_empty());
+
+        let empty = local.pending_registrations(next.cursor).await.unwrap();
+        assert!(empty.items.is_empty());
+        assert_eq!(empty.cursor, next.cursor);
+    }
+
+    #[tokio::test]
+    async fn lists_members() {
+        let local = LocalCoordination::in_memory();
+        assert!(local.members().await.unwrap().is_empty());
+
+        bootstrap(&local).await;
+        local
+            .register("quote".into(), [1; 32], event_log())
+            .await
+            .unwrap();
+        assert_eq!(local.members().await.unwrap(), vec![[0; 32]]);
+
+        // Duplicate onboardings don't count twice.
+        local
+            .onboard([1; 32], signed([1; 32], vec![1]))
+            .await
+            .unwrap();
+        local
+            .onboard([1; 32], signed([1; 32], vec![2]))
+            .await
+            .unwrap();
+        assert_eq!(local.members().await.unwrap(), vec![[0; 32], [1; 32]]);
+
+        // The same host registering another pubkey replaces its member.
+        local
+            .register("quote".into(), [2; 32], event_log())
+            .await
+            .unwrap();
+        assert_eq!(local.members().await.unwrap(), vec![[0; 32]]);
+    }
+
+    #[tokio::test]
+    async fn shares_cluster_through_file() {
+        let path =
+            std::env::temp_dir().join(format!("new-york-local-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let first = LocalCoordination::file(&path);
+        let second = LocalCoordination::file(&path);
+        bootstrap(&first).await;
+        second
+            .register("quote".into(), [1; 32], event_log())
+            .await
+            .unwrap();
+
+        assert_eq!(second.shared_pubkey().await.unwrap(), Some([0; 32]));
+        assert_eq!(
+            first.pending_registrations(None).await.unwrap().items.len(),
+            1
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn onboards_with_mock_attestation() {
+        let local = LocalCoordination::in_memory();
+        let cluster = [7; 32];
+        let crypto = Crypto::new();
+        let (shared_pubkey, shared_secret) = crypto.get_keypair().unwrap();
+
+        let mut member =
+            GuestServices::with_helpers(cluster, local.clone(), MockAttestation::new());
+        member.set_secret(*shared_secret.as_bytes()).await;
+        local
+            .bootstrap(
+                "genesis".into(),
+                *shared_pubkey.as_bytes(),
+                signing_pubkey(shared_secret.as_bytes()),
+            )
+            .await
+            .unwrap();
+
+        // A joining node binds a recent challenge into its quote.
+        let (node_pubkey, node_secret) = crypto.get_keypair().unwrap();
+        let challenge = local.latest_challenge().await.unwrap();
+        let report_data = ReportData::builder(Purpose::Register)
+            .cluster_id(cluster)
+            .node_pubkey(node_pubkey.as_bytes())
+            .freshness(hex::decode(&challenge.hash).unwrap())
+            .build();
+        let quote = MockAttestation::new().get_quote(report_data).await.unwrap();
+
+        let message = member
+            .onboard_new_node(quote, vec![*node_pubkey.as_bytes()], vec![])
+            .await
+            .unwrap();
+        // Signed by the member so the coordination layer takes it.
+        local
+            .onboard(*node_pubkey.as_bytes(), message.clone())
+            .await
+            .unwrap();
+        let decrypted = crypto
+            .decrypt_secret(
+                NONCE,
+                message.encrypted,
+                vec![shared_pubkey],
+                vec![node_secret],
+            )
+            .unwrap();
+        assert_eq!(decrypted.as_bytes(), shared_secret.as_bytes());
+
+        // Challenges older than the allowed age are rejected.
+        let stale = challenge.sequence - MAX_CHALLENGE_AGE_LEDGERS;
+        let report_data = ReportData::builder(Purpose::Register)
+            .cluster_id(cluster)
+            .node_pubkey(node_pubkey.as_bytes())
+            .freshness(hex::decode(super::challenge(stale).hash).unwrap())
+            .build();
+        let quote = MockAttestation::new().get_quote(report_data).await.unwrap();
+        let refused = member
+            .onboard_new_node(quote, vec![*node_pubkey.as_bytes()], vec![])
+            .await
+            .unwrap_err();
+        assert!(Refused::is(&refused));
+
+        // A member without the secret yet fails to onboard but doesn't refuse the node.
+        let fresh = GuestServices::with_helpers(cluster, local.clone(), MockAttestation::new());
+        let report_data = ReportData::builder(Purpose::Register)
+            .cluster_id(cluster)
+            .node_pubkey(node_pubkey.as_bytes())
+            .freshness(hex::decode(&challenge.hash).unwrap())
+            .build();
+        let quote = MockAttestation::new().get_quote(report_data).await.unwrap();
+        let failed = fresh
+            .onboard_new_node(quote, vec![*node_pubkey.as_bytes()], vec![])
+            .await
+            .unwrap_err();
+        assert!(!Refused::is(&failed));
+    }
+
+    #[tokio::test]
+    async fn verifies_bootstrap_quote() {
+        let cluster = [7; 32];
+        let shared_pubkey = [1; 32];
+        let signing_pubkey = signing_pubkey(&SECRET);
+        let quote = |signing_pubkey: [u8; 32]| {
+            let report_data = ReportData::builder(Purpose::Bootstrap)
+                .cluster_id(cluster)
+                .node_pubkey([shared_pubkey.as_slice(), &signing_pubkey].concat())
+                .freshness([3; 32])
+                .build();
+            async move { MockAttestation::new().get_quote(report_data).await.unwrap() }
+        };
+
+        let local = LocalCoordination::in_memory();
+        let joining = GuestServices::with_helpers(cluster, local.clone(), MockAttestation::new());
+        assert!(joining.verify_bootstrap(shared_pubkey).await.is_err());
+        local
+            .bootstrap(quote(signing_pubkey).await, shared_pubkey, signing_pubkey)
+            .await
+            .unwrap();
+        joining.verify_bootstrap(shared_pubkey).await.unwrap();
+        assert!(joining.verify_bootstrap([2; 32]).await.is_err());
+
+        // The quote binds another signing pubkey than the one onboard messages are checked against.
+        let local = LocalCoordination::in_memory();
+        let joining = GuestServices::with_helpers(cluster, local.clone(), MockAttestation::new());
+        local
+            .bootstrap(quote([4; 32]).await, shared_pubkey, signing_pubkey)
+            .await
+            .unwrap();
+        assert!(joining.verify_bootstrap(shared_pubkey).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_registrations_that_keep_failing() {
+        let local = LocalCoordination::in_memory();
+        bootstrap(&local).await;
+        let mut host =
+            HostServices::with_coordination(local.clone(), "localhost:8000".into()).unwrap();
+        host.onboard_retries = 2;
+        host.allow_private_endpoints = true;
+        // We're the only member, so we respond right away.
+        host.set_node_pubkey([0; 32]).await;
+
+        // A host serving a log that doesn't match the registration's commitment.
+        let (addr, server) =
+            warp::serve(warp::path("event_log").map(|| "[]")).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        local
+            .register(
+                "mismatched".into(),
+                [1; 32],
+                EventLogRef {
+                    sha384: [1; 48],
+                    endpoint: addr.to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        // A host that can't be reached.
+        local
+            .register(
+                "unreachable".into(),
+                [2; 32],
+                EventLogRef {
+                    sha384: [1; 48],
+                    endpoint: "127.0.0.1:1".into(),
+                },
+            )
+            .await
+            .unwrap();
+
+        let mut state = OnboardState {
+            cursor: None,
+            fetched: None,
+            pending: vec![],
+        };
+        // The mismatched log is refused right away, the unreachable one is retried.
+        host.poll_registrations(&mut state).await;
+        assert_eq!(state.pending.len(), 1);
+        assert_eq!(state.pending[0].registration.pubkey, [2; 32]);
+        let first = local.pending_registrations(None).await.unwrap().items[0].0;
+        assert_eq!(state.cursor, Some(first));
+
+        host.poll_registrations(&mut state).await;
+        assert!(state.pending.is_empty());
+        assert_eq!(state.cursor, state.fetched);
+        assert!(local.get_onboarding(&[2; 32]).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn keeps_serving_event_logs_until_onboarded() {
+        let path =
+            std::env::temp_dir().join(format!("new-york-event-logs-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let local = LocalCoordination::in_memory();
+        bootstrap(&local).await;
+        let mut host =
+            HostServices::with_coordination(local.clone(), "localhost:8000".into()).unwrap();
+        host.event_logs_path = Some(path.clone());
+
+        let event_log = vec![TdxEventLog::new_str(3, 0x08000001, "app-id", "new-york")];
+        host.register("quote".into(), vec![[1; 32]], vec![], event_log.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            host.event_log([1; 32]).await.unwrap(),
+            Some(event_log.clone())
+        );
+        // A restarted host still serves it.
+        let stored = HostServices::<LocalCoordination>::load_event_logs(&path).unwrap();
+        assert_eq!(stored.get(&[1; 32]), Some(&event_log));
+
+        local
+            .onboard([1; 32], signed([1; 32], vec![1]))
+            .await
+            .unwrap();
+        let registration = local.pending_registrations(None).await.unwrap().items[0]
+            .1
+            .clone();
+        assert!(host
+            .handle_registration(&registration, &[[0; 32]], Instant::now())
+            .await
+            .unwrap());
+        assert_eq!(host.event_log([1; 32]).await.unwrap(), None);
+        assert!(HostServices::<LocalCoordination>::load_event_logs(&path)
+            .unwrap()
+            .is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
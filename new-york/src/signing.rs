@@ -0,0 +1,99 @@
+//! Signs onboard messages so that only cluster members can post them. Otherwise anyone can post garbage for a
+//! newcomer, which then has to go through it (or gets stuck on it) before finding the actual shared secret.
+//!
+//! The signing key is derived from the shared secret, so every member holds the same key and nobody else does: the
+//! secret is only ever handed to nodes whose quote was verified. Its pubkey is set when bootstrapping and bound into
+//! the bootstrap quote along with the shared pubkey (joining nodes check it, see
+//! [`crate::GuestServices::verify_bootstrap`]), the coordination layer (e.g the cluster contract) checks onboard
+//! messages against it.
+//!
+//! Note: the signed payload is the XDR of the `(node_pubkey, encrypted)` tuple as the simple-cluster contract sees
+//! its arguments (both hex-encoded strings), so that the contract can check it without any parsing. The local
+//! coordination layer checks the same payload.
+//!
+
+use anyhow::anyhow;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use stellar_xdr::curr::{Limits, ScVal, ScVec, WriteXdr};
+
+use crate::stellar::rpc::string_arg;
+
+/// The shared secret encrypted to a newcomer, signed by a member.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SignedMessage {
+    pub encrypted: Vec<u8>,
+    /// ed25519 signature of [`onboard_payload`].
+    pub signature: Vec<u8>,
+}
+
+impl SignedMessage {
+    pub fn sign(
+        shared_secret: &[u8; 32],
+        pubkey: &[u8; 32],
+        encrypted: Vec<u8>,
+    ) -> anyhow::Result<Self> {
+        let signature = signing_key(shared_secret).sign(&onboard_payload(pubkey, &encrypted)?);
+
+        Ok(Self {
+            encrypted,
+            signature: signature.to_bytes().to_vec(),
+        })
+    }
+
+    /// Checks that the message for [`pubkey`] was signed by the cluster's [`signing_pubkey`].
+    pub fn verify(&self, signing_pubkey: &[u8; 32], pubkey: &[u8; 32]) -> anyhow::Result<()> {
+        let signature = Signature::from_slice(&self.signature)?;
+        VerifyingKey::from_bytes(signing_pubkey)?
+            .verify_strict(&onboard_payload(pubkey, &self.encrypted)?, &signature)
+            .map_err(|_| anyhow!("onboard message isn't signed by the cluster"))
+    }
+}
+
+pub fn signing_key(shared_secret: &[u8; 32]) -> SigningKey {
+    let mut hasher = Sha256::new();
+    hasher.update(b"new-york-onboard-signing");
+    hasher.update(shared_secret);
+
+    SigningKey::from_bytes(&hasher.finalize().into())
+}
+
+pub fn signing_pubkey(shared_secret: &[u8; 32]) -> [u8; 32] {
+    signing_key(shared_secret).verifying_key().to_bytes()
+}
+
+/// What members sign when onboarding [`pubkey`].
+pub fn onboard_payload(pubkey: &[u8; 32], encrypted: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let args = vec![
+        string_arg(&hex::encode(pubkey))?,
+        string_arg(&hex::encode(encrypted))?,
+    ];
+
+    Ok(ScVal::Vec(Some(ScVec(args.try_into()?))).to_xdr(Limits::none())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_members_sign() {
+        let message = SignedMessage::sign(&[1; 32], &[2; 32], vec![3, 4]).unwrap();
+        let signing_pubkey = signing_pubkey(&[1; 32]);
+        message.verify(&signing_pubkey, &[2; 32]).unwrap();
+
+        // Bound to the newcomer and the message.
+        assert!(message.verify(&signing_pubkey, &[5; 32]).is_err());
+        let tampered = SignedMessage {
+            encrypted: vec![3, 5],
+            ..message.clone()
+        };
+        assert!(tampered.verify(&signing_pubkey, &[2; 32]).is_err());
+
+        // Another secret gives another key.
+        let other = SignedMessage::sign(&[9; 32], &[2; 32], vec![3, 4]).unwrap();
+        assert!(other.verify(&signing_pubkey, &[2; 32]).is_err());
+        assert_ne!(signing_pubkey, super::signing_pubkey(&[9; 32]));
+    }
+}
@@ -1,19 +1,43 @@
 //! Example of using the Stellar network as comms layer for our new-york implementation.
 //!
-//! Note that this example uses the Mercury API and ZVM program to construct transactions before signing them as a proof of concept
-//! for development ease. If availability is a primary concern, then the host should also be running at least a watcher
-//! node to both fetch the events and submitting transactions (and simulation should also be local).
+//! Transactions are built locally, simulated and submitted through Soroban RPC (see [`rpc`]). The Mercury API and
+//! ZVM program can still be used to construct transactions by setting `ZEPHYR`. Either way transactions are checked
+//! against the call we meant to make before being signed (see [`validate`]) and resubmitted until they're included
+//! in a ledger or fail for good (see [`submit`]). Registrations and onboardings are read
+//! from the contract's events through Soroban RPC `getEvents` (see [`events`]). If availability is a primary concern,
+//! then the host should also be running at least a watcher node to both fetch the events and submitting transactions.
+//!
+//! [`Stellar`] wraps these helpers as a [`CoordinationLayer`] for the host and guest services.
 //!
 
+pub mod events;
+pub mod network;
+pub mod rpc;
+pub mod submit;
 mod utils;
+pub mod validate;
 
-use anyhow::anyhow;
+use anyhow::{anyhow, bail, Context};
+use async_trait::async_trait;
 use base64::{prelude::BASE64_STANDARD, Engine};
+use dstack_core::{Bootstrap, CoordinationLayer, Page, Registration};
 use ed25519_dalek::SigningKey;
+use events::EventFollower;
+pub use network::StellarNetwork;
 use reqwest::Client;
+use rpc::{account_arg, bytes_arg, string_arg, SorobanRpc};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use utils::sign_and_send_tx;
+use std::future::Future;
+use stellar_xdr::curr::{
+    ContractDataDurability, ContractDataEntry, Hash, LedgerEntryData, LedgerKey,
+    LedgerKeyContractData, Limits, ReadXdr, ScAddress, ScMapEntry, ScSymbol, ScVal, ScVec,
+    SequenceNumber, Transaction,
+};
+use submit::{Submitter, TxReceipt};
+use validate::{ExpectedCall, DEFAULT_MAX_FEE};
+
+use crate::{env_or, ClusterEvent, EventLogRef, SignedMessage};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct TransactionResponse {
@@ -21,28 +45,52 @@ pub struct TransactionResponse {
     pub error: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-pub struct OnboardedObject {
+/// A ledger used as freshness challenge: its hash is bound into the report data and the quote is only accepted
+/// while the ledger is among the most recent ones.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LedgerChallenge {
+    pub sequence: u32,
     // hex-encoded.
-    pub pubkey: String,
-    pub encrypted: String,
-    pub at_time: i64,
+    pub hash: String,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct PendingObject {
-    // hex-encoded.
-    pub quote: String,
-    pub pubkey: String,
-    pub at_time: i64,
+#[derive(Deserialize)]
+struct HorizonPage<T> {
+    #[serde(rename = "_embedded")]
+    embedded: HorizonRecords<T>,
+}
+
+#[derive(Deserialize)]
+struct HorizonRecords<T> {
+    records: Vec<T>,
+}
+
+/// Returns the [`limit`] most recently closed ledgers, newest first.
+pub async fn get_recent_ledgers(
+    network: &StellarNetwork,
+    limit: u32,
+) -> anyhow::Result<Vec<LedgerChallenge>> {
+    let page: HorizonPage<LedgerChallenge> = Client::new()
+        .get(format!(
+            "{}/ledgers?order=desc&limit={}",
+            network.horizon, limit
+        ))
+        .send()
+        .await?
+        .json()
+        .await?;
+
+    Ok(page.embedded.records)
 }
 
+/// Has the Zephyr program build the transaction. NB: it's only signed if it matches the call we expect, see
+/// [`Submitter::submit`].
 pub async fn post_to_zephyr(
-    secret_key: [u8; 32],
+    network: &StellarNetwork,
     function_name: &str,
     args: serde_json::Value,
-) -> anyhow::Result<()> {
-    let zephyr_url = "https://api.mercurydata.app/zephyr/execute/113";
+) -> anyhow::Result<Transaction> {
+    let zephyr_url = network.zephyr()?;
     let payload = json!({
         "project_name": "newyork",
         "mode": {
@@ -53,23 +101,21 @@ pub async fn post_to_zephyr(
         }
     });
 
-    let client = Client::new();
-    println!("Posting to zephy");
-    let response = client
+    let response = Client::new()
         .post(zephyr_url)
         .header("Content-Type", "application/json")
         .json(&payload)
         .send()
         .await?;
     let txenvelope: TransactionResponse = response.json().await?;
-    //let txenvelope = response.text().await?;
-    println!("Got {:?} for payload {:?}", txenvelope, payload.to_string());
 
-    if let Some(envelope) = txenvelope.tx {
-        sign_and_send_tx(envelope, secret_key).await?
+    match txenvelope.tx {
+        Some(envelope) => Ok(Transaction::from_xdr_base64(envelope, Limits::none())?),
+        None => Err(anyhow!(
+            "zephyr didn't build a transaction: {:?}",
+            txenvelope.error
+        )),
     }
-
-    Ok(())
 }
 
 // This won't post anything to be pulled client side for automated replication but
@@ -77,39 +123,41 @@ pub async fn post_to_zephyr(
 // Again, this is a minimal dstack implementation, so the nodes have to audit the cluster before
 // joining it, i.e they need to make sure that the shared pubkey is within the valid TDX quote.
 pub async fn post_bootstrap(
+    network: &StellarNetwork,
     cluster_contract: [u8; 32],
     secret_key: [u8; 32],
     quote: String,
     shared_pubkey: [u8; 32],
-) -> anyhow::Result<()> {
-    println!("Asked to post bootstrap");
+    signing_pubkey: [u8; 32],
+) -> anyhow::Result<Transaction> {
     let public = stellar_strkey::ed25519::PublicKey(
         *SigningKey::from_bytes(&secret_key)
             .verifying_key()
             .as_bytes(),
     )
     .to_string();
-    let quote = hex_to_b64(&quote);
-    println!("quote size is {}", quote.as_bytes().len());
 
     let args = json!({
         "cluster": stellar_strkey::Contract(cluster_contract).to_string(),
         "pubkey": hex::encode(shared_pubkey),
-        "quote": quote,
+        "quote": hex_to_b64(&quote)?,
+        "signing_pubkey": hex::encode(signing_pubkey),
         "source": public
     });
 
-    post_to_zephyr(secret_key, "bootstrap", args).await
+    post_to_zephyr(network, "bootstrap", args).await
 }
 
 // This will post new data to get_pending allowing the onboard thread to get the quotes + pubkeys
 // of the nodes that want to join the cluster.
 pub async fn post_register(
+    network: &StellarNetwork,
     cluster_contract: [u8; 32],
     secret_key: [u8; 32],
     quote: String,
-    node_pubkey: &[u8; 32],
-) -> anyhow::Result<()> {
+    node_pubkey: [u8; 32],
+    event_log: EventLogRef,
+) -> anyhow::Result<Transaction> {
     let public = stellar_strkey::ed25519::PublicKey(
         *SigningKey::from_bytes(&secret_key)
             .verifying_key()
@@ -119,21 +167,24 @@ pub async fn post_register(
 
     let args = json!({
         "cluster": stellar_strkey::Contract(cluster_contract).to_string(),
-        "quote": hex_to_b64(&quote),
+        "quote": hex_to_b64(&quote)?,
         "pubkey": hex::encode(node_pubkey),
+        "event_log_hash": hex::encode(event_log.sha384),
+        "event_log_endpoint": event_log.endpoint,
         "source": public
     });
 
-    post_to_zephyr(secret_key, "register", args).await
+    post_to_zephyr(network, "register", args).await
 }
 
 // This will post new data to get_onboard allowing the replicatoor to get the encrypted message.
 pub async fn post_onboard(
+    network: &StellarNetwork,
     cluster_contract: [u8; 32],
     secret_key: [u8; 32],
-    encrypted_message: Vec<u8>,
-    node_pubkey: &[u8; 32],
-) -> anyhow::Result<()> {
+    message: SignedMessage,
+    node_pubkey: [u8; 32],
+) -> anyhow::Result<Transaction> {
     let public = stellar_strkey::ed25519::PublicKey(
         *SigningKey::from_bytes(&secret_key)
             .verifying_key()
@@ -143,81 +194,402 @@ pub async fn post_onboard(
 
     let args = json!({
         "cluster": stellar_strkey::Contract(cluster_contract).to_string(),
-        "quote": hex::encode(encrypted_message),
+        "quote": hex::encode(message.encrypted),
         "pubkey": hex::encode(node_pubkey),
+        "signature": hex::encode(message.signature),
         "source": public
     });
 
-    post_to_zephyr(secret_key, "onboard", args).await
+    post_to_zephyr(network, "onboard", args).await
 }
 
-async fn pull_from_zephyr<T: serde::de::DeserializeOwned>(
+/// Reads the cluster contract's instance storage, i.e its `DataKey`s.
+async fn get_instance_storage(
+    rpc: &SorobanRpc,
     cluster_contract: [u8; 32],
-    function_name: &str,
-) -> anyhow::Result<T> {
-    let cluster_contract = stellar_strkey::Contract(cluster_contract).to_string();
-    let zephyr_url = "https://api.mercurydata.app/zephyr/execute/113";
-
-    let args = json!({
-        "cluster": cluster_contract
+) -> anyhow::Result<Vec<ScMapEntry>> {
+    let key = LedgerKey::ContractData(LedgerKeyContractData {
+        contract: ScAddress::Contract(Hash(cluster_contract)),
+        key: ScVal::LedgerKeyContractInstance,
+        durability: ContractDataDurability::Persistent,
     });
 
-    let payload = json!({
-        "project_name": "newyork",
-        "mode": {
-            "Function": {
-                "fname": function_name,
-                "arguments": args.to_string(),
-            }
-        }
-    });
+    let Some(LedgerEntryData::ContractData(ContractDataEntry {
+        val: ScVal::ContractInstance(instance),
+        ..
+    })) = rpc.get_ledger_entries(&[key]).await?.pop()
+    else {
+        bail!("cluster contract not found");
+    };
 
-    let client = Client::new();
-    let response = client
-        .post(zephyr_url)
-        .header("Content-Type", "application/json")
-        .json(&payload)
-        .send()
-        .await?;
+    Ok(instance
+        .storage
+        .map(|storage| storage.0.into_vec())
+        .unwrap_or_default())
+}
+
+/// Value stored under the `DataKey` variant [`name`].
+fn storage_value<'a>(storage: &'a [ScMapEntry], name: &str) -> anyhow::Result<Option<&'a ScVal>> {
+    // NB: unit variants are stored as a vector holding the variant's name.
+    let key = ScVal::Vec(Some(ScVec(
+        vec![ScVal::Symbol(ScSymbol(name.try_into()?))].try_into()?,
+    )));
 
-    Ok(response.json().await?)
+    Ok(storage
+        .iter()
+        .find(|entry| entry.key == key)
+        .map(|entry| &entry.val))
 }
 
-pub async fn get_pending(cluster_contract: [u8; 32]) -> anyhow::Result<Vec<PendingObject>> {
-    let mut res = pull_from_zephyr::<Vec<PendingObject>>(cluster_contract, "pending").await?;
-    println!("Got {} register requests", res.len());
-    res.iter_mut().for_each(|m| {
-        m.quote = b64_to_hex(&m.quote);
-    });
-    println!("Changed to hex");
-    Ok(res)
+fn decode_shared_pubkey(storage: &[ScMapEntry]) -> anyhow::Result<Option<[u8; 32]>> {
+    match storage_value(storage, "SharedPub")? {
+        None => Ok(None),
+        Some(ScVal::String(shared_pub)) => {
+            let bytes = hex::decode(shared_pub.to_utf8_string()?)?;
+            Ok(Some(
+                bytes
+                    .try_into()
+                    .map_err(|_| anyhow!("invalid shared pubkey"))?,
+            ))
+        }
+        Some(other) => Err(anyhow!("unexpected shared pubkey value {:?}", other)),
+    }
+}
+
+/// Reads the shared pubkey from the cluster contract's instance storage, `None` if the cluster isn't bootstrapped
+/// yet.
+pub async fn get_shared_pubkey(
+    rpc: &SorobanRpc,
+    cluster_contract: [u8; 32],
+) -> anyhow::Result<Option<[u8; 32]>> {
+    decode_shared_pubkey(&get_instance_storage(rpc, cluster_contract).await?)
 }
 
-pub async fn get_onboarded(
+/// Reads the bootstrap quote and the pubkeys from the cluster contract's instance storage, `None` if the cluster
+/// isn't bootstrapped yet.
+///
+/// Note: the quote is kept in storage rather than read from the `boot` event since RPC nodes only retain recent
+/// events.
+pub async fn get_bootstrap(
+    rpc: &SorobanRpc,
     cluster_contract: [u8; 32],
-    node_pubkey: &[u8; 32],
-) -> anyhow::Result<String> {
-    let onboarded: Vec<OnboardedObject> = pull_from_zephyr(cluster_contract, "onboarded").await?;
-    for onboarded in onboarded {
-        if onboarded.pubkey == hex::encode(node_pubkey) {
-            return Ok(onboarded.encrypted);
+) -> anyhow::Result<Option<Bootstrap<String, [u8; 32]>>> {
+    let storage = get_instance_storage(rpc, cluster_contract).await?;
+    let Some(shared_pubkey) = decode_shared_pubkey(&storage)? else {
+        return Ok(None);
+    };
+
+    let signing_pubkey = match storage_value(&storage, "SigningPub")? {
+        Some(ScVal::Bytes(signing_pub)) => signing_pub
+            .as_slice()
+            .try_into()
+            .map_err(|_| anyhow!("invalid signing pubkey"))?,
+        other => bail!("unexpected signing pubkey value {:?}", other),
+    };
+    let quote = match storage_value(&storage, "BootQuote")? {
+        Some(ScVal::String(quote)) => hex::encode(BASE64_STANDARD.decode(quote.to_utf8_string()?)?),
+        other => bail!("unexpected bootstrap quote value {:?}", other),
+    };
+
+    Ok(Some(Bootstrap {
+        quote,
+        shared_pubkey,
+        signing_pubkey,
+    }))
+}
+
+/// The simple-cluster contract as coordination layer. Registrations are paged by RPC event id.
+///
+/// Note: reads don't need a secret, so guests only get a [`Stellar::read_only`] instance.
+pub struct Stellar {
+    contract: [u8; 32],
+    secret: Option<[u8; 32]>,
+    network: StellarNetwork,
+    rpc: SorobanRpc,
+    /// Whether transactions are built by the Zephyr program rather than locally.
+    zephyr: bool,
+    max_fee: u32,
+    events: EventFollower,
+    submitter: Submitter,
+}
+
+impl Stellar {
+    pub fn new(contract: [u8; 32], secret: [u8; 32]) -> anyhow::Result<Self> {
+        Ok(Self {
+            secret: Some(secret),
+            ..Self::read_only(contract)?
+        })
+    }
+
+    /// The network is read from the environment (see [`StellarNetwork::from_env`], testnet by default) and the
+    /// maximum fee we're willing to sign for from `MAX_FEE` (in stroops).
+    ///
+    /// Events are followed from `START_LEDGER` (e.g the ledger the contract was deployed at), the oldest one the RPC
+    /// node retains otherwise. With `EVENT_STORE` set to a file path they're persisted there across restarts.
+    ///
+    /// Fails on malformed settings or an unreadable event store.
+    pub fn read_only(contract: [u8; 32]) -> anyhow::Result<Self> {
+        let network = StellarNetwork::from_env()?;
+        let max_fee = env_or("MAX_FEE", DEFAULT_MAX_FEE)?;
+        let start_ledger = std::env::var("START_LEDGER")
+            .ok()
+            .map(|ledger| {
+                ledger
+                    .parse()
+                    .with_context(|| format!("invalid START_LEDGER {:?}", ledger))
+            })
+            .transpose()?;
+        let events = match std::env::var("EVENT_STORE") {
+            Ok(path) => EventFollower::with_store(contract, start_ledger, &path)
+                .with_context(|| format!("couldn't load the event store {}", path))?,
+            Err(_) => EventFollower::new(contract, start_ledger),
+        };
+        Ok(Self {
+            contract,
+            secret: None,
+            rpc: SorobanRpc::new(&network.soroban_rpc),
+            network,
+            zephyr: std::env::var("ZEPHYR").is_ok(),
+            max_fee,
+            events,
+            submitter: Submitter::new(),
+        })
+    }
+
+    /// Talks to [`network`] rather than the one configured in the environment.
+    pub fn with_network(self, network: StellarNetwork) -> Self {
+        Self {
+            rpc: SorobanRpc::new(&network.soroban_rpc),
+            network,
+            ..self
         }
     }
 
-    Err(anyhow!("No matching onboarded node found").into())
-}
+    fn secret(&self) -> anyhow::Result<[u8; 32]> {
+        self.secret
+            .ok_or(anyhow!("a secret key is needed to post transactions"))
+    }
 
-fn hex_to_b64(hex: &str) -> String {
-    let bytes = hex::decode(hex).unwrap();
-    let base64 = BASE64_STANDARD.encode(bytes);
-    base64
+    /// Calls [`function`] on the cluster contract through the [`Submitter`]. The transaction is built locally, or by
+    /// [`zephyr`] if `ZEPHYR` is set. Zephyr fetches the account's sequence on its own, so its transaction gets the
+    /// one the submitter tracks instead (the submitter bumps the fee of either).
+    async fn invoke<F, Fut>(
+        &self,
+        function: &str,
+        args: Vec<ScVal>,
+        zephyr: F,
+    ) -> anyhow::Result<TxReceipt>
+    where
+        F: Fn() -> Fut + Send + Sync,
+        Fut: Future<Output = anyhow::Result<Transaction>> + Send,
+    {
+        let secret = self.secret()?;
+        let expected = ExpectedCall {
+            source: *SigningKey::from_bytes(&secret).verifying_key().as_bytes(),
+            // Set by the submitter.
+            sequence: 0,
+            contract: self.contract,
+            function: function.into(),
+            args,
+            max_fee: self.max_fee,
+        };
+
+        let build = |expected: ExpectedCall| {
+            let zephyr = &zephyr;
+            async move {
+                if self.zephyr {
+                    let mut tx = zephyr().await?;
+                    tx.seq_num = SequenceNumber(expected.sequence);
+                    return Ok(tx);
+                }
+                let tx = rpc::build_invoke_transaction(
+                    expected.source,
+                    expected.sequence,
+                    expected.contract,
+                    &expected.function,
+                    expected.args,
+                )?;
+                let simulation = self.rpc.simulate_transaction(&tx).await?;
+                rpc::assemble(tx, &simulation)
+            }
+        };
+        let receipt = self
+            .submitter
+            .submit(&self.rpc, secret, &self.network.passphrase, expected, build)
+            .await?;
+        println!(
+            "{} transaction {} included in ledger {}",
+            function, receipt.hash, receipt.ledger
+        );
+
+        Ok(receipt)
+    }
 }
 
-fn b64_to_hex(b64: &str) -> String {
-    let base64 = BASE64_STANDARD.decode(b64).unwrap();
-    let hex = hex::encode(base64);
+#[async_trait]
+impl CoordinationLayer for Stellar {
+    type Quote = String;
+    type Pubkey = [u8; 32];
+    type EventLog = EventLogRef;
+    type EncryptedMessage = SignedMessage;
+    type Challenge = LedgerChallenge;
+    type Cursor = String;
+    type Receipt = TxReceipt;
+
+    async fn bootstrap(
+        &self,
+        quote: String,
+        shared_pubkey: [u8; 32],
+        signing_pubkey: [u8; 32],
+    ) -> anyhow::Result<TxReceipt> {
+        let args = vec![
+            string_arg(&hex::encode(shared_pubkey))?,
+            string_arg(&hex_to_b64(&quote)?)?,
+            bytes_arg(&signing_pubkey)?,
+        ];
+        let secret = self.secret()?;
+
+        self.invoke("bootstrap", args, || {
+            post_bootstrap(
+                &self.network,
+                self.contract,
+                secret,
+                quote.clone(),
+                shared_pubkey,
+                signing_pubkey,
+            )
+        })
+        .await
+    }
+
+    /// The registration is posted as our account, which authorizes it, see [`crate::election::members`].
+    async fn register(
+        &self,
+        quote: String,
+        pubkey: [u8; 32],
+        event_log: EventLogRef,
+    ) -> anyhow::Result<TxReceipt> {
+        let secret = self.secret()?;
+        let args = vec![
+            account_arg(*SigningKey::from_bytes(&secret).verifying_key().as_bytes()),
+            string_arg(&hex::encode(pubkey))?,
+            string_arg(&hex_to_b64(&quote)?)?,
+            bytes_arg(&event_log.sha384)?,
+            string_arg(&event_log.endpoint)?,
+        ];
+
+        self.invoke("register", args, || {
+            post_register(
+                &self.network,
+                self.contract,
+                secret,
+                quote.clone(),
+                pubkey,
+                event_log.clone(),
+            )
+        })
+        .await
+    }
+
+    /// NB: the contract refuses messages that aren't signed by the cluster, so this fails rather than getting
+    /// the transaction included.
+    async fn onboard(&self, pubkey: [u8; 32], message: SignedMessage) -> anyhow::Result<TxReceipt> {
+        let args = vec![
+            string_arg(&hex::encode(pubkey))?,
+            string_arg(&hex::encode(&message.encrypted))?,
+            bytes_arg(&message.signature)?,
+        ];
+        let secret = self.secret()?;
 
-    hex
+        self.invoke("onboard", args, || {
+            post_onboard(
+                &self.network,
+                self.contract,
+                secret,
+                message.clone(),
+                pubkey,
+            )
+        })
+        .await
+    }
+
+    async fn pending_registrations(
+        &self,
+        after: Option<String>,
+    ) -> anyhow::Result<Page<Registration<String, [u8; 32], EventLogRef>, String>> {
+        let index = self.events.sync(&self.rpc).await?;
+        let mut items = vec![];
+        let cursor = after.clone().max(index.last_id().map(String::from));
+        for event in index.registrations_after(after.as_deref()) {
+            let ClusterEvent::Register {
+                pubkey,
+                quote,
+                event_log_hash,
+                event_log_endpoint,
+                ..
+            } = &event.event
+            else {
+                continue;
+            };
+            // Anyone can register, malformed requests are skipped.
+            let Ok(pubkey) = hex::decode(pubkey)?.try_into() else {
+                eprintln!("Skipping registration with invalid pubkey {}", pubkey);
+                continue;
+            };
+            let Ok(sha384) = hex::decode(event_log_hash)?.try_into() else {
+                eprintln!(
+                    "Skipping registration with invalid event log hash {}",
+                    event_log_hash
+                );
+                continue;
+            };
+
+            items.push((
+                event.id.clone(),
+                Registration {
+                    quote: quote.clone(),
+                    pubkey,
+                    event_log: EventLogRef {
+                        sha384,
+                        endpoint: event_log_endpoint.clone(),
+                    },
+                },
+            ));
+        }
+
+        Ok(Page { items, cursor })
+    }
+
+    async fn get_onboarding(&self, pubkey: &[u8; 32]) -> anyhow::Result<Vec<SignedMessage>> {
+        Ok(self
+            .events
+            .sync(&self.rpc)
+            .await?
+            .onboarding(pubkey)
+            .to_vec())
+    }
+
+    async fn members(&self) -> anyhow::Result<Vec<[u8; 32]>> {
+        Ok(self.events.sync(&self.rpc).await?.members().to_vec())
+    }
+
+    async fn shared_pubkey(&self) -> anyhow::Result<Option<[u8; 32]>> {
+        get_shared_pubkey(&self.rpc, self.contract).await
+    }
+
+    async fn get_bootstrap(&self) -> anyhow::Result<Option<Bootstrap<String, [u8; 32]>>> {
+        get_bootstrap(&self.rpc, self.contract).await
+    }
+
+    async fn recent_challenges(&self, limit: u32) -> anyhow::Result<Vec<LedgerChallenge>> {
+        get_recent_ledgers(&self.network, limit).await
+    }
+}
+
+/// Fails on quotes that aren't hex, e.g from a malformed request.
+fn hex_to_b64(hex: &str) -> anyhow::Result<String> {
+    let bytes = hex::decode(hex).context("quote isn't hex-encoded")?;
+    Ok(BASE64_STANDARD.encode(bytes))
 }
 
 #[test]
@@ -229,3 +601,9 @@ fn test_size() {
     println!("{}", hex.as_bytes().len());
     println!("{}", base64.as_bytes().len());
 }
+
+#[test]
+fn refuses_quotes_that_arent_hex() {
+    assert_eq!(hex_to_b64("010203").unwrap(), "AQID");
+    assert!(hex_to_b64("not hex").is_err());
+}
@@ -0,0 +1,580 @@
+//! Follows the cluster contract's events through Soroban RPC `getEvents`, rather than relying on an indexer (the
+//! Zephyr program used to index every contract's events into its `pending` and `onboarded` tables).
+//!
+//! Events are filtered by contract and topic on the RPC side and decoded into [`ClusterEvent`]s, the same events the
+//! [`crate::LocalCoordination`] keeps. Quotes are published base64-encoded by the contract and hex-encoded here like
+//! everywhere else in new-york.
+//!
+//! Note: RPC nodes only keep a window of recent ledgers (a day by default), so without a persisted store a follower
+//! can only see what happened within that window. With a store new events and the cursor are appended to it
+//! whenever the cursor moves and a restarted follower replays it to pick up where it left off.
+//!
+//! Followed events aren't handed out as is, they're indexed as they come in (registrations in order, onboard
+//! messages by pubkey and the member list, see [`EventIndex`]).
+//!
+//! NB: anyone can register, events that don't decode (e.g a garbage quote or event log) are skipped. Onboard events
+//! are only published for messages signed by the cluster (see [`crate::signing`]), the signature is kept so that
+//! they can be checked again.
+//!
+
+use std::{collections::HashMap, fs::OpenOptions, io::Write, path::PathBuf};
+
+use anyhow::{anyhow, bail};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use stellar_xdr::curr::{
+    AccountId, Hash, Limits, PublicKey, ReadXdr, ScAddress, ScSymbol, ScVal, Uint256, WriteXdr,
+};
+use tokio::sync::{Mutex, MutexGuard};
+
+use super::rpc::{EventsFrom, RpcEvent, SorobanRpc};
+use crate::{election::Membership, ClusterEvent, SignedMessage};
+
+/// Maximum number of events fetched per `getEvents` call.
+pub const PAGE_LIMIT: u32 = 100;
+
+/// Topics published by the simple-cluster contract, the second topic is always the (shared or node) pubkey.
+const TOPICS: [&str; 3] = ["boot", "register", "onboard"];
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ContractEvent {
+    /// RPC event id, ids are zero-padded so they sort in the order events happened.
+    pub id: String,
+    pub ledger: u32,
+    pub event: ClusterEvent,
+}
+
+/// Lines of the store, a page's events are appended along with the cursor past them.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "record", rename_all = "snake_case")]
+enum StoreRecord {
+    Event(ContractEvent),
+    Cursor { cursor: String },
+}
+
+/// What the followed events are kept as.
+#[derive(Default)]
+pub struct EventIndex {
+    cursor: Option<String>,
+    /// Id of the last event indexed, so that events fetched again (e.g after a crash) aren't indexed twice.
+    last_id: Option<String>,
+    /// Register events in order.
+    registrations: Vec<ContractEvent>,
+    onboardings: HashMap<[u8; 32], Vec<SignedMessage>>,
+    membership: Membership,
+}
+
+impl EventIndex {
+    /// Indexes [`event`] unless it was already, returns whether it's new.
+    fn index(&mut self, event: &ContractEvent) -> bool {
+        if self.last_id.as_ref().is_some_and(|last| event.id <= *last) {
+            return false;
+        }
+        self.last_id = Some(event.id.clone());
+
+        self.membership.apply(&event.event);
+        match &event.event {
+            ClusterEvent::Register { .. } => self.registrations.push(event.clone()),
+            ClusterEvent::Onboard {
+                pubkey,
+                encrypted,
+                signature,
+            } => {
+                // Already checked to be hex by `decode_event`.
+                let Ok(pubkey) = hex::decode(pubkey).unwrap_or_default().try_into() else {
+                    eprintln!("Skipping onboard message for invalid pubkey {}", pubkey);
+                    return true;
+                };
+                self.onboardings
+                    .entry(pubkey)
+                    .or_default()
+                    .push(SignedMessage {
+                        encrypted: hex::decode(encrypted).unwrap_or_default(),
+                        signature: hex::decode(signature).unwrap_or_default(),
+                    });
+            }
+            ClusterEvent::Boot { .. } => (),
+        }
+
+        true
+    }
+
+    fn replay(&mut self, record: StoreRecord) {
+        match record {
+            StoreRecord::Event(event) => {
+                self.index(&event);
+            }
+            StoreRecord::Cursor { cursor } => self.cursor = Some(cursor),
+        }
+    }
+
+    /// Register events with an id greater than [`after`].
+    pub fn registrations_after(&self, after: Option<&str>) -> &[ContractEvent] {
+        let start = after.map_or(0, |after| {
+            self.registrations
+                .partition_point(|event| event.id.as_str() <= after)
+        });
+        &self.registrations[start..]
+    }
+
+    /// Id of the last event seen, if any.
+    pub fn last_id(&self) -> Option<&str> {
+        self.last_id.as_deref()
+    }
+
+    /// Onboard messages posted for [`pubkey`].
+    pub fn onboarding(&self, pubkey: &[u8; 32]) -> &[SignedMessage] {
+        self.onboardings
+            .get(pubkey)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    pub fn members(&self) -> &[[u8; 32]] {
+        self.membership.members()
+    }
+}
+
+pub struct EventFollower {
+    contract: [u8; 32],
+    /// Where to start when there's no cursor yet, the oldest ledger the RPC node knows of otherwise.
+    start_ledger: Option<u32>,
+    store: Option<PathBuf>,
+    index: Mutex<EventIndex>,
+}
+
+impl EventFollower {
+    /// A follower that only keeps events in memory.
+    pub fn new(contract: [u8; 32], start_ledger: Option<u32>) -> Self {
+        Self {
+            contract,
+            start_ledger,
+            store: None,
+            index: Mutex::new(EventIndex::default()),
+        }
+    }
+
+    /// A follower appending its events and cursor to [`path`], replaying them if the file exists.
+    pub fn with_store(
+        contract: [u8; 32],
+        start_ledger: Option<u32>,
+        path: impl Into<PathBuf>,
+    ) -> anyhow::Result<Self> {
+        let path = path.into();
+        let mut index = EventIndex::default();
+        match std::fs::read(&path) {
+            Ok(bytes) => {
+                // A crash while appending can leave a truncated line behind, which is dropped (what it held gets
+                // fetched again) rather than having the next append continue it.
+                let complete = bytes.iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1);
+                if complete < bytes.len() {
+                    OpenOptions::new()
+                        .write(true)
+                        .open(&path)?
+                        .set_len(complete as u64)?;
+                }
+                for line in bytes[..complete].split(|b| *b == b'\n') {
+                    if !line.is_empty() {
+                        index.replay(serde_json::from_slice(line)?);
+                    }
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => return Err(e.into()),
+        }
+
+        Ok(Self {
+            contract,
+            start_ledger,
+            store: Some(path),
+            index: Mutex::new(index),
+        })
+    }
+
+    fn append(&self, records: &[StoreRecord]) -> anyhow::Result<()> {
+        let Some(path) = &self.store else {
+            return Ok(());
+        };
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        // One write per page, so the cursor never lands without the events before it.
+        let mut lines = vec![];
+        for record in records {
+            serde_json::to_writer(&mut lines, record)?;
+            lines.push(b'\n');
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?
+            .write_all(&lines)?;
+
+        Ok(())
+    }
+
+    fn filters(&self) -> anyhow::Result<serde_json::Value> {
+        let topics = TOPICS
+            .iter()
+            .map(|topic| {
+                let symbol = ScVal::Symbol(ScSymbol((*topic).try_into()?));
+                Ok(json!([symbol.to_xdr_base64(Limits::none())?, "*"]))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(json!([{
+            "type": "contract",
+            "contractIds": [stellar_strkey::Contract(self.contract).to_string()],
+            "topics": topics,
+        }]))
+    }
+
+    /// Pages through the events published since the last sync and indexes them. The index stays locked until the
+    /// returned guard is dropped.
+    pub async fn sync(&self, rpc: &SorobanRpc) -> anyhow::Result<MutexGuard<'_, EventIndex>> {
+        let filters = self.filters()?;
+        let mut index = self.index.lock().await;
+
+        loop {
+            let from = match &index.cursor {
+                Some(cursor) => EventsFrom::Cursor(cursor.clone()),
+                None => match self.start_ledger {
+                    Some(ledger) => EventsFrom::Ledger(ledger),
+                    None => EventsFrom::Ledger(rpc.get_health().await?.oldest_ledger),
+                },
+            };
+            let page = rpc.get_events(&from, filters.clone(), PAGE_LIMIT).await?;
+
+            let mut records = vec![];
+            for event in page.events.iter() {
+                if !event.in_successful_contract_call {
+                    continue;
+                }
+                match decode_event(event) {
+                    Ok(decoded) => {
+                        let event = ContractEvent {
+                            id: event.id.clone(),
+                            ledger: event.ledger,
+                            event: decoded,
+                        };
+                        if index.index(&event) {
+                            records.push(StoreRecord::Event(event));
+                        }
+                    }
+                    Err(e) => eprintln!("Skipping event {}: {:?}", event.id, e),
+                }
+            }
+
+            // Older RPC versions don't return a cursor, the last event's id works the same.
+            let next = page
+                .cursor
+                .or_else(|| page.events.last().map(|event| event.id.clone()));
+            if let Some(cursor) = next.filter(|next| index.cursor.as_ref() != Some(next)) {
+                index.cursor = Some(cursor.clone());
+                records.push(StoreRecord::Cursor { cursor });
+            }
+            self.append(&records)?;
+            if page.events.len() < PAGE_LIMIT as usize {
+                break;
+            }
+        }
+
+        Ok(index)
+    }
+}
+
+/// Decodes a `boot`, `register` or `onboard` event of the simple-cluster contract.
+pub fn decode_event(event: &RpcEvent) -> anyhow::Result<ClusterEvent> {
+    let topics = event
+        .topic
+        .iter()
+        .map(|topic| ScVal::from_xdr_base64(topic, Limits::none()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let value = ScVal::from_xdr_base64(&event.value, Limits::none())?;
+
+    let [ScVal::Symbol(name), ScVal::String(pubkey)] = topics.as_slice() else {
+        bail!("unexpected topics {:?}", topics);
+    };
+    let pubkey = pubkey.to_utf8_string()?;
+    hex::decode(&pubkey)?;
+
+    match (name.0.to_utf8_string()?.as_str(), value) {
+        ("boot", ScVal::Vec(Some(values))) => {
+            let [ScVal::String(quote), ScVal::Bytes(signing_pubkey)] = values.as_slice() else {
+                bail!("unexpected boot value {:?}", values);
+            };
+            Ok(ClusterEvent::Boot {
+                shared_pubkey: pubkey,
+                quote: b64_to_hex(&quote.to_utf8_string()?)?,
+                signing_pubkey: hex::encode(signing_pubkey),
+            })
+        }
+        ("register", ScVal::Vec(Some(values))) => {
+            let [ScVal::String(quote), ScVal::Bytes(event_log_hash), ScVal::String(event_log_endpoint), ScVal::Address(host)] =
+                values.as_slice()
+            else {
+                bail!("unexpected register value {:?}", values);
+            };
+            if event_log_hash.len() != 48 {
+                bail!("unexpected event log hash {}", hex::encode(event_log_hash));
+            }
+            Ok(ClusterEvent::Register {
+                pubkey,
+                quote: b64_to_hex(&quote.to_utf8_string()?)?,
+                event_log_hash: hex::encode(event_log_hash),
+                event_log_endpoint: event_log_endpoint.to_utf8_string()?,
+                host: encode_address(host),
+            })
+        }
+        ("onboard", ScVal::Vec(Some(values))) => {
+            let [ScVal::String(encrypted), ScVal::Bytes(signature)] = values.as_slice() else {
+                bail!("unexpected onboard value {:?}", values);
+            };
+            let encrypted = encrypted.to_utf8_string()?;
+            hex::decode(&encrypted)?;
+            Ok(ClusterEvent::Onboard {
+                pubkey,
+                encrypted,
+                signature: hex::encode(signature),
+            })
+        }
+        (name, value) => Err(anyhow!("unexpected {} event with value {:?}", name, value)),
+    }
+}
+
+/// Strkey of an account (`G...`) or contract (`C...`) address.
+fn encode_address(address: &ScAddress) -> String {
+    match address {
+        ScAddress::Account(AccountId(PublicKey::PublicKeyTypeEd25519(Uint256(key)))) => {
+            stellar_strkey::ed25519::PublicKey(*key).to_string()
+        }
+        ScAddress::Contract(Hash(contract)) => stellar_strkey::Contract(*contract).to_string(),
+    }
+}
+
+fn b64_to_hex(b64: &str) -> anyhow::Result<String> {
+    Ok(hex::encode(BASE64_STANDARD.decode(b64)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stellar::rpc::{account_arg, bytes_arg, string_arg};
+    use stellar_xdr::curr::ScVec;
+
+    fn tuple(values: Vec<ScVal>) -> ScVal {
+        ScVal::Vec(Some(ScVec(values.try_into().unwrap())))
+    }
+
+    fn rpc_event(name: &str, pubkey: &str, value: ScVal) -> RpcEvent {
+        let symbol = ScVal::Symbol(ScSymbol(name.try_into().unwrap()));
+        RpcEvent {
+            id: "0000000042-0000000001".into(),
+            ledger: 42,
+            topic: vec![
+                symbol.to_xdr_base64(Limits::none()).unwrap(),
+                string_arg(pubkey)
+                    .unwrap()
+                    .to_xdr_base64(Limits::none())
+                    .unwrap(),
+            ],
+            value: value.to_xdr_base64(Limits::none()).unwrap(),
+            in_successful_contract_call: true,
+        }
+    }
+
+    #[test]
+    fn decodes_cluster_events() {
+        let quote = BASE64_STANDARD.encode([1, 2, 3]);
+        let boot = rpc_event(
+            "boot",
+            "aa",
+            tuple(vec![
+                string_arg(&quote).unwrap(),
+                bytes_arg(&[4; 32]).unwrap(),
+            ]),
+        );
+        assert_eq!(
+            decode_event(&boot).unwrap(),
+            ClusterEvent::Boot {
+                shared_pubkey: "aa".into(),
+                quote: "010203".into(),
+                signing_pubkey: hex::encode([4; 32]),
+            }
+        );
+
+        let register = rpc_event(
+            "register",
+            "bb",
+            tuple(vec![
+                string_arg(&quote).unwrap(),
+                bytes_arg(&[6; 48]).unwrap(),
+                string_arg("localhost:8000").unwrap(),
+                account_arg([7; 32]),
+            ]),
+        );
+        assert_eq!(
+            decode_event(&register).unwrap(),
+            ClusterEvent::Register {
+                pubkey: "bb".into(),
+                quote: "010203".into(),
+                event_log_hash: hex::encode([6; 48]),
+                event_log_endpoint: "localhost:8000".into(),
+                host: stellar_strkey::ed25519::PublicKey([7; 32]).to_string(),
+            }
+        );
+
+        let onboard = rpc_event(
+            "onboard",
+            "cc",
+            tuple(vec![
+                string_arg("dd").unwrap(),
+                bytes_arg(&[5; 64]).unwrap(),
+            ]),
+        );
+        assert_eq!(
+            decode_event(&onboard).unwrap(),
+            ClusterEvent::Onboard {
+                pubkey: "cc".into(),
+                encrypted: "dd".into(),
+                signature: hex::encode([5; 64]),
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_events() {
+        let signature = bytes_arg(&[5; 64]).unwrap();
+        // Not a hex pubkey.
+        let onboard = tuple(vec![string_arg("dd").unwrap(), signature.clone()]);
+        assert!(decode_event(&rpc_event("onboard", "node", onboard)).is_err());
+        // Unsigned onboard messages.
+        assert!(decode_event(&rpc_event("onboard", "cc", string_arg("dd").unwrap())).is_err());
+        // Not a base64 quote.
+        let boot = tuple(vec![
+            string_arg("%%").unwrap(),
+            bytes_arg(&[4; 32]).unwrap(),
+        ]);
+        assert!(decode_event(&rpc_event("boot", "aa", boot)).is_err());
+        // Register values are a (quote, event log hash, endpoint, host) tuple.
+        assert!(decode_event(&rpc_event("register", "bb", string_arg("AQID").unwrap())).is_err());
+        let register = tuple(vec![
+            string_arg("AQID").unwrap(),
+            bytes_arg(&[6; 32]).unwrap(),
+            string_arg("localhost:8000").unwrap(),
+            account_arg([7; 32]),
+        ]);
+        assert!(decode_event(&rpc_event("register", "bb", register)).is_err());
+        // Registrations from before the host was posted.
+        let register = tuple(vec![
+            string_arg("AQID").unwrap(),
+            bytes_arg(&[6; 48]).unwrap(),
+            string_arg("localhost:8000").unwrap(),
+        ]);
+        assert!(decode_event(&rpc_event("register", "bb", register)).is_err());
+        assert!(decode_event(&rpc_event("transfer", "aa", ScVal::Void)).is_err());
+    }
+
+    fn contract_event(ledger: u32, event: ClusterEvent) -> ContractEvent {
+        ContractEvent {
+            id: format!("{:010}-0000000001", ledger),
+            ledger,
+            event,
+        }
+    }
+
+    fn register(pubkey: u8) -> ContractEvent {
+        contract_event(
+            2,
+            ClusterEvent::Register {
+                pubkey: hex::encode([pubkey; 32]),
+                quote: "010203".into(),
+                event_log_hash: hex::encode([6; 48]),
+                event_log_endpoint: "localhost:8000".into(),
+                host: stellar_strkey::ed25519::PublicKey([7; 32]).to_string(),
+            },
+        )
+    }
+
+    #[test]
+    fn indexes_events_once() {
+        let boot = contract_event(
+            1,
+            ClusterEvent::Boot {
+                shared_pubkey: hex::encode([1; 32]),
+                quote: "010203".into(),
+                signing_pubkey: hex::encode([9; 32]),
+            },
+        );
+        let register = register(2);
+        let onboard = contract_event(
+            3,
+            ClusterEvent::Onboard {
+                pubkey: hex::encode([2; 32]),
+                encrypted: "dd".into(),
+                signature: hex::encode([5; 64]),
+            },
+        );
+
+        let mut index = EventIndex::default();
+        for event in [&boot, &register, &onboard] {
+            assert!(index.index(event));
+        }
+        // Fetched again, e.g after a crash.
+        assert!(!index.index(&register));
+        assert!(!index.index(&onboard));
+
+        assert_eq!(
+            index.registrations_after(None),
+            std::slice::from_ref(&register)
+        );
+        assert_eq!(index.registrations_after(Some(&boot.id)).len(), 1);
+        assert!(index.registrations_after(Some(&register.id)).is_empty());
+        assert_eq!(index.last_id(), Some(onboard.id.as_str()));
+        assert_eq!(
+            index.onboarding(&[2; 32]),
+            [SignedMessage {
+                encrypted: vec![0xdd],
+                signature: vec![5; 64],
+            }]
+        );
+        assert!(index.onboarding(&[3; 32]).is_empty());
+        assert_eq!(index.members(), [[1; 32], [2; 32]]);
+    }
+
+    #[test]
+    fn replays_the_store() {
+        let path =
+            std::env::temp_dir().join(format!("new-york-events-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let register = register(2);
+
+        let follower = EventFollower::with_store([0; 32], None, &path).unwrap();
+        follower
+            .append(&[
+                StoreRecord::Event(register.clone()),
+                StoreRecord::Cursor {
+                    cursor: register.id.clone(),
+                },
+            ])
+            .unwrap();
+        // A crash halfway through appending the next page.
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(br#"{"record":"ev"#)
+            .unwrap();
+
+        let follower = EventFollower::with_store([0; 32], None, &path).unwrap();
+        let index = follower.index.try_lock().unwrap();
+        assert_eq!(index.cursor, Some(register.id.clone()));
+        assert_eq!(index.registrations_after(None), [register]);
+        // The truncated line is dropped so that the next page starts on a line of its own.
+        assert!(std::fs::read(&path).unwrap().ends_with(b"\n"));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,150 @@
+//! Which Stellar network the cluster contract lives on. The passphrase goes into every transaction signature (a
+//! transaction signed for testnet isn't valid on mainnet), the endpoints are where we read from and submit to.
+//!
+//! Note: SDF doesn't run a public Soroban RPC for mainnet, so one has to be provided through `SOROBAN_RPC`. Also
+//! the quickstart image serves a standalone network on port 8000 by default, which is the host's default port too,
+//! so either has to be moved when running both on the same machine.
+//!
+
+use anyhow::{anyhow, bail};
+
+pub const MAINNET_PASSPHRASE: &str = "Public Global Stellar Network ; September 2015";
+pub const TESTNET_PASSPHRASE: &str = "Test SDF Network ; September 2015";
+pub const FUTURENET_PASSPHRASE: &str = "Test SDF Future Network ; October 2022";
+/// Default passphrase of the quickstart image's standalone network.
+pub const STANDALONE_PASSPHRASE: &str = "Standalone Network ; February 2017";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StellarNetwork {
+    pub passphrase: String,
+    pub soroban_rpc: String,
+    /// Only used to read recent ledgers for challenges.
+    pub horizon: String,
+    /// Mercury's Zephyr endpoint to have transactions built with `ZEPHYR`, our program is only deployed on testnet.
+    pub zephyr: Option<String>,
+}
+
+impl StellarNetwork {
+    pub fn mainnet(soroban_rpc: impl Into<String>) -> Self {
+        Self {
+            passphrase: MAINNET_PASSPHRASE.into(),
+            soroban_rpc: soroban_rpc.into(),
+            horizon: "https://horizon.stellar.org".into(),
+            zephyr: None,
+        }
+    }
+
+    pub fn testnet() -> Self {
+        Self {
+            passphrase: TESTNET_PASSPHRASE.into(),
+            soroban_rpc: "https://soroban-testnet.stellar.org".into(),
+            horizon: "https://horizon-testnet.stellar.org".into(),
+            zephyr: Some("https://api.mercurydata.app/zephyr/execute/113".into()),
+        }
+    }
+
+    pub fn futurenet() -> Self {
+        Self {
+            passphrase: FUTURENET_PASSPHRASE.into(),
+            soroban_rpc: "https://rpc-futurenet.stellar.org".into(),
+            horizon: "https://horizon-futurenet.stellar.org".into(),
+            zephyr: None,
+        }
+    }
+
+    /// A local standalone network, e.g the quickstart image at [`url`] (`http://localhost:8000` by default).
+    pub fn standalone(url: &str, passphrase: impl Into<String>) -> Self {
+        let url = url.trim_end_matches('/');
+        Self {
+            passphrase: passphrase.into(),
+            soroban_rpc: format!("{}/soroban/rpc", url),
+            horizon: url.into(),
+            zephyr: None,
+        }
+    }
+
+    /// One of `mainnet`, `testnet`, `futurenet` or `standalone` with its defaults. Mainnet needs [`soroban_rpc`].
+    pub fn from_name(name: &str, soroban_rpc: Option<String>) -> anyhow::Result<Self> {
+        let network = match name {
+            "mainnet" => Self::mainnet(
+                soroban_rpc
+                    .clone()
+                    .ok_or(anyhow!("a Soroban RPC endpoint is needed for mainnet"))?,
+            ),
+            "testnet" => Self::testnet(),
+            "futurenet" => Self::futurenet(),
+            "standalone" => Self::standalone("http://localhost:8000", STANDALONE_PASSPHRASE),
+            other => bail!("unknown Stellar network {}", other),
+        };
+
+        Ok(Self {
+            soroban_rpc: soroban_rpc.unwrap_or(network.soroban_rpc.clone()),
+            ..network
+        })
+    }
+
+    /// Picks the network from `STELLAR_NETWORK` (testnet by default), each of its settings can be overridden with
+    /// `NETWORK_PASSPHRASE`, `SOROBAN_RPC`, `HORIZON` and `ZEPHYR_URL`.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let name = std::env::var("STELLAR_NETWORK").unwrap_or("testnet".into());
+        let mut network = Self::from_name(&name, std::env::var("SOROBAN_RPC").ok())?;
+
+        if let Ok(passphrase) = std::env::var("NETWORK_PASSPHRASE") {
+            network.passphrase = passphrase;
+        }
+        if let Ok(horizon) = std::env::var("HORIZON") {
+            network.horizon = horizon;
+        }
+        if let Ok(zephyr) = std::env::var("ZEPHYR_URL") {
+            network.zephyr = Some(zephyr);
+        }
+
+        Ok(network)
+    }
+
+    pub fn zephyr(&self) -> anyhow::Result<&str> {
+        self.zephyr.as_deref().ok_or(anyhow!(
+            "no Zephyr endpoint for this network, set ZEPHYR_URL"
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stellar::{rpc::build_invoke_transaction, utils::hash_transaction};
+
+    #[test]
+    fn picks_networks_by_name() {
+        assert_eq!(
+            StellarNetwork::from_name("testnet", None).unwrap(),
+            StellarNetwork::testnet()
+        );
+        assert!(StellarNetwork::from_name("mainnet", None).is_err());
+        assert!(StellarNetwork::from_name("devnet", None).is_err());
+
+        let mainnet =
+            StellarNetwork::from_name("mainnet", Some("https://rpc.example.org".into())).unwrap();
+        assert_eq!(mainnet.passphrase, MAINNET_PASSPHRASE);
+        assert_eq!(mainnet.soroban_rpc, "https://rpc.example.org");
+
+        let standalone = StellarNetwork::from_name("standalone", None).unwrap();
+        assert_eq!(standalone.soroban_rpc, "http://localhost:8000/soroban/rpc");
+        assert!(standalone.zephyr().is_err());
+    }
+
+    #[test]
+    fn signatures_are_bound_to_the_network() {
+        let tx = build_invoke_transaction([1; 32], 1, [2; 32], "onboard", vec![]).unwrap();
+        let testnet = hash_transaction(&tx, &StellarNetwork::testnet().passphrase).unwrap();
+        let futurenet = hash_transaction(&tx, &StellarNetwork::futurenet().passphrase).unwrap();
+        let standalone = hash_transaction(
+            &tx,
+            &StellarNetwork::standalone("http://localhost:8000", "My Network").passphrase,
+        )
+        .unwrap();
+
+        assert_ne!(testnet, futurenet);
+        assert_ne!(testnet, standalone);
+    }
+}
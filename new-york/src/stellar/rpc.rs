@@ -0,0 +1,414 @@
+//! Minimal Soroban RPC client, enough to build, simulate and submit contract calls ourselves rather than trusting a
+//! third party (e.g Zephyr) with building the transactions we sign.
+//!
+//! Contract calls go through the usual flow: build an `invokeHostFunction` transaction with the source account's next
+//! sequence number, simulate it to get the footprint, authorizations and resource fee, assemble them into the
+//! transaction and submit it once signed.
+//!
+//! Contract events are read through `getEvents`, see [`super::events`] for following the cluster's.
+//!
+
+use anyhow::anyhow;
+use reqwest::Client;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::json;
+use stellar_xdr::curr::{
+    AccountId, Hash, HostFunction, InvokeContractArgs, InvokeHostFunctionOp, LedgerEntryData,
+    LedgerKey, LedgerKeyAccount, Limits, Memo, MuxedAccount, Operation, OperationBody,
+    Preconditions, PublicKey, ReadXdr, ScAddress, ScBytes, ScString, ScSymbol, ScVal,
+    SequenceNumber, SorobanAuthorizationEntry, SorobanTransactionData, Transaction,
+    TransactionEnvelope, TransactionExt, TransactionV1Envelope, Uint256, WriteXdr,
+};
+
+use super::submit::SubmitError;
+
+/// Inclusion fee, the resource fee from simulation is added on top of it.
+pub const BASE_FEE: u32 = 100;
+
+#[derive(Deserialize, Debug)]
+struct RpcResponse<T> {
+    result: Option<T>,
+    error: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize, Debug)]
+struct LedgerEntryResult {
+    /// Base64 `LedgerEntryData`.
+    xdr: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LedgerEntriesResponse {
+    #[serde(default)]
+    entries: Vec<LedgerEntryResult>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SimulationResult {
+    /// Base64 `SorobanAuthorizationEntry`s.
+    #[serde(default)]
+    pub auth: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulateTransactionResponse {
+    pub error: Option<String>,
+    /// Base64 `SorobanTransactionData`.
+    pub transaction_data: Option<String>,
+    pub min_resource_fee: Option<String>,
+    #[serde(default)]
+    pub results: Vec<SimulationResult>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SendTransactionResponse {
+    /// One of PENDING, DUPLICATE, TRY_AGAIN_LATER or ERROR.
+    pub status: String,
+    pub hash: String,
+    /// Base64 `TransactionResult` when the status is ERROR.
+    pub error_result_xdr: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GetTransactionResponse {
+    /// One of SUCCESS, FAILED or NOT_FOUND (not in a ledger yet, or not anymore within the retention window).
+    pub status: String,
+    pub ledger: Option<u32>,
+    /// Base64 `TransactionResult`.
+    pub result_xdr: Option<String>,
+}
+
+/// A contract event as returned by `getEvents`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcEvent {
+    /// Unique and ordered, can be used as paging cursor.
+    pub id: String,
+    pub ledger: u32,
+    /// Base64 `ScVal`s.
+    pub topic: Vec<String>,
+    /// Base64 `ScVal`.
+    pub value: String,
+    #[serde(default = "successful")]
+    pub in_successful_contract_call: bool,
+}
+
+fn successful() -> bool {
+    true
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GetEventsResponse {
+    #[serde(default)]
+    pub events: Vec<RpcEvent>,
+    /// Where the next page starts, only returned by recent RPC versions.
+    pub cursor: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GetHealthResponse {
+    /// Oldest ledger still within the node's retention window.
+    pub oldest_ledger: u32,
+}
+
+/// Where to start reading events from, `getEvents` takes either but not both.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EventsFrom {
+    Ledger(u32),
+    Cursor(String),
+}
+
+pub struct SorobanRpc {
+    url: String,
+    client: Client,
+}
+
+impl SorobanRpc {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            client: Client::new(),
+        }
+    }
+
+    async fn request<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> anyhow::Result<T> {
+        let response: RpcResponse<T> = self
+            .client
+            .post(&self.url)
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": method,
+                "params": params,
+            }))
+            .send()
+            .await?
+            .json()
+            .await?;
+
+        match (response.result, response.error) {
+            (Some(result), None) => Ok(result),
+            (_, error) => Err(anyhow!("{} failed: {:?}", method, error)),
+        }
+    }
+
+    /// Entries that don't exist are left out.
+    pub async fn get_ledger_entries(
+        &self,
+        keys: &[LedgerKey],
+    ) -> anyhow::Result<Vec<LedgerEntryData>> {
+        let keys = keys
+            .iter()
+            .map(|key| key.to_xdr_base64(Limits::none()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let response: LedgerEntriesResponse = self
+            .request("getLedgerEntries", json!({ "keys": keys }))
+            .await?;
+
+        response
+            .entries
+            .iter()
+            .map(|entry| {
+                Ok(LedgerEntryData::from_xdr_base64(
+                    &entry.xdr,
+                    Limits::none(),
+                )?)
+            })
+            .collect()
+    }
+
+    /// Returns the sequence number to use for the next transaction of [`account`].
+    pub async fn next_sequence(&self, account: [u8; 32]) -> anyhow::Result<i64> {
+        let key = LedgerKey::Account(LedgerKeyAccount {
+            account_id: AccountId(PublicKey::PublicKeyTypeEd25519(Uint256(account))),
+        });
+
+        match self.get_ledger_entries(&[key]).await?.pop() {
+            Some(LedgerEntryData::Account(account)) => Ok(account.seq_num.0 + 1),
+            _ => Err(anyhow!(
+                "account {} not found",
+                stellar_strkey::ed25519::PublicKey(account)
+            )),
+        }
+    }
+
+    pub async fn simulate_transaction(
+        &self,
+        tx: &Transaction,
+    ) -> anyhow::Result<SimulateTransactionResponse> {
+        let envelope = TransactionEnvelope::Tx(TransactionV1Envelope {
+            tx: tx.clone(),
+            signatures: Default::default(),
+        });
+
+        self.request(
+            "simulateTransaction",
+            json!({ "transaction": envelope.to_xdr_base64(Limits::none())? }),
+        )
+        .await
+    }
+
+    pub async fn get_health(&self) -> anyhow::Result<GetHealthResponse> {
+        self.request("getHealth", json!({})).await
+    }
+
+    /// One page of contract events matching [`filters`] (see the `getEvents` docs for their format).
+    pub async fn get_events(
+        &self,
+        from: &EventsFrom,
+        filters: serde_json::Value,
+        limit: u32,
+    ) -> anyhow::Result<GetEventsResponse> {
+        let params = match from {
+            EventsFrom::Ledger(ledger) => json!({
+                "startLedger": ledger,
+                "filters": filters,
+                "pagination": { "limit": limit },
+            }),
+            EventsFrom::Cursor(cursor) => json!({
+                "filters": filters,
+                "pagination": { "cursor": cursor, "limit": limit },
+            }),
+        };
+
+        self.request("getEvents", params).await
+    }
+
+    /// Submits a signed base64 envelope. NB: a PENDING status only means it was accepted by the node.
+    pub async fn send_transaction(
+        &self,
+        envelope: &str,
+    ) -> anyhow::Result<SendTransactionResponse> {
+        self.request("sendTransaction", json!({ "transaction": envelope }))
+            .await
+    }
+
+    pub async fn get_transaction(&self, hash: &str) -> anyhow::Result<GetTransactionResponse> {
+        self.request("getTransaction", json!({ "hash": hash }))
+            .await
+    }
+}
+
+/// Builds an unsigned call of [`function`] on [`contract`] with no footprint yet, see [`assemble`].
+pub fn build_invoke_transaction(
+    source: [u8; 32],
+    sequence: i64,
+    contract: [u8; 32],
+    function: &str,
+    args: Vec<ScVal>,
+) -> anyhow::Result<Transaction> {
+    let operation = Operation {
+        source_account: None,
+        body: OperationBody::InvokeHostFunction(InvokeHostFunctionOp {
+            host_function: HostFunction::InvokeContract(InvokeContractArgs {
+                contract_address: ScAddress::Contract(Hash(contract)),
+                function_name: ScSymbol(function.try_into()?),
+                args: args.try_into()?,
+            }),
+            auth: Default::default(),
+        }),
+    };
+
+    Ok(Transaction {
+        source_account: MuxedAccount::Ed25519(Uint256(source)),
+        fee: BASE_FEE,
+        seq_num: SequenceNumber(sequence),
+        cond: Preconditions::None,
+        memo: Memo::None,
+        operations: vec![operation].try_into()?,
+        ext: TransactionExt::V0,
+    })
+}
+
+/// Sets the footprint, authorizations and resource fee from [`simulation`] on [`tx`].
+pub fn assemble(
+    mut tx: Transaction,
+    simulation: &SimulateTransactionResponse,
+) -> anyhow::Result<Transaction> {
+    if let Some(error) = &simulation.error {
+        return Err(SubmitError::ContractPanic(error.clone()).into());
+    }
+    let transaction_data = simulation
+        .transaction_data
+        .as_ref()
+        .ok_or(anyhow!("simulation returned no transaction data"))?;
+    let resource_fee: u32 = simulation
+        .min_resource_fee
+        .as_ref()
+        .ok_or(anyhow!("simulation returned no resource fee"))?
+        .parse()?;
+    let auth = simulation
+        .results
+        .first()
+        .map(|result| {
+            result
+                .auth
+                .iter()
+                .map(|auth| SorobanAuthorizationEntry::from_xdr_base64(auth, Limits::none()))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()?
+        .unwrap_or_default();
+
+    let mut operations = tx.operations.to_vec();
+    let [Operation {
+        body: OperationBody::InvokeHostFunction(invoke),
+        ..
+    }] = operations.as_mut_slice()
+    else {
+        return Err(anyhow!("expected a single invokeHostFunction operation"));
+    };
+    invoke.auth = auth.try_into()?;
+
+    tx.operations = operations.try_into()?;
+    tx.ext = TransactionExt::V1(SorobanTransactionData::from_xdr_base64(
+        transaction_data,
+        Limits::none(),
+    )?);
+    tx.fee = tx
+        .fee
+        .checked_add(resource_fee)
+        .ok_or(anyhow!("fee overflow"))?;
+
+    Ok(tx)
+}
+
+/// Encodes a string argument for the simple-cluster contract.
+pub fn string_arg(value: &str) -> anyhow::Result<ScVal> {
+    Ok(ScVal::String(ScString(value.try_into()?)))
+}
+
+/// An `Address` argument for the account with ed25519 key [`pubkey`].
+pub fn account_arg(pubkey: [u8; 32]) -> ScVal {
+    ScVal::Address(ScAddress::Account(AccountId(
+        PublicKey::PublicKeyTypeEd25519(Uint256(pubkey)),
+    )))
+}
+
+/// e.g a `BytesN<32>` argument.
+pub fn bytes_arg(value: &[u8]) -> anyhow::Result<ScVal> {
+    Ok(ScVal::Bytes(ScBytes(value.try_into()?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{prelude::BASE64_STANDARD, Engine};
+
+    fn simulation(resource_fee: &str) -> SimulateTransactionResponse {
+        // Empty footprint, 1 instruction, no reads nor writes and a resource fee of 1000.
+        let mut transaction_data = vec![0; 12];
+        transaction_data.extend(1_u32.to_be_bytes());
+        transaction_data.extend([0; 8]);
+        transaction_data.extend(1000_i64.to_be_bytes());
+
+        SimulateTransactionResponse {
+            error: None,
+            transaction_data: Some(BASE64_STANDARD.encode(transaction_data)),
+            min_resource_fee: Some(resource_fee.into()),
+            results: vec![SimulationResult { auth: vec![] }],
+        }
+    }
+
+    #[test]
+    fn builds_and_assembles_contract_call() {
+        let tx = build_invoke_transaction(
+            [1; 32],
+            42,
+            [2; 32],
+            "register",
+            vec![string_arg("pubkey").unwrap(), string_arg("quote").unwrap()],
+        )
+        .unwrap();
+        assert_eq!(tx.seq_num, SequenceNumber(42));
+        assert_eq!(tx.ext, TransactionExt::V0);
+
+        let assembled = assemble(tx.clone(), &simulation("1000")).unwrap();
+        assert_eq!(assembled.fee, BASE_FEE + 1000);
+        assert_eq!(assembled.operations, tx.operations);
+        let TransactionExt::V1(data) = assembled.ext else {
+            panic!("missing soroban data");
+        };
+        assert_eq!(data.resource_fee, 1000);
+        assert_eq!(data.resources.instructions, 1);
+    }
+
+    #[test]
+    fn rejects_failed_simulation() {
+        let tx = build_invoke_transaction([1; 32], 1, [2; 32], "onboard", vec![]).unwrap();
+        let mut failed = simulation("1000");
+        failed.error = Some("HostError: contract panicked".into());
+        assert!(assemble(tx.clone(), &failed).is_err());
+        assert!(assemble(tx, &simulation("not a fee")).is_err());
+    }
+}
@@ -0,0 +1,319 @@
+//! Submits the host's contract calls and waits for them to be included in a ledger.
+//!
+//! Sequence numbers are tracked locally rather than fetched for every transaction, so that the onboard thread and
+//! incoming register requests don't race for the same one: submissions are serialized and the next sequence is only
+//! refetched from the RPC when the network says ours is wrong (or we can't tell whether the last one was used).
+//!
+//! Failures are classified from the transaction result so that the ones that can be fixed by resubmitting (bad
+//! sequence, insufficient inclusion fee, a busy node) are retried, each time with a higher inclusion fee. The fee
+//! is still bounded by the maximum the host is willing to sign for (see [`super::validate`]).
+//!
+//! NB: a transaction that made it into a ledger but failed (e.g the contract panicked) still consumes the sequence
+//! number and is not retried, resubmitting the same call would fail the same way.
+//!
+
+use std::{fmt, future::Future, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use stellar_xdr::curr::{
+    InvokeHostFunctionResult, Limits, OperationResult, OperationResultTr, ReadXdr, Transaction,
+    TransactionResult, TransactionResultResult,
+};
+use tokio::{sync::Mutex, time::sleep};
+
+use super::{
+    rpc::{SorobanRpc, BASE_FEE},
+    utils::sign_transaction,
+    validate::{validate_transaction, ExpectedCall},
+};
+
+pub const MAX_ATTEMPTS: u32 = 3;
+/// Each retry multiplies the inclusion fee bump by this.
+pub const FEE_BUMP_FACTOR: u32 = 10;
+/// How long to wait for a transaction to be included, a few ledgers.
+pub const CONFIRMATION_TIMEOUT_SECS: u64 = 60;
+const POLL_INTERVAL_SECS: u64 = 2;
+
+/// A contract call included in a ledger.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TxReceipt {
+    /// hex-encoded.
+    pub hash: String,
+    pub ledger: u32,
+    /// In stroops.
+    pub fee_charged: i64,
+    pub attempts: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SubmitError {
+    BadSequence,
+    InsufficientFee,
+    /// The node asked us to resubmit later.
+    TryAgainLater,
+    /// The contract call failed, either in simulation or once applied.
+    ContractPanic(String),
+    /// Any other failure, with the result code.
+    Rejected(String),
+    /// Not included within [`CONFIRMATION_TIMEOUT_SECS`], it might still be.
+    Timeout(String),
+}
+
+impl SubmitError {
+    pub fn retryable(&self) -> bool {
+        matches!(
+            self,
+            SubmitError::BadSequence | SubmitError::InsufficientFee | SubmitError::TryAgainLater
+        )
+    }
+}
+
+impl fmt::Display for SubmitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubmitError::BadSequence => write!(f, "bad sequence number"),
+            SubmitError::InsufficientFee => write!(f, "insufficient fee"),
+            SubmitError::TryAgainLater => write!(f, "node asked to try again later"),
+            SubmitError::ContractPanic(error) => write!(f, "contract call failed: {}", error),
+            SubmitError::Rejected(code) => write!(f, "transaction rejected with {}", code),
+            SubmitError::Timeout(hash) => write!(f, "transaction {} wasn't included in time", hash),
+        }
+    }
+}
+
+impl std::error::Error for SubmitError {}
+
+/// `None` if the transaction succeeded.
+pub fn classify(result: &TransactionResult) -> Option<SubmitError> {
+    match &result.result {
+        TransactionResultResult::TxSuccess(_)
+        | TransactionResultResult::TxFeeBumpInnerSuccess(_) => None,
+        TransactionResultResult::TxBadSeq => Some(SubmitError::BadSequence),
+        TransactionResultResult::TxInsufficientFee => Some(SubmitError::InsufficientFee),
+        TransactionResultResult::TxFailed(operations)
+            if operations.iter().any(|operation| {
+                matches!(
+                    operation,
+                    OperationResult::OpInner(OperationResultTr::InvokeHostFunction(
+                        InvokeHostFunctionResult::Trapped
+                    ))
+                )
+            }) =>
+        {
+            Some(SubmitError::ContractPanic("trapped".into()))
+        }
+        other => Some(SubmitError::Rejected(other.name().into())),
+    }
+}
+
+fn decode_result(result_xdr: Option<&String>) -> anyhow::Result<TransactionResult> {
+    let result_xdr = result_xdr.ok_or(anyhow::anyhow!("missing transaction result"))?;
+    Ok(TransactionResult::from_xdr_base64(
+        result_xdr,
+        Limits::none(),
+    )?)
+}
+
+/// How much is added to the inclusion fee on the [`attempt`]th try (starting at 0).
+pub fn fee_bump(attempt: u32) -> u32 {
+    BASE_FEE.saturating_mul(FEE_BUMP_FACTOR.saturating_pow(attempt) - 1)
+}
+
+/// Submits the transactions of a single source account.
+#[derive(Default)]
+pub struct Submitter {
+    /// Next sequence number to use, `None` when it has to be fetched.
+    sequence: Mutex<Option<i64>>,
+}
+
+impl Submitter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds [`expected`] through [`build`] (with the sequence filled in), checks, signs and submits it until it is
+    /// included or fails for a reason that resubmitting won't fix.
+    pub async fn submit<F, Fut>(
+        &self,
+        rpc: &SorobanRpc,
+        secret: [u8; 32],
+        network_passphrase: &str,
+        mut expected: ExpectedCall,
+        build: F,
+    ) -> anyhow::Result<TxReceipt>
+    where
+        F: Fn(ExpectedCall) -> Fut,
+        Fut: Future<Output = anyhow::Result<Transaction>>,
+    {
+        let secret = stellar_strkey::ed25519::PrivateKey(secret).to_string();
+        let mut sequence = self.sequence.lock().await;
+
+        let mut attempt = 0;
+        loop {
+            expected.sequence = match *sequence {
+                Some(sequence) => sequence,
+                None => rpc.next_sequence(expected.source).await?,
+            };
+            let mut tx = build(expected.clone()).await?;
+            tx.fee = tx.fee.saturating_add(fee_bump(attempt));
+            validate_transaction(&tx, &expected)?;
+            let signed = sign_transaction(tx, network_passphrase, &secret);
+
+            let error = match self.send_and_wait(rpc, &signed).await {
+                Ok((hash, ledger, fee_charged)) => {
+                    *sequence = Some(expected.sequence + 1);
+                    return Ok(TxReceipt {
+                        hash,
+                        ledger,
+                        fee_charged,
+                        attempts: attempt + 1,
+                    });
+                }
+                Err((error, included)) => {
+                    *sequence = match (&error, included) {
+                        (SubmitError::BadSequence | SubmitError::Timeout(_), _) => None,
+                        (_, true) => Some(expected.sequence + 1),
+                        (_, false) => Some(expected.sequence),
+                    };
+                    error
+                }
+            };
+
+            attempt += 1;
+            if !error.retryable() || attempt >= MAX_ATTEMPTS {
+                return Err(error.into());
+            }
+            eprintln!(
+                "{} transaction failed ({}), retrying",
+                expected.function, error
+            );
+            if error == SubmitError::TryAgainLater {
+                sleep(Duration::from_secs(POLL_INTERVAL_SECS)).await;
+            }
+        }
+    }
+
+    /// Returns the hash, ledger and fee charged, or the error along with whether the transaction made it into a
+    /// ledger (and so used its sequence number).
+    async fn send_and_wait(
+        &self,
+        rpc: &SorobanRpc,
+        signed: &str,
+    ) -> Result<(String, u32, i64), (SubmitError, bool)> {
+        let rejected = |e: anyhow::Error| (SubmitError::Rejected(e.to_string()), false);
+
+        let response = rpc.send_transaction(signed).await.map_err(rejected)?;
+        match response.status.as_str() {
+            "PENDING" | "DUPLICATE" => (),
+            "TRY_AGAIN_LATER" => return Err((SubmitError::TryAgainLater, false)),
+            _ => {
+                let result = decode_result(response.error_result_xdr.as_ref()).map_err(rejected)?;
+                let error =
+                    classify(&result).unwrap_or(SubmitError::Rejected(response.status.clone()));
+                return Err((error, false));
+            }
+        }
+        println!("Submitted transaction {}", response.hash);
+
+        let mut waited = 0;
+        while waited < CONFIRMATION_TIMEOUT_SECS {
+            sleep(Duration::from_secs(POLL_INTERVAL_SECS)).await;
+            waited += POLL_INTERVAL_SECS;
+
+            let transaction = match rpc.get_transaction(&response.hash).await {
+                Ok(transaction) => transaction,
+                Err(e) => {
+                    eprintln!("Couldn't get transaction {}: {:?}", response.hash, e);
+                    continue;
+                }
+            };
+            if transaction.status == "NOT_FOUND" {
+                continue;
+            }
+
+            let result = decode_result(transaction.result_xdr.as_ref())
+                .map_err(|e| (SubmitError::Rejected(e.to_string()), true))?;
+            return match classify(&result) {
+                None => Ok((
+                    response.hash,
+                    transaction.ledger.unwrap_or_default(),
+                    result.fee_charged,
+                )),
+                Some(error) => Err((error, true)),
+            };
+        }
+
+        Err((SubmitError::Timeout(response.hash), false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stellar_xdr::curr::{
+        Hash, InnerTransactionResult, InnerTransactionResultExt, InnerTransactionResultPair,
+        InnerTransactionResultResult, TransactionResultExt, VecM,
+    };
+
+    fn result(result: TransactionResultResult) -> TransactionResult {
+        TransactionResult {
+            fee_charged: 100,
+            result,
+            ext: TransactionResultExt::V0,
+        }
+    }
+
+    #[test]
+    fn classifies_results() {
+        assert_eq!(
+            classify(&result(TransactionResultResult::TxSuccess(VecM::default()))),
+            None
+        );
+        assert_eq!(
+            classify(&result(TransactionResultResult::TxBadSeq)),
+            Some(SubmitError::BadSequence)
+        );
+        assert_eq!(
+            classify(&result(TransactionResultResult::TxInsufficientFee)),
+            Some(SubmitError::InsufficientFee)
+        );
+
+        let trapped = vec![OperationResult::OpInner(
+            OperationResultTr::InvokeHostFunction(InvokeHostFunctionResult::Trapped),
+        )];
+        let panicked = classify(&result(TransactionResultResult::TxFailed(
+            trapped.try_into().unwrap(),
+        )))
+        .unwrap();
+        assert!(matches!(panicked, SubmitError::ContractPanic(_)));
+        assert!(!panicked.retryable());
+
+        let rejected = classify(&result(TransactionResultResult::TxBadAuth)).unwrap();
+        assert_eq!(rejected, SubmitError::Rejected("TxBadAuth".into()));
+        assert!(!rejected.retryable());
+
+        // A fee bump of a failed transaction isn't a success either.
+        let inner = InnerTransactionResultPair {
+            transaction_hash: Hash([0; 32]),
+            result: InnerTransactionResult {
+                fee_charged: 100,
+                result: InnerTransactionResultResult::TxBadSeq,
+                ext: InnerTransactionResultExt::V0,
+            },
+        };
+        assert!(
+            classify(&result(TransactionResultResult::TxFeeBumpInnerFailed(
+                inner
+            )))
+            .is_some()
+        );
+    }
+
+    #[test]
+    fn bumps_fees() {
+        assert_eq!(fee_bump(0), 0);
+        assert_eq!(fee_bump(1), 900);
+        assert_eq!(fee_bump(2), 9900);
+        assert_eq!(fee_bump(20), u32::MAX);
+    }
+}
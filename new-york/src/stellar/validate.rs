@@ -0,0 +1,206 @@
+//! Checks on transactions before the host signs them.
+//!
+//! When transactions are built by a third party (the Zephyr program) the host would otherwise sign anything it gets
+//! back, e.g a payment draining the account. Transactions built locally are checked too since simulation results
+//! (authorizations and fees) come from the RPC.
+//!
+//! NB: the footprint can't be checked without simulating ourselves, a wrong one only makes the transaction fail.
+//!
+
+use anyhow::{anyhow, bail};
+use stellar_xdr::curr::{
+    Hash, HostFunction, InvokeContractArgs, MuxedAccount, Operation, OperationBody, Preconditions,
+    ScAddress, ScVal, SorobanAuthorizedFunction, SorobanCredentials, Transaction, Uint256,
+};
+
+/// Default upper bound for the total fee (inclusion + resources) of a transaction, 1 XLM.
+pub const DEFAULT_MAX_FEE: u32 = 10_000_000;
+
+/// The contract call we asked for.
+#[derive(Clone, Debug)]
+pub struct ExpectedCall {
+    pub source: [u8; 32],
+    pub sequence: i64,
+    pub contract: [u8; 32],
+    pub function: String,
+    pub args: Vec<ScVal>,
+    pub max_fee: u32,
+}
+
+impl ExpectedCall {
+    fn matches(&self, call: &InvokeContractArgs) -> bool {
+        call.contract_address == ScAddress::Contract(Hash(self.contract))
+            && call.function_name.0.as_slice() == self.function.as_bytes()
+            && call.args.as_slice() == self.args.as_slice()
+    }
+}
+
+/// Refuses [`tx`] unless it is exactly the [`expected`] call from our account.
+pub fn validate_transaction(tx: &Transaction, expected: &ExpectedCall) -> anyhow::Result<()> {
+    if tx.source_account != MuxedAccount::Ed25519(Uint256(expected.source)) {
+        bail!("unexpected source account {:?}", tx.source_account);
+    }
+    if tx.seq_num.0 != expected.sequence {
+        bail!(
+            "unexpected sequence {}, expected {}",
+            tx.seq_num.0,
+            expected.sequence
+        );
+    }
+    if tx.fee > expected.max_fee {
+        bail!("fee {} is above the maximum {}", tx.fee, expected.max_fee);
+    }
+    // Time bounds are harmless, other preconditions (e.g extra signers) aren't expected.
+    if !matches!(tx.cond, Preconditions::None | Preconditions::Time(_)) {
+        bail!("unexpected preconditions {:?}", tx.cond);
+    }
+
+    let [Operation {
+        source_account: None,
+        body: OperationBody::InvokeHostFunction(invoke),
+    }] = tx.operations.as_slice()
+    else {
+        bail!("expected a single contract call from the transaction source");
+    };
+    let HostFunction::InvokeContract(call) = &invoke.host_function else {
+        bail!("unexpected host function {:?}", invoke.host_function);
+    };
+    if !expected.matches(call) {
+        return Err(anyhow!(
+            "unexpected contract call {}",
+            call.function_name.0.to_utf8_string_lossy()
+        ));
+    }
+
+    // Source account credentials are covered by our signature, so they may only authorize the call itself.
+    for auth in invoke.auth.iter() {
+        let authorized_call = match &auth.root_invocation.function {
+            SorobanAuthorizedFunction::ContractFn(call) => expected.matches(call),
+            _ => false,
+        };
+        if matches!(auth.credentials, SorobanCredentials::SourceAccount)
+            && (!authorized_call || !auth.root_invocation.sub_invocations.is_empty())
+        {
+            bail!("transaction authorizes more than the expected call");
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stellar::rpc::{build_invoke_transaction, string_arg};
+    use stellar_xdr::curr::{
+        Memo, PaymentOp, SorobanAuthorizationEntry, SorobanAuthorizedInvocation, VecM,
+    };
+
+    fn expected() -> ExpectedCall {
+        ExpectedCall {
+            source: [1; 32],
+            sequence: 42,
+            contract: [2; 32],
+            function: "onboard".into(),
+            args: vec![
+                string_arg("pubkey").unwrap(),
+                string_arg("encrypted").unwrap(),
+            ],
+            max_fee: 1000,
+        }
+    }
+
+    fn requested() -> Transaction {
+        let expected = expected();
+        build_invoke_transaction(
+            expected.source,
+            expected.sequence,
+            expected.contract,
+            &expected.function,
+            expected.args,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn accepts_requested_call() {
+        let mut tx = requested();
+        tx.memo = Memo::Text("new-york".try_into().unwrap());
+        validate_transaction(&tx, &expected()).unwrap();
+    }
+
+    #[test]
+    fn rejects_other_transactions() {
+        let mut tx = requested();
+        tx.source_account = MuxedAccount::Ed25519(Uint256([3; 32]));
+        assert!(validate_transaction(&tx, &expected()).is_err());
+
+        let mut tx = requested();
+        tx.seq_num.0 += 1;
+        assert!(validate_transaction(&tx, &expected()).is_err());
+
+        let mut tx = requested();
+        tx.fee = 1001;
+        assert!(validate_transaction(&tx, &expected()).is_err());
+
+        let mut other = expected();
+        other.function = "register".into();
+        assert!(validate_transaction(&requested(), &other).is_err());
+
+        let mut other = expected();
+        other.args.pop();
+        assert!(validate_transaction(&requested(), &other).is_err());
+
+        let mut other = expected();
+        other.contract = [3; 32];
+        assert!(validate_transaction(&requested(), &other).is_err());
+
+        let mut tx = requested();
+        tx.operations = vec![Operation {
+            source_account: None,
+            body: OperationBody::Payment(PaymentOp {
+                destination: MuxedAccount::Ed25519(Uint256([3; 32])),
+                asset: stellar_xdr::curr::Asset::Native,
+                amount: 1_000_000_000,
+            }),
+        }]
+        .try_into()
+        .unwrap();
+        assert!(validate_transaction(&tx, &expected()).is_err());
+    }
+
+    #[test]
+    fn rejects_unexpected_authorizations() {
+        let mut tx = requested();
+        let mut operations = tx.operations.to_vec();
+        let OperationBody::InvokeHostFunction(invoke) = &mut operations[0].body else {
+            unreachable!()
+        };
+        let HostFunction::InvokeContract(call) = invoke.host_function.clone() else {
+            unreachable!()
+        };
+        let mut other_call = call.clone();
+        other_call.contract_address = ScAddress::Contract(Hash([4; 32]));
+
+        invoke.auth = vec![SorobanAuthorizationEntry {
+            credentials: SorobanCredentials::SourceAccount,
+            root_invocation: SorobanAuthorizedInvocation {
+                function: SorobanAuthorizedFunction::ContractFn(call),
+                sub_invocations: VecM::default(),
+            },
+        }]
+        .try_into()
+        .unwrap();
+        tx.operations = operations.clone().try_into().unwrap();
+        validate_transaction(&tx, &expected()).unwrap();
+
+        let OperationBody::InvokeHostFunction(invoke) = &mut operations[0].body else {
+            unreachable!()
+        };
+        let mut auth = invoke.auth.to_vec();
+        auth[0].root_invocation.function = SorobanAuthorizedFunction::ContractFn(other_call);
+        invoke.auth = auth.try_into().unwrap();
+        tx.operations = operations.try_into().unwrap();
+        assert!(validate_transaction(&tx, &expected()).is_err());
+    }
+}
@@ -56,3 +56,11 @@ async fn dummy_get_quote_verify_test() {
 
     println!("Appdata verified successfully");
 }
+
+#[test]
+fn names_malformed_settings() {
+    std::env::set_var("NEW_YORK_TEST_SETTING", "many");
+    let error = crate::env_or("NEW_YORK_TEST_SETTING", 1usize).unwrap_err();
+    assert!(format!("{:?}", error).contains("NEW_YORK_TEST_SETTING"));
+    assert_eq!(crate::env_or("NEW_YORK_UNSET_SETTING", 1usize).unwrap(), 1);
+}
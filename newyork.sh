@@ -13,6 +13,8 @@ CLUSTER="$1"
 SECRET="$2"
 HOST="$3"
 PUBKEY="${4:-}"  # this is optional
+# Where the other members' hosts reach this one, e.g PUBLIC_IP:8000.
+ENDPOINT="${ENDPOINT:?ENDPOINT must be set to this host's address as reachable by the other members}"
 
 generate_newyork_yml() {
   echo "Generating newyork.yml with CLUSTER=$CLUSTER..."
@@ -58,7 +60,7 @@ build_and_run_rust() {
   popd
 
   echo "Running Rust application..."
-  CLUSTER="$CLUSTER" SECRET="$SECRET" ./target/release/host &
+  CLUSTER="$CLUSTER" SECRET="$SECRET" ENDPOINT="$ENDPOINT" ./target/release/host &
 }
 
 # Upload to flashbox!
@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 use zephyr_sdk::{
-    prelude::*, soroban_sdk::{String as SorobanString, Symbol, TryIntoVal}, utils::soroban_string_to_alloc_string, DatabaseDerive, DatabaseInteract, EnvClient, TransactionResponse
+    prelude::*, soroban_sdk::{xdr::ScVal, Address, BytesN, String as SorobanString, Symbol, TryIntoVal}, utils::soroban_string_to_alloc_string, DatabaseDerive, DatabaseInteract, EnvClient, TransactionResponse
 };
 
 #[derive(DatabaseDerive, Clone, Serialize)]
@@ -8,6 +8,10 @@ use zephyr_sdk::{
 pub struct Pending {
     pub pubkey: String,
     pub quote: String,
+    pub event_log_hash: String,
+    pub event_log_endpoint: String,
+    // The account that posted (and authorized) the registration.
+    pub host: String,
     pub at_time: i64
 }
 
@@ -27,33 +31,59 @@ pub extern "C" fn on_close() {
     env.log().debug(format!("Got timestamp"), None);
     let events = env.reader().pretty().soroban_events();
     for event in events {
-        //if stellar_strkey::Contract(event.contract).to_string() == env!("CLUSTER") {
-            env.log().debug(format!("Got new event {:?}", event.topics.clone()), None);
-            let topic1: Symbol = env.from_scval(&event.topics.to_vec()[0]);
-            let pubkey: SorobanString = env.from_scval(&event.topics.to_vec()[1]); // note: we always have a topic2 for simple-cluster so this is safe
-            
-            if topic1 == Symbol::new(&env.soroban(), "register") {
-                let quote: SorobanString = env.from_scval(&event.data);
-                let new_pending = Pending {
-                    quote: soroban_string_to_alloc_string(&env, quote),
-                    pubkey: soroban_string_to_alloc_string(&env, pubkey),
-                    at_time
-                };
-                new_pending.put(&env);
-            } else if topic1 == Symbol::new(&env.soroban(), "onboard") {
-                let encrypted: SorobanString = env.from_scval(&event.data);
-                let new_onboard = Onboard {
-                    encrypted: soroban_string_to_alloc_string(&env, encrypted),
-                    pubkey: soroban_string_to_alloc_string(&env, pubkey),
-                    at_time
-                };
-
-                new_onboard.put(&env);
+        // NB: the program is built for a single cluster, set through CLUSTER at build time.
+        if stellar_strkey::Contract(event.contract).to_string() != env!("CLUSTER") {
+            continue;
+        }
+        env.log().debug(format!("Got new event {:?}", event.topics.clone()), None);
+        // `from_scval` panics on values of another shape, e.g events from before the contract was upgraded, so
+        // they're checked first and skipped if they don't match.
+        let topics = event.topics.to_vec();
+        let [ScVal::Symbol(_), ScVal::String(_)] = topics.as_slice() else {
+            env.log().debug(format!("Skipping event with topics {:?}", topics), None);
+            continue;
+        };
+        let topic1: Symbol = env.from_scval(&topics[0]);
+        let pubkey: SorobanString = env.from_scval(&topics[1]);
+
+        if topic1 == Symbol::new(&env.soroban(), "register") {
+            if !is_tuple(&event.data, |values| matches!(values, [ScVal::String(_), ScVal::Bytes(hash), ScVal::String(_), ScVal::Address(_)] if hash.len() == 48)) {
+                env.log().debug(format!("Skipping register event with value {:?}", event.data), None);
+                continue;
             }
-        //}
+            let (quote, event_log_hash, event_log_endpoint, host): (SorobanString, BytesN<48>, SorobanString, Address) = env.from_scval(&event.data);
+            let new_pending = Pending {
+                quote: soroban_string_to_alloc_string(&env, quote),
+                event_log_hash: hex::encode(event_log_hash.to_array()),
+                event_log_endpoint: soroban_string_to_alloc_string(&env, event_log_endpoint),
+                host: soroban_string_to_alloc_string(&env, host.to_string()),
+                pubkey: soroban_string_to_alloc_string(&env, pubkey),
+                at_time
+            };
+            new_pending.put(&env);
+        } else if topic1 == Symbol::new(&env.soroban(), "onboard") {
+            if !is_tuple(&event.data, |values| matches!(values, [ScVal::String(_), ScVal::Bytes(signature)] if signature.len() == 64)) {
+                env.log().debug(format!("Skipping onboard event with value {:?}", event.data), None);
+                continue;
+            }
+            // The contract already checked the signature.
+            let (encrypted, _signature): (SorobanString, BytesN<64>) = env.from_scval(&event.data);
+            let new_onboard = Onboard {
+                encrypted: soroban_string_to_alloc_string(&env, encrypted),
+                pubkey: soroban_string_to_alloc_string(&env, pubkey),
+                at_time
+            };
+
+            new_onboard.put(&env);
+        }
     }
 }
 
+/// Whether [`value`] is a tuple whose values match [`shape`].
+fn is_tuple(value: &ScVal, shape: impl Fn(&[ScVal]) -> bool) -> bool {
+    matches!(value, ScVal::Vec(Some(values)) if shape(values.as_slice()))
+}
+
 //
 // TX BUILDERS
 //
@@ -64,6 +94,18 @@ pub struct PostArgs {
     cluster: String,
     quote: String,
     pubkey: String,
+    // hex-encoded, only used when registering.
+    #[serde(default)]
+    event_log_hash: Option<String>,
+    // Only used when registering.
+    #[serde(default)]
+    event_log_endpoint: Option<String>,
+    // hex-encoded, only used when bootstrapping.
+    #[serde(default)]
+    signing_pubkey: Option<String>,
+    // hex-encoded, only used when onboarding.
+    #[serde(default)]
+    signature: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -88,6 +130,23 @@ fn simulate_contract_call(env: &EnvClient, body: &PostArgs, function_name: &str)
     let quote = SorobanString::from_str(&env.soroban(), &body.quote);
     let pubkey = SorobanString::from_str(&env.soroban(), &body.pubkey);
 
+    let args = if let Some(event_log_hash) = &body.event_log_hash {
+        let event_log_hash: [u8; 48] = hex::decode(event_log_hash).unwrap().try_into().unwrap();
+        let event_log_hash = BytesN::from_array(&env.soroban(), &event_log_hash);
+        let event_log_endpoint = SorobanString::from_str(&env.soroban(), body.event_log_endpoint.as_ref().unwrap());
+        // The source registers as the host, the contract has it authorize the call.
+        let host = Address::from_string(&SorobanString::from_str(&env.soroban(), &body.source));
+        (host, pubkey, quote, event_log_hash, event_log_endpoint).try_into_val(env.soroban()).unwrap()
+    } else if let Some(signing_pubkey) = &body.signing_pubkey {
+        let signing_pubkey: [u8; 32] = hex::decode(signing_pubkey).unwrap().try_into().unwrap();
+        let signing_pubkey = BytesN::from_array(&env.soroban(), &signing_pubkey);
+        (pubkey, quote, signing_pubkey).try_into_val(env.soroban()).unwrap()
+    } else {
+        let signature: [u8; 64] = hex::decode(body.signature.as_ref().unwrap()).unwrap().try_into().unwrap();
+        let signature = BytesN::from_array(&env.soroban(), &signature);
+        (pubkey, quote, signature).try_into_val(env.soroban()).unwrap()
+    };
+
     env.simulate_contract_call_to_tx(
         body.source.clone(),
         sequence,
@@ -95,7 +154,7 @@ fn simulate_contract_call(env: &EnvClient, body: &PostArgs, function_name: &str)
             .unwrap()
             .0,
         Symbol::new(&env.soroban(), function_name),
-        (pubkey, quote).try_into_val(env.soroban()).unwrap(),
+        args,
     ).unwrap()
 }
 